@@ -0,0 +1,474 @@
+//! A lossless representation of the Serde data model in JSON and YAML.
+//!
+//! Every value becomes a map with a single entry from its kind to its content,
+//! as documented in the README's "Lossless round trips" section.
+
+use crate::shape::{shape_error, Segment, ShapeError};
+use serde_object::Object;
+use std::{borrow::Cow, convert::TryFrom};
+
+/// Wraps every value in `object` in a map that names its kind.
+#[allow(clippy::too_many_lines)] // One arm per kind.
+pub(crate) fn annotate(object: Object<'static>) -> Object<'static> {
+	let (kind, content) = match object {
+		Object::Bool(value) => ("bool", Object::Bool(value)),
+		Object::I8(value) => ("i8", Object::I8(value)),
+		Object::I16(value) => ("i16", Object::I16(value)),
+		Object::I32(value) => ("i32", Object::I32(value)),
+		Object::I64(value) => ("i64", Object::I64(value)),
+		Object::I128(value) => ("i128", string(value.to_string())),
+		Object::U8(value) => ("u8", Object::U8(value)),
+		Object::U16(value) => ("u16", Object::U16(value)),
+		Object::U32(value) => ("u32", Object::U32(value)),
+		Object::U64(value) => ("u64", Object::U64(value)),
+		Object::U128(value) => ("u128", string(value.to_string())),
+		Object::F32(value) if value.is_finite() => ("f32", Object::F32(value)),
+		Object::F32(value) => ("f32", string(non_finite(value.into()))),
+		Object::F64(value) if value.is_finite() => ("f64", Object::F64(value)),
+		Object::F64(value) => ("f64", string(non_finite(value))),
+		Object::Char(value) => ("char", string(value.to_string())),
+		Object::String(value) => ("string", Object::String(value)),
+		Object::ByteArray(value) => ("bytes", string(hex::encode(value))),
+		Object::Option(None) => ("none", Object::Unit),
+		Object::Option(Some(value)) => ("some", annotate(*value)),
+		Object::Unit => ("unit", Object::Unit),
+		Object::UnitStruct { name } => ("unit_struct", Object::String(name)),
+		Object::UnitVariant { name, variant } => (
+			"unit_variant",
+			record(vec![
+				("name", Object::String(name)),
+				("variant", annotate(*variant)),
+			]),
+		),
+		Object::NewtypeStruct { name, value } => (
+			"newtype_struct",
+			record(vec![
+				("name", Object::String(name)),
+				("value", annotate(*value)),
+			]),
+		),
+		Object::NewtypeVariant {
+			name,
+			variant,
+			value,
+		} => (
+			"newtype_variant",
+			record(vec![
+				("name", Object::String(name)),
+				("variant", annotate(*variant)),
+				("value", annotate(*value)),
+			]),
+		),
+		Object::Seq(elements) => ("seq", annotate_all(elements)),
+		Object::Tuple(elements) => ("tuple", annotate_all(elements)),
+		Object::TupleStruct { name, fields } => (
+			"tuple_struct",
+			record(vec![
+				("name", Object::String(name)),
+				("fields", annotate_all(fields)),
+			]),
+		),
+		Object::TupleVariant {
+			name,
+			variant,
+			fields,
+		} => (
+			"tuple_variant",
+			record(vec![
+				("name", Object::String(name)),
+				("variant", annotate(*variant)),
+				("fields", annotate(*fields)),
+			]),
+		),
+		Object::Map(map) => (
+			"map",
+			Object::Seq(
+				map.into_iter()
+					.map(|(k, v)| Object::Seq(vec![annotate(k), annotate(v)]))
+					.collect(),
+			),
+		),
+		Object::Struct { name, fields } => (
+			"struct",
+			record(vec![
+				("name", Object::String(name)),
+				(
+					"fields",
+					Object::Map(
+						fields
+							.into_iter()
+							.map(|(k, v)| (Object::String(k), v.map_or(Object::Unit, annotate)))
+							.collect(),
+					),
+				),
+			]),
+		),
+		Object::StructVariant {
+			name,
+			variant,
+			fields,
+		} => (
+			"struct_variant",
+			record(vec![
+				("name", Object::String(name)),
+				("variant", annotate(*variant)),
+				("fields", annotate(*fields)),
+			]),
+		),
+		Object::FieldMap(map) => (
+			"field_map",
+			Object::Seq(
+				map.into_iter()
+					.map(|(k, v)| Object::Seq(vec![annotate(k), v.map_or(Object::Unit, annotate)]))
+					.collect(),
+			),
+		),
+		Object::DualVariantKey { index, name } => (
+			"dual_variant_key",
+			record(vec![
+				("index", Object::U32(index)),
+				("name", Object::String(name)),
+			]),
+		),
+	};
+	Object::Map(vec![(Object::String(Cow::Borrowed(kind)), content)])
+}
+
+fn string(string: String) -> Object<'static> {
+	Object::String(Cow::Owned(string))
+}
+
+fn non_finite(value: f64) -> String {
+	if value.is_nan() {
+		"NaN".to_string()
+	} else if value > 0.0 {
+		"inf".to_string()
+	} else {
+		"-inf".to_string()
+	}
+}
+
+fn record(fields: Vec<(&'static str, Object<'static>)>) -> Object<'static> {
+	Object::Map(
+		fields
+			.into_iter()
+			.map(|(k, v)| (Object::String(Cow::Borrowed(k)), v))
+			.collect(),
+	)
+}
+
+fn annotate_all(elements: Vec<Object<'static>>) -> Object<'static> {
+	Object::Seq(elements.into_iter().map(annotate).collect())
+}
+
+/// Reconstructs the document `object` was [`annotate`]d from.
+pub(crate) fn deannotate(object: Object<'static>) -> Result<Object<'static>, ShapeError> {
+	value(object, &mut vec![])
+}
+
+#[allow(clippy::too_many_lines)] // One arm per kind.
+fn value(object: Object<'static>, path: &mut Vec<Segment>) -> Result<Object<'static>, ShapeError> {
+	let (kind, content) = match object {
+		Object::Map(map) if map.len() == 1 => {
+			let (kind, content) = map.into_iter().next().expect("one entry");
+			match kind {
+				Object::String(kind) => (kind, content),
+				_ => return Err(shape_error(path, "Expected an annotated value")),
+			}
+		}
+		_ => {
+			return Err(shape_error(
+				path,
+				"Expected an annotated value, like `{\"string\": \"text\"}`",
+			))
+		}
+	};
+
+	path.push(Segment::Key(kind.to_string()));
+	let result = match kind.as_ref() {
+		"bool" => match content {
+			Object::Bool(value) => Ok(Object::Bool(value)),
+			_ => Err(shape_error(path, "Expected a boolean")),
+		},
+		"i8" => integer(&content, path).map(Object::I8),
+		"i16" => integer(&content, path).map(Object::I16),
+		"i32" => integer(&content, path).map(Object::I32),
+		"i64" => integer(&content, path).map(Object::I64),
+		"i128" => integer(&content, path).map(Object::I128),
+		"u8" => integer(&content, path).map(Object::U8),
+		"u16" => integer(&content, path).map(Object::U16),
+		"u32" => integer(&content, path).map(Object::U32),
+		"u64" => integer(&content, path).map(Object::U64),
+		"u128" => integer(&content, path).map(Object::U128),
+		#[allow(clippy::cast_possible_truncation)]
+		"f32" => float(&content, path).map(|value| Object::F32(value as f32)),
+		"f64" => float(&content, path).map(Object::F64),
+		"char" => {
+			let text = text(content, path)?;
+			let mut chars = text.chars();
+			match (chars.next(), chars.next()) {
+				(Some(char), None) => Ok(Object::Char(char)),
+				_ => Err(shape_error(path, "Expected a single character")),
+			}
+		}
+		"string" => text(content, path).map(Object::String),
+		"bytes" => hex::decode(text(content, path)?.as_ref())
+			.map(|bytes| Object::ByteArray(Cow::Owned(bytes)))
+			.map_err(|error| shape_error(path, format!("Invalid hex: {}", error))),
+		"none" => Ok(Object::Option(None)),
+		"some" => value(content, path).map(|value| Object::Option(Some(Box::new(value)))),
+		"unit" => Ok(Object::Unit),
+		"unit_struct" => text(content, path).map(|name| Object::UnitStruct { name }),
+		"unit_variant" => fields(content, &["name", "variant"], path).and_then(|fields| {
+			let [name, variant] = <[_; 2]>::try_from(fields).expect("two fields");
+			Ok(Object::UnitVariant {
+				name: text(name, path)?,
+				variant: Box::new(value_at("variant", variant, path)?),
+			})
+		}),
+		"newtype_struct" => fields(content, &["name", "value"], path).and_then(|fields| {
+			let [name, inner] = <[_; 2]>::try_from(fields).expect("two fields");
+			Ok(Object::NewtypeStruct {
+				name: text(name, path)?,
+				value: Box::new(value_at("value", inner, path)?),
+			})
+		}),
+		"newtype_variant" => {
+			fields(content, &["name", "variant", "value"], path).and_then(|fields| {
+				let [name, variant, inner] = <[_; 3]>::try_from(fields).expect("three fields");
+				Ok(Object::NewtypeVariant {
+					name: text(name, path)?,
+					variant: Box::new(value_at("variant", variant, path)?),
+					value: Box::new(value_at("value", inner, path)?),
+				})
+			})
+		}
+		"seq" => values(content, path).map(Object::Seq),
+		"tuple" => values(content, path).map(Object::Tuple),
+		"tuple_struct" => fields(content, &["name", "fields"], path).and_then(|fields| {
+			let [name, elements] = <[_; 2]>::try_from(fields).expect("two fields");
+			path.push(Segment::Key("fields".to_string()));
+			let elements = values(elements, path)?;
+			path.pop();
+			Ok(Object::TupleStruct {
+				name: text(name, path)?,
+				fields: elements,
+			})
+		}),
+		"tuple_variant" | "struct_variant" => fields(content, &["name", "variant", "fields"], path)
+			.and_then(|fields| {
+				let [name, variant, inner] = <[_; 3]>::try_from(fields).expect("three fields");
+				let name = text(name, path)?;
+				let variant = Box::new(value_at("variant", variant, path)?);
+				let fields = Box::new(value_at("fields", inner, path)?);
+				Ok(if kind == "tuple_variant" {
+					Object::TupleVariant {
+						name,
+						variant,
+						fields,
+					}
+				} else {
+					Object::StructVariant {
+						name,
+						variant,
+						fields,
+					}
+				})
+			}),
+		"map" => pairs(content, path).and_then(|pairs| {
+			pairs
+				.into_iter()
+				.map(|(k, v)| match v {
+					Some(v) => Ok((k, v)),
+					None => Err(shape_error(path, "Map values can't be absent")),
+				})
+				.collect::<Result<_, _>>()
+				.map(Object::Map)
+		}),
+		"field_map" => pairs(content, path).map(Object::FieldMap),
+		"struct" => fields(content, &["name", "fields"], path).and_then(|fields| {
+			let [name, map] = <[_; 2]>::try_from(fields).expect("two fields");
+			let name = text(name, path)?;
+			path.push(Segment::Key("fields".to_string()));
+			let map = match map {
+				Object::Map(map) => map,
+				_ => return Err(shape_error(path, "Expected a map of fields")),
+			};
+			let mut fields = vec![];
+			for (k, v) in map {
+				let k = text(k, path)?;
+				let v = match v {
+					Object::Unit => None,
+					v => Some(value_at(&k, v, path)?),
+				};
+				fields.push((k, v));
+			}
+			path.pop();
+			Ok(Object::Struct { name, fields })
+		}),
+		"dual_variant_key" => fields(content, &["index", "name"], path).and_then(|fields| {
+			let [index, name] = <[_; 2]>::try_from(fields).expect("two fields");
+			Ok(Object::DualVariantKey {
+				index: integer(&index, path)?,
+				name: text(name, path)?,
+			})
+		}),
+		kind => Err(shape_error(path, format!("Unknown kind `{}`", kind))),
+	};
+	path.pop();
+	result
+}
+
+fn value_at(
+	key: &str,
+	object: Object<'static>,
+	path: &mut Vec<Segment>,
+) -> Result<Object<'static>, ShapeError> {
+	path.push(Segment::Key(key.to_string()));
+	let value = value(object, path)?;
+	path.pop();
+	Ok(value)
+}
+
+fn text(object: Object<'static>, path: &[Segment]) -> Result<Cow<'static, str>, ShapeError> {
+	match object {
+		Object::String(text) => Ok(text),
+		_ => Err(shape_error(path, "Expected a string")),
+	}
+}
+
+fn integer<T: TryFrom<i128> + TryFrom<u128>>(
+	object: &Object,
+	path: &[Segment],
+) -> Result<T, ShapeError> {
+	let out_of_range = || shape_error(path, "Integer out of range");
+	match *object {
+		Object::I8(value) => T::try_from(i128::from(value)).map_err(|_| out_of_range()),
+		Object::I16(value) => T::try_from(i128::from(value)).map_err(|_| out_of_range()),
+		Object::I32(value) => T::try_from(i128::from(value)).map_err(|_| out_of_range()),
+		Object::I64(value) => T::try_from(i128::from(value)).map_err(|_| out_of_range()),
+		Object::I128(value) => T::try_from(value).map_err(|_| out_of_range()),
+		Object::U8(value) => T::try_from(u128::from(value)).map_err(|_| out_of_range()),
+		Object::U16(value) => T::try_from(u128::from(value)).map_err(|_| out_of_range()),
+		Object::U32(value) => T::try_from(u128::from(value)).map_err(|_| out_of_range()),
+		Object::U64(value) => T::try_from(u128::from(value)).map_err(|_| out_of_range()),
+		Object::U128(value) => T::try_from(value).map_err(|_| out_of_range()),
+		Object::String(ref text) => {
+			if let Ok(value) = text.parse::<u128>() {
+				T::try_from(value).map_err(|_| out_of_range())
+			} else if let Ok(value) = text.parse::<i128>() {
+				T::try_from(value).map_err(|_| out_of_range())
+			} else {
+				Err(shape_error(path, "Expected an integer"))
+			}
+		}
+		_ => Err(shape_error(path, "Expected an integer")),
+	}
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn float(object: &Object, path: &[Segment]) -> Result<f64, ShapeError> {
+	Ok(match *object {
+		Object::F32(value) => value.into(),
+		Object::F64(value) => value,
+		Object::I64(value) => value as f64,
+		Object::U64(value) => value as f64,
+		Object::String(ref text) => match text.as_ref() {
+			"NaN" => f64::NAN,
+			"inf" => f64::INFINITY,
+			"-inf" => f64::NEG_INFINITY,
+			_ => {
+				return Err(shape_error(
+					path,
+					"Expected a number, `NaN`, `inf` or `-inf`",
+				))
+			}
+		},
+		_ => return Err(shape_error(path, "Expected a number")),
+	})
+}
+
+/// Takes exactly the `names` entries from a record, in that order.
+fn fields(
+	object: Object<'static>,
+	names: &[&str],
+	path: &[Segment],
+) -> Result<Vec<Object<'static>>, ShapeError> {
+	let expected = || {
+		shape_error(
+			path,
+			format!("Expected a map with exactly the keys {}", names.join(", ")),
+		)
+	};
+	let map = match object {
+		Object::Map(map) if map.len() == names.len() => map,
+		_ => return Err(expected()),
+	};
+	let mut fields: Vec<_> = names.iter().map(|_| None).collect();
+	for (k, v) in map {
+		let index = match &k {
+			Object::String(k) => names.iter().position(|name| name == k),
+			_ => None,
+		}
+		.ok_or_else(expected)?;
+		fields[index] = Some(v);
+	}
+	fields
+		.into_iter()
+		.map(|field| field.ok_or_else(expected))
+		.collect()
+}
+
+fn values(
+	object: Object<'static>,
+	path: &mut Vec<Segment>,
+) -> Result<Vec<Object<'static>>, ShapeError> {
+	match object {
+		Object::Seq(elements) => elements
+			.into_iter()
+			.enumerate()
+			.map(|(index, element)| {
+				path.push(Segment::Index(index));
+				let element = value(element, path)?;
+				path.pop();
+				Ok(element)
+			})
+			.collect(),
+		_ => Err(shape_error(path, "Expected a list")),
+	}
+}
+
+/// Pairs of a key and a value that may be absent (`null`).
+#[allow(clippy::type_complexity)]
+fn pairs(
+	object: Object<'static>,
+	path: &mut Vec<Segment>,
+) -> Result<Vec<(Object<'static>, Option<Object<'static>>)>, ShapeError> {
+	let entries = match object {
+		Object::Seq(entries) => entries,
+		_ => return Err(shape_error(path, "Expected a list of [key, value] pairs")),
+	};
+	let mut pairs = vec![];
+	for (index, entry) in entries.into_iter().enumerate() {
+		path.push(Segment::Index(index));
+		let (k, v) = match entry {
+			Object::Seq(entry) if entry.len() == 2 => {
+				let mut entry = entry.into_iter();
+				(entry.next().expect("key"), entry.next().expect("value"))
+			}
+			_ => return Err(shape_error(path, "Expected a [key, value] pair")),
+		};
+		path.push(Segment::Index(0));
+		let k = value(k, path)?;
+		path.pop();
+		path.push(Segment::Index(1));
+		let v = match v {
+			Object::Unit => None,
+			v => Some(value(v, path)?),
+		};
+		path.pop();
+		pairs.push((k, v));
+		path.pop();
+	}
+	Ok(pairs)
+}
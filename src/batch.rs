@@ -0,0 +1,241 @@
+//! Converting whole directory trees.
+
+use crate::{transcode, Error, In, Options, Out};
+use std::{
+	collections::HashMap,
+	fs::{self, File},
+	io, panic,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, AtomicUsize, Ordering},
+		mpsc::{self, Sender},
+		Arc,
+	},
+	thread,
+};
+
+/// The outcome of converting one file with [`convert_tree`].
+#[derive(Debug)]
+pub struct Conversion {
+	/// The input file.
+	pub source: PathBuf,
+
+	/// The output file, whether or not it was written.
+	pub target: PathBuf,
+
+	/// The format the input file was read as, or [`None`] if it was skipped for its unknown or missing extension.
+	pub in_format: Option<In>,
+
+	/// Whether converting the file succeeded.
+	pub result: Result<(), Error>,
+}
+
+/// Converts each file in `src_dir` and its subdirectories to `out_format`,
+/// mirroring the tree in `out_dir` with `out_format` as extension.
+///
+/// Files are read as `in_format` or else as their extension says.
+/// Files with other extensions are skipped, but listed with an [`Error::Options`] so that they count as failed.
+/// Hidden files and directories are skipped silently, as is `out_dir` if it's inside `src_dir`.
+/// Each output file is only written once its conversion succeeded.
+///
+/// Returns the conversions in order of source path, regardless of `jobs`.
+/// Unless `fail_fast`, a failure doesn't stop the files after it from being converted.
+/// Otherwise, no further conversions are started, though with several `jobs`, those already running finish.
+///
+/// Up to `jobs` files are converted in parallel, each on its own thread.
+///
+/// # Errors
+///
+/// Iff `jobs` is 0 or `src_dir` or one of its subdirectories can't be listed.
+pub fn convert_tree(
+	src_dir: &Path,
+	out_dir: &Path,
+	in_format: Option<In>,
+	out_format: Out,
+	fail_fast: bool,
+	jobs: usize,
+	options: &Options,
+) -> Result<Vec<Conversion>, Error> {
+	if jobs == 0 {
+		return Err(Error::Options(
+			"Can't convert files with 0 jobs.".to_string(),
+		));
+	}
+
+	let mut files = vec![];
+	// Missing unless it's already there, in which case it must not be converted again.
+	let out_dir_canonical = fs::canonicalize(out_dir).ok();
+	list_files(
+		src_dir,
+		Path::new(""),
+		out_dir_canonical.as_deref(),
+		&mut files,
+	)
+	.map_err(Error::Input)?;
+	files.sort();
+
+	let mut targets: HashMap<PathBuf, PathBuf> = HashMap::new();
+	let mut tasks = vec![];
+	for file in files {
+		let in_format = in_format.or_else(|| In::from_extension(&file));
+		let source = src_dir.join(&file);
+		let target = out_dir.join(file).with_extension(out_format.to_string());
+		let converted_already = targets.get(&target).cloned();
+		if in_format.is_some() {
+			targets
+				.entry(target.clone())
+				.or_insert_with(|| source.clone());
+		}
+		tasks.push(Task {
+			source,
+			target,
+			in_format,
+			converted_already,
+		});
+	}
+
+	let pool = Arc::new(Pool {
+		tasks,
+		out_format,
+		fail_fast,
+		options: options.clone(),
+		next: AtomicUsize::new(0),
+		failed: AtomicBool::new(false),
+	});
+	let (sender, receiver) = mpsc::channel();
+	let workers: Vec<_> = (1..jobs.min(pool.tasks.len()))
+		.map(|_| {
+			let pool = Arc::clone(&pool);
+			let sender = sender.clone();
+			thread::spawn(move || pool.work(&sender))
+		})
+		.collect();
+	pool.work(&sender);
+	drop(sender);
+
+	let mut results: Vec<_> = pool.tasks.iter().map(|_| None).collect();
+	for (index, result) in receiver {
+		results[index] = Some(result);
+	}
+	for worker in workers {
+		if let Err(panic) = worker.join() {
+			panic::resume_unwind(panic);
+		}
+	}
+
+	Ok(pool
+		.tasks
+		.iter()
+		.zip(results)
+		.filter_map(|(task, result)| {
+			Some(Conversion {
+				source: task.source.clone(),
+				target: task.target.clone(),
+				in_format: task.in_format,
+				result: result?,
+			})
+		})
+		.collect())
+}
+
+struct Task {
+	source: PathBuf,
+	target: PathBuf,
+	/// [`None`] if the extension is unknown.
+	in_format: Option<In>,
+	/// The earlier source with the same target, if any.
+	converted_already: Option<PathBuf>,
+}
+
+/// Shared by the threads of [`convert_tree`], which each take the next task until none are left.
+struct Pool {
+	tasks: Vec<Task>,
+	out_format: Out,
+	fail_fast: bool,
+	options: Options,
+	next: AtomicUsize,
+	failed: AtomicBool,
+}
+
+impl Pool {
+	fn work(&self, results: &Sender<(usize, Result<(), Error>)>) {
+		while !(self.fail_fast && self.failed.load(Ordering::SeqCst)) {
+			let index = self.next.fetch_add(1, Ordering::SeqCst);
+			let task = match self.tasks.get(index) {
+				Some(task) => task,
+				None => return,
+			};
+			let result = match (task.in_format, &task.converted_already) {
+				(None, _) => Err(unknown_extension(&task.source)),
+				(Some(_), Some(other)) => Err(Error::Options(format!(
+					"{} is converted from {} already.",
+					task.target.display(),
+					other.display()
+				))),
+				(Some(in_format), None) => convert(
+					&task.source,
+					in_format,
+					&task.target,
+					self.out_format,
+					&self.options,
+				),
+			};
+			if result.is_err() {
+				self.failed.store(true, Ordering::SeqCst);
+			}
+			// The receiver outlives all workers.
+			results.send((index, result)).ok();
+		}
+	}
+}
+
+fn unknown_extension(source: &Path) -> Error {
+	Error::Options(match source.extension() {
+		Some(extension) => format!(
+			"Unknown input file extension `.{}`. Specify the format with --in.",
+			extension.to_string_lossy()
+		),
+		None => "The file has no extension to choose the input format by. Specify it with --in."
+			.to_string(),
+	})
+}
+
+/// Lists the files in `dir` as paths relative to `root`, prefixed with `relative`.
+fn list_files(
+	root: &Path,
+	relative: &Path,
+	skip: Option<&Path>,
+	files: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+	for entry in fs::read_dir(root.join(relative))? {
+		let entry = entry?;
+		if entry.file_name().to_string_lossy().starts_with('.') {
+			continue;
+		}
+		let path = relative.join(entry.file_name());
+		if entry.file_type()?.is_dir() {
+			if skip != Some(fs::canonicalize(entry.path())?.as_path()) {
+				list_files(root, &path, skip, files)?;
+			}
+		} else {
+			files.push(path);
+		}
+	}
+	Ok(())
+}
+
+fn convert(
+	source: &Path,
+	in_format: In,
+	target: &Path,
+	out_format: Out,
+	options: &Options,
+) -> Result<(), Error> {
+	let reader = File::open(source).map_err(Error::Input)?;
+	let mut output = vec![];
+	transcode(reader, in_format, &mut output, out_format, options)?;
+	if let Some(parent) = target.parent() {
+		fs::create_dir_all(parent).map_err(Error::Output)?;
+	}
+	fs::write(target, output).map_err(Error::Output)
+}
@@ -0,0 +1,541 @@
+//! Structural comparison of documents.
+//!
+//! Documents are compared the way most formats see them:
+//! Options and newtype structs are transparent, integers of different widths are equal if their values are,
+//! structs are maps and `char`s are strings.
+//! The order of map entries does matter, unless [`Transform::SortKeys`](`crate::Transform::SortKeys`) is applied first.
+
+use crate::{
+	shape::{render_path, render_pointer, shape_error, Segment, ShapeError},
+	transform::duplicate,
+};
+use serde::{ser::SerializeMap as _, Serialize, Serializer};
+use serde_object::Object;
+use std::{
+	borrow::Cow,
+	cmp::Ordering,
+	convert::{TryFrom, TryInto},
+	fmt::{self, Display, Formatter},
+};
+
+/// A difference between two documents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+	path: String,
+	description: String,
+}
+
+impl Difference {
+	/// Where in the documents the difference is, like `a.b[1]`. Empty for the top level.
+	#[must_use]
+	pub fn path(&self) -> &str {
+		&self.path
+	}
+
+	/// What changed, like `bytes became a sequence`.
+	#[must_use]
+	pub fn description(&self) -> &str {
+		&self.description
+	}
+}
+
+impl Display for Difference {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		if self.path.is_empty() {
+			write!(f, "{}", self.description)
+		} else {
+			write!(f, "{} (at `{}`)", self.description, self.path)
+		}
+	}
+}
+
+/// How `b` differs from `a` at one place, borrowing any new value from `b`.
+pub(crate) struct Change<'a> {
+	path: Vec<Segment>,
+	kind: ChangeKind<'a>,
+}
+
+enum ChangeKind<'a> {
+	/// Only in `b`.
+	Added(&'a Object<'a>),
+
+	/// Only in `a`.
+	Removed,
+
+	/// Different in `b`, as described.
+	Replaced(String, &'a Object<'a>),
+
+	/// A difference JSON Patch can't express, like the order of entries or the kind of a key.
+	Note(String),
+}
+
+impl Change<'_> {
+	pub(crate) fn difference(&self) -> Difference {
+		Difference {
+			path: render_path(&self.path),
+			description: match &self.kind {
+				ChangeKind::Added(value) => format!("added {}", preview(value)),
+				ChangeKind::Removed => "removed".to_string(),
+				ChangeKind::Replaced(description, _) | ChangeKind::Note(description) => {
+					description.clone()
+				}
+			},
+		}
+	}
+}
+
+/// Short values as JSON, longer ones only by kind.
+pub(crate) fn preview(value: &Object) -> String {
+	match serde_json::to_string(value) {
+		Ok(json) if json.chars().count() <= 40 => json,
+		_ => View::of(value).kind().to_string(),
+	}
+}
+
+/// Lists how `b` differs from `a`.
+pub(crate) fn compare<'a>(a: &Object, b: &'a Object<'a>) -> Vec<Change<'a>> {
+	let mut changes = vec![];
+	compare_at(a, b, &mut vec![], &mut changes);
+	changes
+}
+
+/// One RFC 6902 JSON Patch operation.
+pub(crate) struct Operation<'a> {
+	op: &'static str,
+	path: String,
+	value: Option<&'a Object<'a>>,
+}
+
+impl Serialize for Operation<'_> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let mut map = serializer.serialize_map(Some(2 + self.value.iter().count()))?;
+		map.serialize_entry("op", self.op)?;
+		map.serialize_entry("path", &self.path)?;
+		if let Some(value) = self.value {
+			map.serialize_entry("value", value)?;
+		}
+		map.end()
+	}
+}
+
+impl Operation<'_> {
+	/// The operation as a document, for formats other than JSON.
+	pub(crate) fn to_object(&self) -> Object<'static> {
+		let string = |string: &str| Object::String(Cow::Owned(string.to_string()));
+		let mut entries = vec![
+			(string("op"), string(self.op)),
+			(string("path"), string(&self.path)),
+		];
+		if let Some(value) = self.value {
+			entries.push((string("value"), duplicate(value)));
+		}
+		Object::Map(entries)
+	}
+}
+
+/// Turns `changes` into a JSON Patch that turns `a` into `b`, skipping what it can't express.
+pub(crate) fn json_patch<'a>(changes: &[Change<'a>]) -> Vec<Operation<'a>> {
+	changes
+		.iter()
+		.filter_map(|change| {
+			let (op, value) = match change.kind {
+				ChangeKind::Added(value) => ("add", Some(value)),
+				ChangeKind::Removed => ("remove", None),
+				ChangeKind::Replaced(_, value) => ("replace", Some(value)),
+				ChangeKind::Note(_) => return None,
+			};
+			Some(Operation {
+				op,
+				path: render_pointer(&change.path),
+				value,
+			})
+		})
+		.collect()
+}
+
+/// Creates an RFC 7386 JSON Merge Patch that turns `a` into `b`, or `None` if there's nothing to change.
+///
+/// Fails where `b` has null values in maps, which Merge Patches can't express.
+pub(crate) fn merge_patch(
+	a: &Object,
+	b: &Object,
+	path: &mut Vec<Segment>,
+) -> Result<Option<Object<'static>>, ShapeError> {
+	let (view_a, view_b) = (View::of(a), View::of(b));
+	if let (View::Map(_) | View::Fields(_), View::Map(_) | View::Fields(_)) = (&view_a, &view_b) {
+		let (x, y) = (entries(&view_a), entries(&view_b));
+		let key = |key: &String| Object::String(Cow::Owned(key.clone()));
+		let mut changed = vec![];
+		for (k, _, value_x) in &x {
+			path.push(Segment::Key(k.clone()));
+			let entry = match y.iter().find(|(key, ..)| key == k) {
+				Some((_, _, value_y)) => merge_patch(value_x, value_y, path)?,
+				// Null removes the entry.
+				None => Some(Object::Unit),
+			};
+			path.pop();
+			changed.extend(entry.map(|entry| (key(k), entry)));
+		}
+		for (k, _, value_y) in &y {
+			if !x.iter().any(|(key, ..)| key == k) {
+				path.push(Segment::Key(k.clone()));
+				check_merge_patch_value(value_y, path)?;
+				path.pop();
+				changed.push((key(k), duplicate(value_y)));
+			}
+		}
+		return Ok(if changed.is_empty() {
+			None
+		} else {
+			Some(Object::Map(changed))
+		});
+	}
+
+	if compare(a, b).is_empty() {
+		return Ok(None);
+	}
+	check_merge_patch_value(b, path)?;
+	Ok(Some(duplicate(b)))
+}
+
+/// Fails if `value` is or contains null in a map, below the top level.
+fn check_merge_patch_value(value: &Object, path: &mut Vec<Segment>) -> Result<(), ShapeError> {
+	let view = View::of(value);
+	match &view {
+		View::Null if !path.is_empty() => {
+			Err(shape_error(path, "Merge Patches can't set values to null"))
+		}
+		View::Map(_) | View::Fields(_) => {
+			for (key, _, value) in entries(&view) {
+				path.push(Segment::Key(key));
+				check_merge_patch_value(value, path)?;
+				path.pop();
+			}
+			Ok(())
+		}
+		_ => Ok(()),
+	}
+}
+
+/// Names the kind of `object`, like `a map`.
+pub(crate) fn kind(object: &Object) -> &'static str {
+	View::of(object).kind()
+}
+
+/// Orders map keys for [`Transform::SortKeys`](`crate::Transform::SortKeys`).
+pub(crate) fn key_cmp(a: &Object, b: &Object) -> Ordering {
+	match (View::of(a), View::of(b)) {
+		(View::Integer(a), View::Integer(b)) => a.cmp(&b),
+		(View::Float(a), View::Float(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+		(a, b) => a.key().cmp(&b.key()),
+	}
+}
+
+/// A value as most formats see it.
+pub(crate) enum View<'a> {
+	Null,
+	Bool(bool),
+	Integer(Integer),
+	Float(f64),
+	Text(Cow<'a, str>),
+	Bytes(&'a [u8]),
+	Seq(&'a [Object<'a>]),
+	Map(Vec<(&'a Object<'a>, &'a Object<'a>)>),
+	Fields(Vec<(&'a str, &'a Object<'a>)>),
+	Variant(Cow<'a, str>, Option<&'a Object<'a>>),
+}
+
+/// Unsigned only above `i128::MAX`, so that the derived order is numeric.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Integer {
+	Signed(i128),
+	Unsigned(u128),
+}
+
+impl Display for Integer {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Integer::Signed(value) => value.fmt(f),
+			Integer::Unsigned(value) => value.fmt(f),
+		}
+	}
+}
+
+impl<'a> View<'a> {
+	pub(crate) fn of(object: &'a Object<'a>) -> Self {
+		match object {
+			Object::Option(None) | Object::Unit | Object::UnitStruct { .. } => View::Null,
+			Object::Option(Some(value)) | Object::NewtypeStruct { value, .. } => View::of(value),
+			Object::Bool(value) => View::Bool(*value),
+			Object::I8(value) => View::Integer(Integer::Signed((*value).into())),
+			Object::I16(value) => View::Integer(Integer::Signed((*value).into())),
+			Object::I32(value) => View::Integer(Integer::Signed((*value).into())),
+			Object::I64(value) => View::Integer(Integer::Signed((*value).into())),
+			Object::I128(value) => View::Integer(Integer::Signed(*value)),
+			Object::U8(value) => View::Integer(Integer::Signed((*value).into())),
+			Object::U16(value) => View::Integer(Integer::Signed((*value).into())),
+			Object::U32(value) => View::Integer(Integer::Signed((*value).into())),
+			Object::U64(value) => View::Integer(Integer::Signed((*value).into())),
+			Object::U128(value) => View::Integer(
+				u128::try_into(*value).map_or(Integer::Unsigned(*value), Integer::Signed),
+			),
+			Object::F32(value) => View::Float((*value).into()),
+			Object::F64(value) => View::Float(*value),
+			Object::Char(value) => View::Text(Cow::Owned(value.to_string())),
+			Object::String(value) => View::Text(Cow::Borrowed(value)),
+			Object::DualVariantKey { name, .. } => View::Text(Cow::Borrowed(name)),
+			Object::ByteArray(value) => View::Bytes(value),
+			Object::Seq(elements)
+			| Object::Tuple(elements)
+			| Object::TupleStruct {
+				fields: elements, ..
+			} => View::Seq(elements),
+			Object::Map(map) => View::Map(map.iter().map(|(k, v)| (k, v)).collect()),
+			Object::FieldMap(map) => View::Map(
+				map.iter()
+					.filter_map(|(k, v)| Some((k, v.as_ref()?)))
+					.collect(),
+			),
+			Object::Struct { fields, .. } => View::Fields(
+				fields
+					.iter()
+					.filter_map(|(k, v)| Some((k.as_ref(), v.as_ref()?)))
+					.collect(),
+			),
+			Object::UnitVariant { variant, .. } => View::Variant(variant_name(variant), None),
+			Object::NewtypeVariant {
+				variant,
+				value: fields,
+				..
+			}
+			| Object::TupleVariant {
+				variant, fields, ..
+			}
+			| Object::StructVariant {
+				variant, fields, ..
+			} => View::Variant(variant_name(variant), Some(fields)),
+		}
+	}
+
+	pub(crate) fn kind(&self) -> &'static str {
+		match self {
+			View::Null => "null",
+			View::Bool(_) => "a boolean",
+			View::Integer(_) => "an integer",
+			View::Float(_) => "a float",
+			View::Text(_) => "a string",
+			View::Bytes(_) => "bytes",
+			View::Seq(_) => "a sequence",
+			View::Map(_) | View::Fields(_) => "a map",
+			View::Variant(..) => "an enum variant",
+		}
+	}
+
+	/// Identifies map keys across documents.
+	pub(crate) fn key(&self) -> String {
+		match self {
+			View::Null => "null".to_string(),
+			View::Bool(value) => value.to_string(),
+			View::Integer(value) => value.to_string(),
+			View::Float(value) => value.to_string(),
+			View::Text(value) | View::Variant(value, None) => value.to_string(),
+			View::Bytes(value) => bytes_key(value),
+			// Formats without byte strings write them as sequences of integers.
+			View::Seq(elements) => match elements
+				.iter()
+				.map(|element| match View::of(element) {
+					View::Integer(Integer::Signed(value)) => u8::try_from(value).ok(),
+					_ => None,
+				})
+				.collect::<Option<Vec<_>>>()
+			{
+				Some(bytes) => bytes_key(&bytes),
+				None => format!(
+					"[{}]",
+					elements
+						.iter()
+						.map(|element| View::of(element).key())
+						.collect::<Vec<_>>()
+						.join(", ")
+				),
+			},
+			View::Map(_) | View::Fields(_) | View::Variant(_, Some(_)) => {
+				format!("{:?}", self.kind())
+			}
+		}
+	}
+}
+
+fn bytes_key(bytes: &[u8]) -> String {
+	std::str::from_utf8(bytes).map_or_else(|_| hex::encode(bytes), str::to_string)
+}
+
+fn variant_name<'a>(variant: &'a Object<'a>) -> Cow<'a, str> {
+	match View::of(variant) {
+		View::Text(name) => name,
+		view => Cow::Owned(view.key()),
+	}
+}
+
+/// Entries of a map or struct, with their identifying key.
+fn entries<'a>(view: &View<'a>) -> Vec<(String, &'static str, &'a Object<'a>)> {
+	match view {
+		View::Map(map) => map
+			.iter()
+			.map(|(k, v)| {
+				let k = View::of(k);
+				(k.key(), k.kind(), *v)
+			})
+			.collect(),
+		View::Fields(fields) => fields
+			.iter()
+			.map(|(k, v)| ((*k).to_string(), "a string", *v))
+			.collect(),
+		_ => vec![],
+	}
+}
+
+fn compare_at<'a>(
+	a: &Object,
+	b: &'a Object<'a>,
+	path: &mut Vec<Segment>,
+	changes: &mut Vec<Change<'a>>,
+) {
+	let (view_a, view_b) = (View::of(a), View::of(b));
+	let description = match (&view_a, &view_b) {
+		(View::Null, View::Null) => return,
+		(View::Bool(x), View::Bool(y)) if x == y => return,
+		(View::Integer(x), View::Integer(y)) if x == y => return,
+		// Bitwise, so that signed zeros are told apart.
+		(View::Float(x), View::Float(y))
+			if x.to_bits() == y.to_bits() || x.is_nan() && y.is_nan() =>
+		{
+			return
+		}
+		(View::Text(x), View::Text(y)) if x == y => return,
+		(View::Bytes(x), View::Bytes(y)) if x == y => return,
+		(View::Bool(x), View::Bool(y)) => format!("changed from {} to {}", x, y),
+		(View::Integer(x), View::Integer(y)) => format!("changed from {} to {}", x, y),
+		(View::Float(x), View::Float(y)) => format!("changed from {:?} to {:?}", x, y),
+		(View::Text(x), View::Text(y)) => format!("changed from {:?} to {:?}", x, y),
+		(View::Bytes(x), View::Bytes(y)) => {
+			format!("changed from {} to {} bytes", x.len(), y.len())
+		}
+		(View::Seq(x), View::Seq(y)) => {
+			for (index, (x, y)) in x.iter().zip(y.iter()).enumerate() {
+				path.push(Segment::Index(index));
+				compare_at(x, y, path, changes);
+				path.pop();
+			}
+			// Last first, so that patches can remove them in order.
+			for index in (y.len()..x.len()).rev() {
+				push(path, Segment::Index(index), ChangeKind::Removed, changes);
+			}
+			for (index, y) in y.iter().enumerate().skip(x.len()) {
+				push(path, Segment::Index(index), ChangeKind::Added(y), changes);
+			}
+			return;
+		}
+		(View::Map(_) | View::Fields(_), View::Map(_) | View::Fields(_)) => {
+			return compare_entries(&entries(&view_a), &entries(&view_b), path, changes);
+		}
+		(View::Variant(x, payload_x), View::Variant(y, payload_y)) if x == y => {
+			match (payload_x, payload_y) {
+				(None, None) => return,
+				(Some(payload_x), Some(payload_y)) => {
+					path.push(Segment::Key(x.to_string()));
+					compare_at(payload_x, payload_y, path, changes);
+					path.pop();
+					return;
+				}
+				(None, Some(_)) => "unit variant gained a value".to_string(),
+				(Some(_), None) => "variant became a unit variant".to_string(),
+			}
+		}
+		(View::Variant(x, _), View::Variant(y, _)) => {
+			format!("variant changed from `{}` to `{}`", x, y)
+		}
+		(x, y) => format!("{} became {}", x.kind(), y.kind()),
+	};
+	changes.push(Change {
+		path: path.clone(),
+		kind: ChangeKind::Replaced(description, b),
+	});
+}
+
+fn compare_entries<'a>(
+	x: &[(String, &'static str, &Object)],
+	y: &[(String, &'static str, &'a Object<'a>)],
+	path: &mut Vec<Segment>,
+	changes: &mut Vec<Change<'a>>,
+) {
+	for (key, kind_x, value_x) in x {
+		match y.iter().find(|(k, ..)| k == key) {
+			Some((_, kind_y, value_y)) => {
+				path.push(Segment::Key(key.clone()));
+				if kind_x != kind_y {
+					changes.push(Change {
+						path: path.clone(),
+						kind: ChangeKind::Note(format!(
+							"key changed from {} to {}",
+							kind_x, kind_y
+						)),
+					});
+				}
+				compare_at(value_x, value_y, path, changes);
+				path.pop();
+			}
+			None => push(
+				path,
+				Segment::Key(key.clone()),
+				ChangeKind::Removed,
+				changes,
+			),
+		}
+	}
+	for (key, _, value_y) in y {
+		if !x.iter().any(|(k, ..)| k == key) {
+			push(
+				path,
+				Segment::Key(key.clone()),
+				ChangeKind::Added(value_y),
+				changes,
+			);
+		}
+	}
+
+	let order = |entries: &[(String, _, _)], other: &[(String, _, _)]| {
+		entries
+			.iter()
+			.map(|(key, ..)| key)
+			.filter(|key| other.iter().any(|(k, ..)| k == *key))
+			.map(|key| format!("`{}`", key))
+			.collect::<Vec<_>>()
+	};
+	let (order_x, order_y) = (order(x, y), order(y, x));
+	if order_x != order_y {
+		changes.push(Change {
+			path: path.clone(),
+			kind: ChangeKind::Note(format!(
+				"key order changed from {} to {}",
+				order_x.join(", "),
+				order_y.join(", ")
+			)),
+		});
+	}
+}
+
+/// Records `kind` for the entry at `last` below `path`.
+fn push<'a>(
+	path: &mut Vec<Segment>,
+	last: Segment,
+	kind: ChangeKind<'a>,
+	changes: &mut Vec<Change<'a>>,
+) {
+	path.push(last);
+	changes.push(Change {
+		path: path.clone(),
+		kind,
+	});
+	path.pop();
+}
@@ -0,0 +1,249 @@
+//! Format detection from file extensions and, for input, content.
+
+use crate::{Error, In, Out};
+use std::{ffi::OsStr, path::Path};
+
+/// CBOR's self-describe tag 55799, which may prefix any CBOR document.
+const CBOR_SELF_DESCRIBE: &[u8] = b"\xd9\xd9\xf7";
+
+impl In {
+	/// How many bytes of input [`In::sniff`] looks at at most.
+	pub const SNIFF_LENGTH: usize = 1024;
+
+	/// Guesses the format of the file at `path` from its extension (case-insensitively).
+	#[must_use]
+	pub fn from_extension(path: &Path) -> Option<Self> {
+		Some(
+			match path
+				.extension()
+				.and_then(OsStr::to_str)?
+				.to_ascii_lowercase()
+				.as_str()
+			{
+				"bencode" | "torrent" => Self::Bencode,
+				"bincode" => Self::Bincode,
+				"cbor" => Self::Cbor,
+				"json" | "jsonl" | "ndjson" => Self::Json,
+				"msgpack" | "mpk" => Self::Msgpack,
+				"taml" => Self::Taml,
+				"toml" => Self::Toml,
+				"xml" => Self::Xml,
+				"yaml" | "yml" => Self::Yaml,
+				_ => return None,
+			},
+		)
+	}
+
+	/// Guesses the format of a document from its first (up to) [`In::SNIFF_LENGTH`] bytes.
+	///
+	/// Recognises CBOR by its self-describe tag or a top-level map or array,
+	/// MessagePack by a top-level map or array,
+	/// Bencode by its structure, XML by a leading tag and JSON by a leading brace or bracket.
+	/// Other text is assumed to be YAML.
+	///
+	/// # Errors
+	///
+	/// [`Error::Options`], listing the candidates, iff the format is ambiguous.
+	pub fn sniff(prefix: &[u8]) -> Result<Self, Error> {
+		if prefix.starts_with(CBOR_SELF_DESCRIBE) {
+			return Ok(Self::Cbor);
+		}
+		if is_bencode(prefix) {
+			return Ok(Self::Bencode);
+		}
+
+		let text = match std::str::from_utf8(prefix) {
+			Ok(text) => Some(text),
+			// The prefix may end in the middle of a character.
+			Err(error) if error.error_len().is_none() => {
+				std::str::from_utf8(&prefix[..error.valid_up_to()]).ok()
+			}
+			Err(_) => None,
+		}
+		.filter(|text| {
+			!text
+				.chars()
+				.any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r'))
+		});
+		let text = match text {
+			Some(text) => text,
+			None => return binary(prefix[0]),
+		};
+
+		let text = text.trim_start_matches('\u{feff}').trim_start();
+		Ok(match text.chars().next() {
+			Some('<') => Self::Xml,
+			Some('{' | '[') => Self::Json,
+			_ => Self::Yaml,
+		})
+	}
+}
+
+/// Whether `prefix` is a whole Bencode value or parses as the start of one that goes on past it.
+///
+/// A cut-off prefix only counts once it contains a byte string that fits into it and is followed by more input,
+/// since YAML like `description: …` or `d1: …` starts similarly.
+fn is_bencode(prefix: &[u8]) -> bool {
+	let mut parser = BencodeParser {
+		rest: prefix,
+		confirmed: false,
+	};
+	match parser.value(0) {
+		Ok(()) => parser.rest.is_empty(),
+		Err(End::Truncated) => parser.confirmed,
+		Err(End::Invalid) => false,
+	}
+}
+
+/// Nesting beyond this is judged by what was parsed so far.
+const MAX_BENCODE_DEPTH: usize = 64;
+
+struct BencodeParser<'a> {
+	rest: &'a [u8],
+	/// Whether a byte string was followed by more input.
+	confirmed: bool,
+}
+
+enum End {
+	Truncated,
+	Invalid,
+}
+
+impl BencodeParser<'_> {
+	fn value(&mut self, depth: usize) -> Result<(), End> {
+		if depth > MAX_BENCODE_DEPTH {
+			return Err(End::Truncated);
+		}
+		match self.rest.first().ok_or(End::Truncated)? {
+			b'i' => {
+				self.rest = &self.rest[1..];
+				self.rest = self.rest.strip_prefix(b"-").unwrap_or(self.rest);
+				let length = self.digits()?;
+				self.rest = &self.rest[length..];
+				self.expect(b'e')
+			}
+			b'l' => {
+				self.rest = &self.rest[1..];
+				while !self.end()? {
+					self.value(depth + 1)?;
+				}
+				Ok(())
+			}
+			b'd' => {
+				self.rest = &self.rest[1..];
+				while !self.end()? {
+					// Dictionary keys are byte strings.
+					self.byte_string()?;
+					self.value(depth + 1)?;
+				}
+				Ok(())
+			}
+			b'0'..=b'9' => self.byte_string(),
+			_ => Err(End::Invalid),
+		}
+	}
+
+	fn byte_string(&mut self) -> Result<(), End> {
+		let digits = self.digits()?;
+		let length: usize = std::str::from_utf8(&self.rest[..digits])
+			.ok()
+			.and_then(|digits| digits.parse().ok())
+			.ok_or(End::Truncated)?;
+		self.rest = &self.rest[digits..];
+		self.expect(b':')?;
+		if self.rest.len() < length {
+			return Err(End::Truncated);
+		}
+		self.rest = &self.rest[length..];
+		self.confirmed |= !self.rest.is_empty();
+		Ok(())
+	}
+
+	/// Counts the leading digits, of which there must be at least one.
+	fn digits(&self) -> Result<usize, End> {
+		let length = self.rest.iter().take_while(|b| b.is_ascii_digit()).count();
+		match self.rest.get(length) {
+			None => Err(End::Truncated),
+			Some(_) if length == 0 => Err(End::Invalid),
+			Some(_) => Ok(length),
+		}
+	}
+
+	fn expect(&mut self, byte: u8) -> Result<(), End> {
+		match self.rest.split_first() {
+			None => Err(End::Truncated),
+			Some((first, rest)) if *first == byte => {
+				self.rest = rest;
+				Ok(())
+			}
+			Some(_) => Err(End::Invalid),
+		}
+	}
+
+	/// Consumes the `e` that ends a list or dictionary, if it's next.
+	fn end(&mut self) -> Result<bool, End> {
+		match self.rest.first().ok_or(End::Truncated)? {
+			b'e' => {
+				self.rest = &self.rest[1..];
+				Ok(true)
+			}
+			_ => Ok(false),
+		}
+	}
+}
+
+impl Out {
+	/// Chooses the format to write to `path` by its extension (case-insensitively).
+	///
+	/// # Errors
+	///
+	/// [`Error::Options`] iff `path` has no or an unknown extension.
+	pub fn from_extension(path: &Path) -> Result<Self, Error> {
+		let extension = path.extension().and_then(OsStr::to_str).ok_or_else(|| {
+			Error::Options(format!(
+				"{} has no extension to choose the output format by. Specify it with --out.",
+				path.display()
+			))
+		})?;
+		Ok(match extension.to_ascii_lowercase().as_str() {
+			"bencode" | "torrent" => Self::Bencode,
+			"bincode" => Self::Bincode,
+			"cbor" => Self::Cbor,
+			"json" | "jsonl" | "ndjson" => Self::Json,
+			"msgpack" | "mpk" => Self::Msgpack,
+			"taml" => Self::Taml,
+			"toml" => Self::Toml,
+			"xml" => Self::Xml,
+			"yaml" | "yml" => Self::Yaml,
+			_ => {
+				return Err(Error::Options(format!(
+					"Unknown output file extension `.{}`. Specify the format with --out.",
+					extension
+				)))
+			}
+		})
+	}
+}
+
+/// Distinguishes binary formats by the marker of a top-level map or array.
+fn binary(first: u8) -> Result<In, Error> {
+	let cbor = (0x80..=0xbf).contains(&first);
+	let msgpack = (0x80..=0x9f).contains(&first) || (0xdc..=0xdf).contains(&first);
+	match (cbor, msgpack) {
+		(true, false) => Ok(In::Cbor),
+		(false, true) => Ok(In::Msgpack),
+		(true, true) => Err(ambiguous(&[In::Cbor, In::Msgpack])),
+		(false, false) => Err(ambiguous(&[In::Bincode, In::Cbor, In::Msgpack])),
+	}
+}
+
+fn ambiguous(candidates: &[In]) -> Error {
+	Error::Options(format!(
+		"Couldn't detect the input format, which may be any of: {}. Specify it with --in.",
+		candidates
+			.iter()
+			.map(ToString::to_string)
+			.collect::<Vec<_>>()
+			.join(", ")
+	))
+}
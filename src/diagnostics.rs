@@ -0,0 +1,126 @@
+use codespan_reporting::{
+	diagnostic::{Diagnostic, Label, Severity},
+	files::SimpleFile,
+	term::{self, termcolor::WriteColor},
+};
+use serde_taml::taml::diagnostics::{
+	Diagnostic as TamlDiagnostic, DiagnosticLabelPriority, DiagnosticLevel,
+};
+use std::{
+	error::Error as StdError,
+	fmt::{self, Debug, Display, Formatter},
+	io,
+	sync::Arc,
+};
+
+/// Human-readable diagnostics collected while reading an input document, along with its source text.
+///
+/// Currently only TAML input produces these.
+#[derive(Debug)]
+pub struct Diagnostics {
+	text: String,
+	diagnostics: Vec<TamlDiagnostic<usize>>,
+}
+
+impl Diagnostics {
+	pub(crate) fn new(text: String, diagnostics: Vec<TamlDiagnostic<usize>>) -> Self {
+		Self { text, diagnostics }
+	}
+
+	#[must_use]
+	pub fn is_empty(&self) -> bool {
+		self.diagnostics.is_empty()
+	}
+
+	#[must_use]
+	pub fn error_count(&self) -> usize {
+		self.diagnostics
+			.iter()
+			.filter(|diagnostic| matches!(diagnostic.level(), DiagnosticLevel::Error))
+			.count()
+	}
+
+	#[must_use]
+	pub fn warning_count(&self) -> usize {
+		self.diagnostics.len() - self.error_count()
+	}
+
+	/// Renders the diagnostics with source excerpts into `writer`.
+	///
+	/// `name` is used to refer to the input document, usually its file path.
+	/// Warnings are skipped unless `warnings` is `true`.
+	///
+	/// # Errors
+	///
+	/// Iff writing to `writer` fails.
+	pub fn emit(&self, name: &str, writer: &mut dyn WriteColor, warnings: bool) -> io::Result<()> {
+		let file = SimpleFile::new(name, self.text.as_str());
+		let config = term::Config::default();
+		for diagnostic in &self.diagnostics {
+			let severity = match diagnostic.level() {
+				DiagnosticLevel::Error => Severity::Error,
+				DiagnosticLevel::Warning if warnings => Severity::Warning,
+				DiagnosticLevel::Warning => continue,
+			};
+
+			let mut labels = vec![];
+			let mut notes = vec![];
+			for label in &diagnostic.labels {
+				let caption = label.caption.as_deref().unwrap_or_default();
+				match (&label.span, label.priority) {
+					(Some(span), DiagnosticLabelPriority::Primary) => {
+						labels.push(Label::primary((), span.clone()).with_message(caption));
+					}
+					(Some(span), DiagnosticLabelPriority::Auxiliary) => {
+						labels.push(Label::secondary((), span.clone()).with_message(caption));
+					}
+					(None, _) => notes.push(caption.to_string()),
+				}
+			}
+
+			let diagnostic = Diagnostic::new(severity)
+				.with_code(diagnostic.code())
+				.with_message(diagnostic.message())
+				.with_labels(labels)
+				.with_notes(notes);
+			term::emit(writer, &config, &file, &diagnostic).map_err(|error| match error {
+				codespan_reporting::files::Error::Io(error) => error,
+				error => io::Error::new(io::ErrorKind::Other, error.to_string()),
+			})?;
+		}
+		Ok(())
+	}
+}
+
+/// Summarises the diagnostics in one line, for use as error message.
+impl Display for Diagnostics {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let mut errors = self
+			.diagnostics
+			.iter()
+			.filter(|diagnostic| matches!(diagnostic.level(), DiagnosticLevel::Error));
+		match errors.next() {
+			Some(first) => {
+				write!(f, "{} {}", first.code(), first.message())?;
+				let more = errors.count();
+				if more > 0 {
+					write!(f, " (and {} more errors)", more)?;
+				}
+				Ok(())
+			}
+			None => write!(f, "{} warnings", self.warning_count()),
+		}
+	}
+}
+
+impl StdError for Diagnostics {}
+
+/// A callback that receives [`Diagnostics`] as soon as they are available, regardless of whether reading the input succeeds.
+#[derive(Clone)]
+pub struct Reporter(pub Arc<dyn Fn(&Diagnostics) + Send + Sync>);
+
+impl Debug for Reporter {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.write_str("Reporter(..)")
+	}
+}
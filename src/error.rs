@@ -0,0 +1,142 @@
+use crate::{Difference, In, Out};
+use std::{
+	error::Error as StdError,
+	fmt::{self, Display, Formatter},
+	io,
+};
+
+/// Errors that can occur while [`transcode`](`crate::transcode`)ing.
+///
+/// Each kind of error maps onto a distinct [process exit code](`Error::exit_code`),
+/// so that scripts can tell them apart.
+#[derive(Debug)]
+pub enum Error {
+	/// The requested combination of options isn't supported.
+	Options(String),
+
+	/// Reading the input failed.
+	Input(io::Error),
+
+	/// Writing the output failed.
+	Output(io::Error),
+
+	/// The input is not a valid document in the given format.
+	Deserialize {
+		format: In,
+		source: Box<dyn StdError + Send + Sync>,
+	},
+
+	/// The document can't be represented in the given format.
+	Serialize {
+		format: Out,
+		source: Box<dyn StdError + Send + Sync>,
+	},
+
+	/// A transformation couldn't be applied to the document.
+	Transform {
+		transform: &'static str,
+		source: Box<dyn StdError + Send + Sync>,
+	},
+
+	/// [Checking the round trip](`crate::Options::check_round_trip`) found that converting to `format` loses information.
+	RoundTrip {
+		format: Out,
+		differences: Vec<Difference>,
+	},
+
+	/// An input to [`merge`](`crate::merge`) doesn't fit the documents before it.
+	Merge {
+		/// Which input, counting from 1.
+		input: usize,
+		source: Box<dyn StdError + Send + Sync>,
+	},
+}
+
+impl Error {
+	/// The process exit code `reserde` uses to report this error:
+	///
+	/// | code | meaning                                             |
+	/// |------|-----------------------------------------------------|
+	/// | 1    | invalid command line arguments, [`Error::Options`]  |
+	/// | 2    | [`Error::Input`]                                    |
+	/// | 3    | [`Error::Output`]                                   |
+	/// | 4    | [`Error::Deserialize`]                              |
+	/// | 5    | [`Error::Serialize`]                                |
+	/// | 6    | [`Error::Transform`]                                |
+	/// | 7    | [`Error::RoundTrip`]                                |
+	/// | 8    | [`Error::Merge`]                                    |
+	#[must_use]
+	pub fn exit_code(&self) -> i32 {
+		match self {
+			Error::Options(_) => 1,
+			Error::Input(_) => 2,
+			Error::Output(_) => 3,
+			Error::Deserialize { .. } => 4,
+			Error::Serialize { .. } => 5,
+			Error::Transform { .. } => 6,
+			Error::RoundTrip { .. } => 7,
+			Error::Merge { .. } => 8,
+		}
+	}
+}
+
+impl Display for Error {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Error::Options(message) => write!(f, "Invalid options: {}", message),
+			Error::Input(error) => write!(f, "Failed to read input: {}", error),
+			Error::Output(error) => write!(f, "Failed to write output: {}", error),
+			Error::Deserialize { format, source } => {
+				write!(f, "Failed to deserialize input as {}: {}", format, source)
+			}
+			Error::Serialize { format, source } => {
+				write!(f, "Failed to serialize output as {}: {}", format, source)
+			}
+			Error::Transform { transform, source } => {
+				write!(f, "Failed to apply {}: {}", transform, source)
+			}
+			Error::RoundTrip {
+				format,
+				differences,
+			} => {
+				write!(f, "Converting to {} and back changes the document:", format)?;
+				for difference in differences {
+					write!(f, "\n    {}", difference)?;
+				}
+				Ok(())
+			}
+			Error::Merge { input, source } => {
+				write!(f, "Failed to merge input {}: {}", input, source)
+			}
+		}
+	}
+}
+
+impl StdError for Error {
+	fn source(&self) -> Option<&(dyn StdError + 'static)> {
+		match self {
+			Error::Options(_) | Error::RoundTrip { .. } => None,
+			Error::Input(error) | Error::Output(error) => Some(error),
+			Error::Deserialize { source, .. }
+			| Error::Serialize { source, .. }
+			| Error::Transform { source, .. }
+			| Error::Merge { source, .. } => Some(source.as_ref()),
+		}
+	}
+}
+
+/// Creates an [`Error::Deserialize`] mapping for use with [`Result::map_err`].
+pub(crate) fn de<E: StdError + Send + Sync + 'static>(format: In) -> impl FnOnce(E) -> Error {
+	move |source| Error::Deserialize {
+		format,
+		source: Box::new(source),
+	}
+}
+
+/// Creates an [`Error::Serialize`] mapping for use with [`Result::map_err`].
+pub(crate) fn ser<E: StdError + Send + Sync + 'static>(format: Out) -> impl FnOnce(E) -> Error {
+	move |source| Error::Serialize {
+		format,
+		source: Box::new(source),
+	}
+}
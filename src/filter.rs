@@ -0,0 +1,953 @@
+//! A small jq-like language for querying documents, like `.info | {name, length}`.
+
+use crate::{
+	compare::{self, Integer, View},
+	transform::duplicate,
+};
+use serde_object::Object;
+use std::{
+	borrow::Cow,
+	cmp::Ordering,
+	convert::TryFrom,
+	error::Error as StdError,
+	fmt::{self, Display, Formatter},
+	str::FromStr,
+	sync::Arc,
+};
+
+/// A jq-like filter, as used by [`Transform::Filter`](`crate::Transform::Filter`).
+///
+/// This supports a subset of jq:
+///
+/// - `.`, `.key`, `."key"`, `.[0]`, `.[-1]`, `.["key"]`, `.[]` and `..`, with `?` to ignore errors,
+/// - `|`, `,`, `//`, `and`, `or`, `==`, `!=`, `<`, `<=`, `>`, `>=`, `+`, `-`, `*`, `/` and parentheses,
+/// - literals like `1`, `"text"`, `true` and `null`, arrays like `[.[] | .name]` and objects like `{name, size: .length}`,
+/// - and the functions `length`, `keys`, `map(f)`, `select(f)`, `has(key)`, `type`, `not` and `empty`.
+///
+/// Keys match map entries, struct fields and enum variants (as in externally tagged JSON).
+/// Options and newtype structs are looked through.
+#[derive(Debug, Clone)]
+pub struct Filter {
+	source: String,
+	expr: Arc<Expr>,
+}
+
+impl PartialEq for Filter {
+	fn eq(&self, other: &Self) -> bool {
+		self.source == other.source
+	}
+}
+
+impl Eq for Filter {}
+
+impl Display for Filter {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.source)
+	}
+}
+
+/// A [`Filter`] couldn't be parsed.
+#[derive(Debug)]
+pub struct FilterError(String);
+
+impl Display for FilterError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "Invalid filter: {}", self.0)
+	}
+}
+
+impl StdError for FilterError {}
+
+impl FromStr for Filter {
+	type Err = FilterError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut parser = Parser {
+			tokens: lex(s)?,
+			position: 0,
+			source: s,
+		};
+		let expr = parser.pipe()?;
+		if let Some(token) = parser.peek() {
+			return Err(parser.unexpected(token));
+		}
+		Ok(Self {
+			source: s.to_string(),
+			expr: Arc::new(expr),
+		})
+	}
+}
+
+impl Filter {
+	/// Runs the filter on `input`, returning each of its outputs.
+	pub(crate) fn run(&self, input: &Object) -> Result<Vec<Object<'static>>, String> {
+		Ok(eval(&self.expr, input)?
+			.into_iter()
+			.map(Value::into_owned)
+			.collect())
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+	Dot,
+	Recurse,
+	Field(String),
+	Ident(String),
+	String(String),
+	Number(String),
+	Symbol(&'static str),
+}
+
+impl Display for Token {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Token::Dot => f.write_str("."),
+			Token::Recurse => f.write_str(".."),
+			Token::Field(name) => write!(f, ".{}", name),
+			Token::Ident(name) | Token::Number(name) => f.write_str(name),
+			Token::String(string) => write!(f, "{:?}", string),
+			Token::Symbol(symbol) => f.write_str(symbol),
+		}
+	}
+}
+
+/// Longest first, so that e.g. `//` isn't read as two `/`.
+const SYMBOLS: &[&str] = &[
+	"//", "==", "!=", "<=", ">=", "|", ",", "<", ">", "+", "-", "*", "/", "(", ")", "[", "]", "{",
+	"}", ":", ";", "?",
+];
+
+fn lex(source: &str) -> Result<Vec<Token>, FilterError> {
+	let is_ident_start = |c: char| c.is_ascii_alphabetic() || c == '_';
+	let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+	let ident = |rest: &str| rest.find(|c| !is_ident(c)).unwrap_or(rest.len());
+
+	let mut tokens = vec![];
+	let mut rest = source.trim_start();
+	while let Some(c) = rest.chars().next() {
+		let (token, length) = if rest.starts_with("..") {
+			(Token::Recurse, 2)
+		} else if c == '.' {
+			match rest[1..].chars().next() {
+				Some(c) if is_ident_start(c) => {
+					let length = ident(&rest[1..]);
+					(Token::Field(rest[1..=length].to_string()), length + 1)
+				}
+				_ => (Token::Dot, 1),
+			}
+		} else if is_ident_start(c) {
+			let length = ident(rest);
+			(Token::Ident(rest[..length].to_string()), length)
+		} else if c.is_ascii_digit() {
+			let length = rest
+				.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E'))
+				.unwrap_or(rest.len());
+			(Token::Number(rest[..length].to_string()), length)
+		} else if c == '"' {
+			let (string, length) = lex_string(rest)
+				.ok_or_else(|| FilterError(format!("Unterminated string in `{}`", source)))?;
+			(Token::String(string), length)
+		} else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol)) {
+			(Token::Symbol(symbol), symbol.len())
+		} else {
+			return Err(FilterError(format!("Unexpected `{}` in `{}`", c, source)));
+		};
+		tokens.push(token);
+		rest = rest[length..].trim_start();
+	}
+	Ok(tokens)
+}
+
+/// Reads the string literal at the start of `rest`, returning it and its length in `rest`.
+fn lex_string(rest: &str) -> Option<(String, usize)> {
+	let mut string = String::new();
+	let mut chars = rest.char_indices().skip(1);
+	while let Some((i, c)) = chars.next() {
+		match c {
+			'"' => return Some((string, i + 1)),
+			'\\' => string.push(match chars.next()?.1 {
+				'n' => '\n',
+				'r' => '\r',
+				't' => '\t',
+				'u' => {
+					let digits: String = (0..4)
+						.filter_map(|_| chars.next())
+						.map(|(_, c)| c)
+						.collect();
+					char::from_u32(u32::from_str_radix(&digits, 16).ok()?)?
+				}
+				c => c,
+			}),
+			c => string.push(c),
+		}
+	}
+	None
+}
+
+#[derive(Debug)]
+enum Expr {
+	Identity,
+	Recurse,
+	Literal(Literal),
+	Index(Box<Expr>, Box<Expr>),
+	Iterate(Box<Expr>),
+	Try(Box<Expr>),
+	Pipe(Box<Expr>, Box<Expr>),
+	Comma(Box<Expr>, Box<Expr>),
+	Alternative(Box<Expr>, Box<Expr>),
+	And(Box<Expr>, Box<Expr>),
+	Or(Box<Expr>, Box<Expr>),
+	Binary(&'static str, Box<Expr>, Box<Expr>),
+	Array(Option<Box<Expr>>),
+	Object(Vec<(Expr, Expr)>),
+	Call(&'static str, Vec<Expr>),
+}
+
+#[derive(Debug)]
+enum Literal {
+	Null,
+	Bool(bool),
+	Integer(i64),
+	Float(f64),
+	String(String),
+}
+
+/// Functions by name and number of arguments.
+const FUNCTIONS: &[(&str, usize)] = &[
+	("empty", 0),
+	("has", 1),
+	("keys", 0),
+	("length", 0),
+	("map", 1),
+	("not", 0),
+	("select", 1),
+	("type", 0),
+];
+
+struct Parser<'a> {
+	tokens: Vec<Token>,
+	position: usize,
+	source: &'a str,
+}
+
+impl Parser<'_> {
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.position)
+	}
+
+	fn next(&mut self) -> Option<Token> {
+		let token = self.tokens.get(self.position).cloned();
+		self.position += 1;
+		token
+	}
+
+	/// Consumes the next token if it's `symbol`.
+	fn eat(&mut self, symbol: &str) -> bool {
+		let found = matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol)
+			|| matches!(self.peek(), Some(Token::Ident(s)) if s == symbol);
+		if found {
+			self.position += 1;
+		}
+		found
+	}
+
+	fn expect(&mut self, symbol: &str) -> Result<(), FilterError> {
+		if self.eat(symbol) {
+			Ok(())
+		} else {
+			Err(match self.peek() {
+				Some(token) => FilterError(format!(
+					"Expected `{}` but found `{}` in `{}`",
+					symbol, token, self.source
+				)),
+				None => FilterError(format!(
+					"Expected `{}` at the end of `{}`",
+					symbol, self.source
+				)),
+			})
+		}
+	}
+
+	fn unexpected(&self, token: &Token) -> FilterError {
+		FilterError(format!("Unexpected `{}` in `{}`", token, self.source))
+	}
+
+	fn pipe(&mut self) -> Result<Expr, FilterError> {
+		let left = self.comma()?;
+		Ok(if self.eat("|") {
+			Expr::Pipe(Box::new(left), Box::new(self.pipe()?))
+		} else {
+			left
+		})
+	}
+
+	fn comma(&mut self) -> Result<Expr, FilterError> {
+		let mut left = self.alternative()?;
+		while self.eat(",") {
+			left = Expr::Comma(Box::new(left), Box::new(self.alternative()?));
+		}
+		Ok(left)
+	}
+
+	fn alternative(&mut self) -> Result<Expr, FilterError> {
+		let left = self.or()?;
+		Ok(if self.eat("//") {
+			Expr::Alternative(Box::new(left), Box::new(self.alternative()?))
+		} else {
+			left
+		})
+	}
+
+	fn or(&mut self) -> Result<Expr, FilterError> {
+		let mut left = self.and()?;
+		while self.eat("or") {
+			left = Expr::Or(Box::new(left), Box::new(self.and()?));
+		}
+		Ok(left)
+	}
+
+	fn and(&mut self) -> Result<Expr, FilterError> {
+		let mut left = self.comparison()?;
+		while self.eat("and") {
+			left = Expr::And(Box::new(left), Box::new(self.comparison()?));
+		}
+		Ok(left)
+	}
+
+	fn comparison(&mut self) -> Result<Expr, FilterError> {
+		let left = self.additive()?;
+		for &operator in &["==", "!=", "<=", ">=", "<", ">"] {
+			if self.eat(operator) {
+				return Ok(Expr::Binary(
+					operator,
+					Box::new(left),
+					Box::new(self.additive()?),
+				));
+			}
+		}
+		Ok(left)
+	}
+
+	fn additive(&mut self) -> Result<Expr, FilterError> {
+		let mut left = self.multiplicative()?;
+		loop {
+			let operator = if self.eat("+") {
+				"+"
+			} else if self.eat("-") {
+				"-"
+			} else {
+				return Ok(left);
+			};
+			left = Expr::Binary(operator, Box::new(left), Box::new(self.multiplicative()?));
+		}
+	}
+
+	fn multiplicative(&mut self) -> Result<Expr, FilterError> {
+		let mut left = self.postfix()?;
+		loop {
+			let operator = if self.eat("*") {
+				"*"
+			} else if self.eat("/") {
+				"/"
+			} else {
+				return Ok(left);
+			};
+			left = Expr::Binary(operator, Box::new(left), Box::new(self.postfix()?));
+		}
+	}
+
+	fn postfix(&mut self) -> Result<Expr, FilterError> {
+		let mut expr = self.primary()?;
+		loop {
+			expr = match self.peek() {
+				Some(Token::Field(name)) => {
+					let key = Expr::Literal(Literal::String(name.clone()));
+					self.position += 1;
+					Expr::Index(Box::new(expr), Box::new(key))
+				}
+				Some(Token::Dot)
+					if matches!(self.tokens.get(self.position + 1), Some(Token::String(_))) =>
+				{
+					self.position += 1;
+					let key = self.primary()?;
+					Expr::Index(Box::new(expr), Box::new(key))
+				}
+				Some(Token::Symbol("[")) => {
+					self.position += 1;
+					if self.eat("]") {
+						Expr::Iterate(Box::new(expr))
+					} else {
+						let key = self.pipe()?;
+						self.expect("]")?;
+						Expr::Index(Box::new(expr), Box::new(key))
+					}
+				}
+				Some(Token::Symbol("?")) => {
+					self.position += 1;
+					Expr::Try(Box::new(expr))
+				}
+				_ => return Ok(expr),
+			}
+		}
+	}
+
+	fn primary(&mut self) -> Result<Expr, FilterError> {
+		let token = self
+			.next()
+			.ok_or_else(|| FilterError(format!("Unexpected end of `{}`", self.source)))?;
+		Ok(match token {
+			Token::Dot => match self.peek() {
+				Some(Token::String(key)) => {
+					let key = Expr::Literal(Literal::String(key.clone()));
+					self.position += 1;
+					Expr::Index(Box::new(Expr::Identity), Box::new(key))
+				}
+				_ => Expr::Identity,
+			},
+			Token::Recurse => Expr::Recurse,
+			Token::Field(name) => Expr::Index(
+				Box::new(Expr::Identity),
+				Box::new(Expr::Literal(Literal::String(name))),
+			),
+			Token::String(string) => Expr::Literal(Literal::String(string)),
+			Token::Number(number) => Expr::Literal(match number.parse() {
+				Ok(integer) => Literal::Integer(integer),
+				Err(_) => Literal::Float(number.parse().map_err(|_| {
+					FilterError(format!("`{}` in `{}` is not a number", number, self.source))
+				})?),
+			}),
+			Token::Ident(name) => match name.as_str() {
+				"null" => Expr::Literal(Literal::Null),
+				"true" => Expr::Literal(Literal::Bool(true)),
+				"false" => Expr::Literal(Literal::Bool(false)),
+				_ => self.call(&name)?,
+			},
+			Token::Symbol("(") => {
+				let expr = self.pipe()?;
+				self.expect(")")?;
+				expr
+			}
+			Token::Symbol("[") => {
+				if self.eat("]") {
+					Expr::Array(None)
+				} else {
+					let expr = self.pipe()?;
+					self.expect("]")?;
+					Expr::Array(Some(Box::new(expr)))
+				}
+			}
+			Token::Symbol("{") => self.object()?,
+			Token::Symbol("-") => Expr::Binary(
+				"-",
+				Box::new(Expr::Literal(Literal::Integer(0))),
+				Box::new(self.postfix()?),
+			),
+			token @ Token::Symbol(_) => return Err(self.unexpected(&token)),
+		})
+	}
+
+	fn call(&mut self, name: &str) -> Result<Expr, FilterError> {
+		let mut arguments = vec![];
+		if self.eat("(") {
+			loop {
+				arguments.push(self.pipe()?);
+				if !self.eat(";") {
+					break;
+				}
+			}
+			self.expect(")")?;
+		}
+		let &(name, _) = FUNCTIONS
+			.iter()
+			.find(|&&(n, arity)| n == name && arity == arguments.len())
+			.ok_or_else(|| {
+				FilterError(format!(
+					"Unknown function `{}/{}` in `{}`",
+					name,
+					arguments.len(),
+					self.source
+				))
+			})?;
+		Ok(match (name, arguments.pop()) {
+			// `map(f)` is `[.[] | f]`.
+			("map", Some(f)) => Expr::Array(Some(Box::new(Expr::Pipe(
+				Box::new(Expr::Iterate(Box::new(Expr::Identity))),
+				Box::new(f),
+			)))),
+			(name, argument) => Expr::Call(name, argument.into_iter().collect()),
+		})
+	}
+
+	fn object(&mut self) -> Result<Expr, FilterError> {
+		let mut entries = vec![];
+		if self.eat("}") {
+			return Ok(Expr::Object(entries));
+		}
+		loop {
+			let (key, shorthand) = match self.next() {
+				Some(Token::Ident(key) | Token::String(key)) => {
+					(Expr::Literal(Literal::String(key.clone())), Some(key))
+				}
+				Some(Token::Symbol("(")) => {
+					let key = self.pipe()?;
+					self.expect(")")?;
+					(key, None)
+				}
+				Some(token) => return Err(self.unexpected(&token)),
+				None => return Err(FilterError(format!("Unexpected end of `{}`", self.source))),
+			};
+			let value = if self.eat(":") {
+				self.alternative()?
+			} else if let Some(key) = shorthand {
+				// `{name}` is `{name: .name}`.
+				Expr::Index(
+					Box::new(Expr::Identity),
+					Box::new(Expr::Literal(Literal::String(key))),
+				)
+			} else {
+				self.expect(":")?;
+				unreachable!("`expect` fails")
+			};
+			entries.push((key, value));
+			if !self.eat(",") {
+				break;
+			}
+		}
+		self.expect("}")?;
+		Ok(Expr::Object(entries))
+	}
+}
+
+/// An output of a filter, borrowed from the input where possible.
+enum Value<'a> {
+	Ref(&'a Object<'a>),
+	Owned(Object<'static>),
+}
+
+impl Value<'_> {
+	fn get(&self) -> &Object<'_> {
+		match self {
+			Value::Ref(object) => object,
+			Value::Owned(object) => object,
+		}
+	}
+
+	fn into_owned(self) -> Object<'static> {
+		match self {
+			Value::Ref(object) => duplicate(object),
+			Value::Owned(object) => object,
+		}
+	}
+}
+
+fn eval<'a>(expr: &Expr, input: &'a Object<'a>) -> Result<Vec<Value<'a>>, String> {
+	Ok(match expr {
+		Expr::Identity => vec![Value::Ref(input)],
+		Expr::Recurse => {
+			let mut outputs = vec![];
+			recurse(input, &mut outputs);
+			outputs.into_iter().map(Value::Ref).collect()
+		}
+		Expr::Literal(literal) => vec![Value::Owned(literal.to_object())],
+		Expr::Index(target, key) => eval_index(target, key, input)?,
+		Expr::Iterate(target) => eval_iterate(target, input)?,
+		Expr::Try(expr) => eval(expr, input).unwrap_or_default(),
+		Expr::Pipe(left, right) => {
+			let mut outputs = vec![];
+			for value in eval(left, input)? {
+				match value {
+					Value::Ref(value) => outputs.extend(eval(right, value)?),
+					Value::Owned(value) => outputs.extend(
+						eval(right, &value)?
+							.into_iter()
+							.map(|output| Value::Owned(output.into_owned())),
+					),
+				}
+			}
+			outputs
+		}
+		Expr::Comma(left, right) => {
+			let mut outputs = eval(left, input)?;
+			outputs.extend(eval(right, input)?);
+			outputs
+		}
+		Expr::Alternative(left, right) => {
+			let outputs: Vec<_> = eval(left, input)
+				.unwrap_or_default()
+				.into_iter()
+				.filter(|value| truthy(value.get()))
+				.collect();
+			if outputs.is_empty() {
+				eval(right, input)?
+			} else {
+				outputs
+			}
+		}
+		Expr::And(left, right) | Expr::Or(left, right) => {
+			let is_and = matches!(expr, Expr::And(..));
+			let mut outputs = vec![];
+			for left in eval(left, input)? {
+				if truthy(left.get()) == is_and {
+					for right in eval(right, input)? {
+						outputs.push(Value::Owned(Object::Bool(truthy(right.get()))));
+					}
+				} else {
+					outputs.push(Value::Owned(Object::Bool(!is_and)));
+				}
+			}
+			outputs
+		}
+		Expr::Binary(operator, left, right) => {
+			let lefts = eval(left, input)?;
+			let mut outputs = vec![];
+			// Like jq, with the right side in the outer loop.
+			for right in eval(right, input)? {
+				for left in &lefts {
+					outputs.push(Value::Owned(binary(operator, left.get(), right.get())?));
+				}
+			}
+			outputs
+		}
+		Expr::Array(None) => vec![Value::Owned(Object::Seq(vec![]))],
+		Expr::Array(Some(expr)) => vec![Value::Owned(Object::Seq(
+			eval(expr, input)?
+				.into_iter()
+				.map(Value::into_owned)
+				.collect(),
+		))],
+		Expr::Object(entries) => eval_object(entries, input)?,
+		Expr::Call(name, arguments) => call(name, arguments, input)?,
+	})
+}
+
+fn eval_index<'a>(
+	target: &Expr,
+	key: &Expr,
+	input: &'a Object<'a>,
+) -> Result<Vec<Value<'a>>, String> {
+	let keys = eval(key, input)?;
+	let mut outputs = vec![];
+	for target in eval(target, input)? {
+		for key in &keys {
+			outputs.push(match &target {
+				Value::Ref(target) => index(target, key.get())?.map_or(null(), Value::Ref),
+				Value::Owned(target) => {
+					index(target, key.get())?.map_or(null(), |child| Value::Owned(duplicate(child)))
+				}
+			});
+		}
+	}
+	Ok(outputs)
+}
+
+fn eval_iterate<'a>(target: &Expr, input: &'a Object<'a>) -> Result<Vec<Value<'a>>, String> {
+	let mut outputs = vec![];
+	for target in eval(target, input)? {
+		match target {
+			Value::Ref(target) => outputs.extend(children(target)?.into_iter().map(Value::Ref)),
+			Value::Owned(target) => outputs.extend(
+				children(&target)?
+					.into_iter()
+					.map(|child| Value::Owned(duplicate(child))),
+			),
+		}
+	}
+	Ok(outputs)
+}
+
+/// Builds one object for each combination of the entries' keys and values, like jq.
+fn eval_object<'a>(
+	entries: &[(Expr, Expr)],
+	input: &'a Object<'a>,
+) -> Result<Vec<Value<'a>>, String> {
+	let mut objects = vec![vec![]];
+	for (key, value) in entries {
+		let keys = eval(key, input)?;
+		let values = eval(value, input)?;
+		let mut extended = vec![];
+		for object in &objects {
+			for key in &keys {
+				if !matches!(View::of(key.get()), View::Text(_)) {
+					return Err(format!(
+						"Object keys must be strings, not {}",
+						compare::kind(key.get())
+					));
+				}
+				for value in &values {
+					let mut object: Vec<_> = object
+						.iter()
+						.map(|(k, v)| (duplicate(k), duplicate(v)))
+						.collect();
+					object.push((duplicate(key.get()), duplicate(value.get())));
+					extended.push(object);
+				}
+			}
+		}
+		objects = extended;
+	}
+	Ok(objects
+		.into_iter()
+		.map(|object| Value::Owned(Object::Map(object)))
+		.collect())
+}
+
+fn null<'a>() -> Value<'a> {
+	Value::Owned(Object::Unit)
+}
+
+fn truthy(object: &Object) -> bool {
+	!matches!(View::of(object), View::Null | View::Bool(false))
+}
+
+fn recurse<'a>(object: &'a Object<'a>, outputs: &mut Vec<&'a Object<'a>>) {
+	outputs.push(object);
+	for child in children(object).unwrap_or_default() {
+		recurse(child, outputs);
+	}
+}
+
+/// Values of maps and elements of sequences.
+fn children<'a>(object: &'a Object<'a>) -> Result<Vec<&'a Object<'a>>, String> {
+	Ok(match View::of(object) {
+		View::Seq(elements) => elements.iter().collect(),
+		View::Map(map) => map.into_iter().map(|(_, v)| v).collect(),
+		View::Fields(fields) => fields.into_iter().map(|(_, v)| v).collect(),
+		View::Variant(_, Some(payload)) => vec![payload],
+		view => return Err(format!("Can't iterate over {}", view.kind())),
+	})
+}
+
+/// Whether `a` and `b` identify the same map entry.
+fn same_key(a: &Object, b: &Object) -> bool {
+	let textual = |view: &View| matches!(view, View::Text(_) | View::Bytes(_));
+	let (a, b) = (View::of(a), View::of(b));
+	(textual(&a) && textual(&b) || matches!((&a, &b), (View::Integer(_), View::Integer(_))))
+		&& a.key() == b.key()
+}
+
+fn index<'a>(object: &'a Object<'a>, key: &Object) -> Result<Option<&'a Object<'a>>, String> {
+	Ok(match (View::of(object), View::of(key)) {
+		(View::Null, _) => None,
+		(View::Map(map), View::Text(_) | View::Integer(_)) => map
+			.into_iter()
+			.find(|(k, _)| same_key(k, key))
+			.map(|(_, v)| v),
+		(View::Fields(fields), View::Text(key)) => {
+			fields.into_iter().find(|(k, _)| *k == key).map(|(_, v)| v)
+		}
+		(View::Variant(name, payload), View::Text(key)) => payload.filter(|_| name == key),
+		(View::Seq(elements), View::Integer(Integer::Signed(index))) => {
+			let index = if index < 0 {
+				i128::try_from(elements.len()).map_or(-1, |len| len + index)
+			} else {
+				index
+			};
+			usize::try_from(index)
+				.ok()
+				.and_then(|index| elements.get(index))
+		}
+		(object, key) => return Err(format!("Can't index {} with {}", object.kind(), key.kind())),
+	})
+}
+
+fn call<'a>(
+	name: &str,
+	arguments: &[Expr],
+	input: &'a Object<'a>,
+) -> Result<Vec<Value<'a>>, String> {
+	let view = View::of(input);
+	let length = |length: usize| Object::U64(length as u64);
+	Ok(vec![Value::Owned(match (name, arguments) {
+		("empty", []) => return Ok(vec![]),
+		("select", [f]) => {
+			let mut outputs = vec![];
+			for condition in eval(f, input)? {
+				if truthy(condition.get()) {
+					outputs.push(Value::Ref(input));
+				}
+			}
+			return Ok(outputs);
+		}
+		("has", [key]) => {
+			let mut outputs = vec![];
+			for key in eval(key, input)? {
+				let has = match (&view, View::of(key.get())) {
+					(View::Map(map), _) => map.iter().any(|(k, _)| same_key(k, key.get())),
+					(View::Fields(fields), View::Text(key)) => {
+						fields.iter().any(|(k, _)| *k == key)
+					}
+					(View::Seq(elements), View::Integer(Integer::Signed(index))) => {
+						usize::try_from(index).map_or(false, |index| index < elements.len())
+					}
+					(view, key) => {
+						return Err(format!(
+							"Can't check whether {} has {}",
+							view.kind(),
+							key.kind()
+						))
+					}
+				};
+				outputs.push(Value::Owned(Object::Bool(has)));
+			}
+			return Ok(outputs);
+		}
+		("length", []) => match view {
+			View::Null => length(0),
+			View::Integer(Integer::Signed(value)) => value
+				.checked_abs()
+				.map_or_else(|| Object::F64(float(Integer::Signed(value)).abs()), number),
+			View::Integer(Integer::Unsigned(value)) => Object::U128(value),
+			View::Float(value) => Object::F64(value.abs()),
+			View::Text(text) => length(text.chars().count()),
+			View::Bytes(bytes) => length(bytes.len()),
+			View::Seq(elements) => length(elements.len()),
+			View::Map(map) => length(map.len()),
+			View::Fields(fields) => length(fields.len()),
+			view => return Err(format!("{} has no length", capitalize(view.kind()))),
+		},
+		("keys", []) => Object::Seq(match view {
+			View::Map(map) => {
+				let mut keys: Vec<_> = map.into_iter().map(|(k, _)| k).collect();
+				keys.sort_by(|a, b| compare::key_cmp(a, b));
+				keys.into_iter().map(duplicate).collect()
+			}
+			View::Fields(fields) => {
+				let mut keys: Vec<_> = fields.into_iter().map(|(k, _)| k).collect();
+				keys.sort_unstable();
+				keys.into_iter()
+					.map(|k| Object::String(Cow::Owned(k.to_string())))
+					.collect()
+			}
+			View::Seq(elements) => (0..elements.len()).map(length).collect(),
+			view => return Err(format!("{} has no keys", capitalize(view.kind()))),
+		}),
+		("not", []) => Object::Bool(!truthy(input)),
+		("type", []) => Object::String(Cow::Borrowed(match view {
+			View::Null => "null",
+			View::Bool(_) => "boolean",
+			View::Integer(_) | View::Float(_) => "number",
+			View::Text(_) => "string",
+			View::Bytes(_) => "bytes",
+			View::Seq(_) => "array",
+			View::Map(_) | View::Fields(_) => "object",
+			View::Variant(..) => "variant",
+		})),
+		_ => unreachable!("checked while parsing"),
+	})])
+}
+
+fn capitalize(kind: &str) -> String {
+	let mut chars = kind.chars();
+	chars
+		.next()
+		.map(|first| first.to_uppercase().chain(chars).collect())
+		.unwrap_or_default()
+}
+
+impl Literal {
+	fn to_object(&self) -> Object<'static> {
+		match self {
+			Literal::Null => Object::Unit,
+			Literal::Bool(value) => Object::Bool(*value),
+			Literal::Integer(value) => Object::I64(*value),
+			Literal::Float(value) => Object::F64(*value),
+			Literal::String(value) => Object::String(Cow::Owned(value.clone())),
+		}
+	}
+}
+
+/// The smallest of `i64` and `i128` that fits `value`.
+fn number(value: i128) -> Object<'static> {
+	i64::try_from(value).map_or(Object::I128(value), Object::I64)
+}
+
+#[allow(clippy::cast_precision_loss)] // Like jq, which only has `f64`.
+fn float(integer: Integer) -> f64 {
+	match integer {
+		Integer::Signed(value) => value as f64,
+		Integer::Unsigned(value) => value as f64,
+	}
+}
+
+fn binary(operator: &str, left: &Object, right: &Object) -> Result<Object<'static>, String> {
+	let (l, r) = (View::of(left), View::of(right));
+	Ok(match (operator, &l, &r) {
+		("==", ..) => Object::Bool(compare::compare(left, right).is_empty()),
+		("!=", ..) => Object::Bool(!compare::compare(left, right).is_empty()),
+		("<" | "<=" | ">" | ">=", ..) => {
+			let ordering = match (&l, &r) {
+				(View::Integer(l), View::Integer(r)) => Some(l.cmp(r)),
+				(View::Integer(l), View::Float(r)) => float(*l).partial_cmp(r),
+				(View::Float(l), View::Integer(r)) => l.partial_cmp(&float(*r)),
+				(View::Float(l), View::Float(r)) => l.partial_cmp(r),
+				(View::Text(l), View::Text(r)) => Some(l.cmp(r)),
+				_ => return Err(format!("Can't compare {} with {}", l.kind(), r.kind())),
+			};
+			Object::Bool(match (operator, ordering) {
+				(_, None) => false,
+				("<", Some(ordering)) => ordering == Ordering::Less,
+				("<=", Some(ordering)) => ordering != Ordering::Greater,
+				(">", Some(ordering)) => ordering == Ordering::Greater,
+				(_, Some(ordering)) => ordering != Ordering::Less,
+			})
+		}
+		("+", View::Null, _) => duplicate(right),
+		("+", _, View::Null) => duplicate(left),
+		("+", View::Text(l), View::Text(r)) => Object::String(Cow::Owned(format!("{}{}", l, r))),
+		("+", View::Seq(l), View::Seq(r)) => {
+			Object::Seq(l.iter().chain(r.iter()).map(duplicate).collect())
+		}
+		("-", View::Seq(l), View::Seq(r)) => Object::Seq(
+			l.iter()
+				.filter(|l| !r.iter().any(|r| compare::compare(l, r).is_empty()))
+				.map(duplicate)
+				.collect(),
+		),
+		(_, View::Integer(Integer::Signed(l)), View::Integer(Integer::Signed(r))) => {
+			let result = match operator {
+				"+" => l.checked_add(*r),
+				"-" => l.checked_sub(*r),
+				"*" => l.checked_mul(*r),
+				_ if *r == 0 => return Err("Division by zero".to_string()),
+				_ if l.checked_rem(*r) == Some(0) => l.checked_div(*r),
+				_ => None,
+			};
+			result.map_or_else(
+				|| {
+					Object::F64(arithmetic(
+						operator,
+						float(Integer::Signed(*l)),
+						float(Integer::Signed(*r)),
+					))
+				},
+				number,
+			)
+		}
+		(_, View::Integer(_) | View::Float(_), View::Integer(_) | View::Float(_)) => {
+			let as_float = |view: &View| match view {
+				View::Integer(integer) => float(*integer),
+				View::Float(value) => *value,
+				_ => unreachable!("matched above"),
+			};
+			Object::F64(arithmetic(operator, as_float(&l), as_float(&r)))
+		}
+		_ => {
+			return Err(format!(
+				"Can't apply `{}` to {} and {}",
+				operator,
+				l.kind(),
+				r.kind()
+			))
+		}
+	})
+}
+
+fn arithmetic(operator: &str, left: f64, right: f64) -> f64 {
+	match operator {
+		"+" => left + right,
+		"-" => left - right,
+		"*" => left * right,
+		_ => left / right,
+	}
+}
@@ -0,0 +1,1132 @@
+//! A Serde-based document converter.
+//!
+//! This library contains the conversion logic behind the `reserde` command line tool,
+//! so that it can be embedded without shelling out to the binary.
+//!
+//! # Example
+//!
+//! ```rust
+//! use reserde::{transcode, In, Options, Out};
+//!
+//! let mut output = vec![];
+//! transcode(
+//!     r#"{"key": "value"}"#.as_bytes(),
+//!     In::Json,
+//!     &mut output,
+//!     Out::Yaml,
+//!     &Options::default(),
+//! )?;
+//! assert_eq!(output, b"---\nkey: value\n");
+//! # Ok::<_, reserde::Error>(())
+//! ```
+
+#![doc(html_root_url = "https://docs.rs/reserde/0.0.4")]
+#![warn(clippy::pedantic)]
+
+use bincode::Options as _;
+use serde::{Deserialize as _, Serialize};
+use serde_detach::{detach, Detach};
+use serde_object::Object;
+use std::{
+	borrow::Cow,
+	io::{BufRead as _, BufReader, Read, Write},
+};
+use strum::{Display, EnumString, EnumVariantNames, IntoStaticStr};
+
+mod annotated;
+mod batch;
+mod compare;
+mod detect;
+mod diagnostics;
+mod error;
+mod filter;
+mod merge;
+mod msgpack;
+mod patch;
+mod path;
+mod schema;
+mod shape;
+mod split;
+mod streaming;
+mod taml_format;
+mod toml_format;
+mod transform;
+
+pub use batch::{convert_tree, Conversion};
+pub use compare::Difference;
+pub use diagnostics::{Diagnostics, Reporter};
+pub use error::Error;
+pub use filter::{Filter, FilterError};
+pub use patch::{Patch, PatchError};
+pub use path::{KeyPath, KeyPathError};
+pub use schema::{Schema, SchemaError};
+pub use transform::Transform;
+
+/// Input formats.
+#[derive(Debug, Display, EnumString, EnumVariantNames, Clone, Copy)]
+pub enum In {
+	#[strum(serialize = "bencode")]
+	Bencode,
+
+	/// Requires a [`Schema`], since Bincode isn't self-describing.
+	#[strum(serialize = "bincode")]
+	Bincode,
+
+	#[strum(serialize = "cbor")]
+	Cbor,
+
+	#[strum(serialize = "json")]
+	Json,
+
+	#[strum(serialize = "msgpack")]
+	Msgpack,
+
+	#[strum(serialize = "taml")]
+	Taml,
+
+	#[strum(serialize = "toml")]
+	Toml,
+
+	#[strum(serialize = "urlencoded")]
+	Urlencoded,
+
+	#[strum(serialize = "xml")]
+	Xml,
+
+	#[strum(serialize = "yaml")]
+	Yaml,
+}
+
+/// Output formats.
+#[derive(Debug, Display, EnumString, EnumVariantNames, Clone, Copy)]
+pub enum Out {
+	#[strum(serialize = "bencode")]
+	Bencode,
+
+	#[strum(serialize = "bincode")]
+	Bincode,
+
+	#[strum(serialize = "cbor")]
+	Cbor,
+
+	#[strum(serialize = "json")]
+	Json,
+
+	#[strum(serialize = "msgpack")]
+	Msgpack,
+
+	#[strum(serialize = "taml")]
+	Taml,
+
+	#[strum(serialize = "toml")]
+	Toml,
+
+	#[strum(serialize = "urlencoded")]
+	Urlencoded,
+
+	#[strum(serialize = "xml")]
+	Xml,
+
+	#[strum(serialize = "yaml")]
+	Yaml,
+}
+
+impl From<Out> for In {
+	fn from(out_format: Out) -> Self {
+		match out_format {
+			Out::Bencode => In::Bencode,
+			Out::Bincode => In::Bincode,
+			Out::Cbor => In::Cbor,
+			Out::Json => In::Json,
+			Out::Msgpack => In::Msgpack,
+			Out::Taml => In::Taml,
+			Out::Toml => In::Toml,
+			Out::Urlencoded => In::Urlencoded,
+			Out::Xml => In::Xml,
+			Out::Yaml => In::Yaml,
+		}
+	}
+}
+
+/// Text encodings tried when stringifying byte arrays and non-string keys.
+#[derive(Debug, EnumString, EnumVariantNames, IntoStaticStr, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+	/// Fails on invalid UTF-8.
+	#[strum(serialize = "utf8")]
+	Utf8,
+
+	/// ISO-8859-1, mapping each byte to the code point with the same value. Never fails.
+	#[strum(serialize = "latin1")]
+	Latin1,
+
+	/// Windows-1252, as decoded by web browsers. Never fails.
+	#[strum(serialize = "cp1252")]
+	Cp1252,
+
+	/// Little-endian UTF-16.
+	///
+	/// A byte order mark is kept as U+FEFF, so that `--bytes-from` writes it back.
+	/// Fails on odd lengths, unpaired surrogates and a big-endian byte order mark.
+	#[strum(serialize = "utf16le")]
+	Utf16Le,
+
+	/// Big-endian UTF-16.
+	///
+	/// A byte order mark is kept as U+FEFF, so that `--bytes-from` writes it back.
+	/// Fails on odd lengths, unpaired surrogates and a little-endian byte order mark.
+	#[strum(serialize = "utf16be")]
+	Utf16Be,
+
+	/// Lowercase hexadecimal digits. Never fails, so try this last.
+	#[strum(serialize = "hex")]
+	Hex,
+
+	/// Standard Base64 with padding. Never fails, so try this last.
+	#[strum(serialize = "base64")]
+	Base64,
+}
+
+/// How documents are converted.
+#[derive(Debug, Display, EnumString, EnumVariantNames, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+	/// Stream where possible, otherwise go through [`Object`].
+	#[strum(serialize = "auto")]
+	Auto,
+
+	/// Pass values straight from the deserializer to the serializer.
+	///
+	/// This is only available when no transformations are requested and both formats allow it,
+	/// currently from Bencode or JSON to CBOR or JSON.
+	///
+	/// On failure, part of the output may already have been written.
+	#[strum(serialize = "streaming")]
+	Streaming,
+
+	/// Always deserialize the whole document into an [`Object`] first.
+	#[strum(serialize = "object")]
+	Object,
+}
+
+impl Default for Strategy {
+	fn default() -> Self {
+		Self::Auto
+	}
+}
+
+/// How TOML datetimes are represented when converting to formats without datetimes.
+///
+/// TOML output always turns tagged datetimes back into TOML datetimes,
+/// so they survive a round trip through other formats.
+#[derive(Debug, Display, EnumString, EnumVariantNames, Clone, Copy, PartialEq, Eq)]
+pub enum TomlDatetimes {
+	/// As plain strings, like `"1979-05-27T07:32:00Z"`.
+	#[strum(serialize = "string")]
+	String,
+
+	/// As single-entry maps, like `{"$datetime": "1979-05-27T07:32:00Z"}`.
+	#[strum(serialize = "tagged")]
+	Tagged,
+}
+
+impl Default for TomlDatetimes {
+	fn default() -> Self {
+		Self::String
+	}
+}
+
+/// How structs are written as MessagePack.
+#[derive(Debug, Display, EnumString, EnumVariantNames, Clone, Copy, PartialEq, Eq)]
+pub enum MsgpackStructs {
+	/// As maps from field names to values.
+	#[strum(serialize = "named")]
+	Named,
+
+	/// As arrays of field values, like `rmp_serde` does by default.
+	#[strum(serialize = "compact")]
+	Compact,
+}
+
+impl Default for MsgpackStructs {
+	fn default() -> Self {
+		Self::Named
+	}
+}
+
+/// How [`merge`] combines sequences.
+///
+/// Maps and structs are always merged by key and other values are replaced by later ones.
+#[derive(Debug, Display, EnumString, EnumVariantNames, Clone, Copy, PartialEq, Eq)]
+pub enum Merge {
+	/// Later sequences replace earlier ones.
+	#[strum(serialize = "replace")]
+	Replace,
+
+	/// Later sequences are appended to earlier ones.
+	#[strum(serialize = "append")]
+	Append,
+}
+
+impl Default for Merge {
+	fn default() -> Self {
+		Self::Replace
+	}
+}
+
+/// How many documents [`transcode`] reads from its input.
+#[derive(Debug, Display, EnumString, EnumVariantNames, Clone, Copy, PartialEq, Eq)]
+pub enum Documents {
+	/// The input is one document.
+	#[strum(serialize = "single")]
+	Single,
+
+	/// Read every document of YAML streams, concatenated JSON values (like JSON Lines) and RFC 8742 CBOR sequences,
+	/// apply the transformations to each and write them as the output format's equivalent.
+	///
+	/// Other input formats are read as a single document.
+	/// Other output formats can only be written if there's exactly one document.
+	#[strum(serialize = "each")]
+	Each,
+
+	/// Like [`Documents::Each`], but collect the transformed documents into one sequence.
+	#[strum(serialize = "slurp")]
+	Slurp,
+}
+
+impl Default for Documents {
+	fn default() -> Self {
+		Self::Single
+	}
+}
+
+/// How [`join`] assembles documents.
+#[derive(Debug, Display, EnumString, EnumVariantNames, Clone, Copy, PartialEq, Eq)]
+pub enum Join {
+	/// A sequence of the documents, in order.
+	#[strum(serialize = "sequence")]
+	Sequence,
+
+	/// A map from each document's name to the document.
+	#[strum(serialize = "map")]
+	Map,
+}
+
+impl Default for Join {
+	fn default() -> Self {
+		Self::Sequence
+	}
+}
+
+/// Conversion options, mirroring the command line flags.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+	/// Pretty-print (where supported).
+	pub pretty: bool,
+
+	/// Transformations applied to the document, in order.
+	pub transforms: Vec<Transform>,
+
+	/// Whether to stream or go through [`Object`].
+	pub strategy: Strategy,
+
+	/// How TOML datetimes are represented in other formats.
+	pub toml_datetimes: TomlDatetimes,
+
+	/// How structs are written as MessagePack.
+	pub msgpack_structs: MsgpackStructs,
+
+	/// Receives human-readable [`Diagnostics`] about the input, if there are any.
+	pub reporter: Option<Reporter>,
+
+	/// Read and write JSON and YAML in a lossless annotated shape that names each value's kind,
+	/// like `{"u8": 5}` or `{"bytes": "c0ffee"}`.
+	///
+	/// This applies to whichever of input and output is JSON or YAML.
+	pub annotated: bool,
+
+	/// Describes the input's types, for formats that aren't self-describing.
+	///
+	/// Required to read [`In::Bincode`].
+	pub schema: Option<Schema>,
+
+	/// Read the output back and fail with [`Error::RoundTrip`] instead of writing it
+	/// if the document changed on the way.
+	pub check_round_trip: bool,
+
+	/// How [`merge`] combines sequences.
+	pub merge: Merge,
+
+	/// What kind of patch [`make_patch`] writes.
+	pub patch_format: PatchFormat,
+
+	/// How many documents to read from the input.
+	pub documents: Documents,
+
+	/// What [`split`] names each part by, instead of its index or key.
+	pub split_by: Option<KeyPath>,
+
+	/// How [`join`] assembles documents.
+	pub join: Join,
+}
+
+impl Options {
+	fn needs_object(&self) -> bool {
+		!self.transforms.is_empty()
+			|| self.annotated
+			|| self.check_round_trip
+			|| self.reads_documents()
+	}
+
+	fn reads_documents(&self) -> bool {
+		self.documents != Documents::Single
+	}
+}
+
+/// How [`diff`] writes differences.
+#[derive(Debug, Display, EnumString, EnumVariantNames, Clone, Copy, PartialEq, Eq)]
+pub enum DiffFormat {
+	/// One line per difference, like ``changed from 1 to 2 (at `a.b`)``.
+	#[strum(serialize = "text")]
+	Text,
+
+	/// An RFC 6902 JSON Patch that turns the first document into the second.
+	///
+	/// Differences it can't express, like the order of map entries, are left out.
+	#[strum(serialize = "json-patch")]
+	JsonPatch,
+}
+
+impl Default for DiffFormat {
+	fn default() -> Self {
+		Self::Text
+	}
+}
+
+/// What kind of patch [`make_patch`] writes.
+#[derive(Debug, Display, EnumString, EnumVariantNames, Clone, Copy, PartialEq, Eq)]
+pub enum PatchFormat {
+	/// An RFC 6902 JSON Patch, a list of operations.
+	///
+	/// The order of map entries isn't patched.
+	#[strum(serialize = "json-patch")]
+	JsonPatch,
+
+	/// An RFC 7386 JSON Merge Patch, a document with just the changed entries and null for removed ones.
+	///
+	/// Sequences are replaced as a whole, and values can't be set to null.
+	#[strum(serialize = "merge-patch")]
+	MergePatch,
+}
+
+impl Default for PatchFormat {
+	fn default() -> Self {
+		Self::JsonPatch
+	}
+}
+
+/// Reads a document in `in_format` from `reader` and writes it as `out_format` into `writer`,
+/// applying the transformations requested in `options` in between.
+///
+/// `writer` is flushed on success.
+///
+/// # Errors
+///
+/// Iff reading, deserializing, transforming, serializing or writing fails.
+/// See [`Error`] for details.
+pub fn transcode(
+	reader: impl Read,
+	in_format: In,
+	writer: impl Write,
+	out_format: Out,
+	options: &Options,
+) -> Result<(), Error> {
+	let can_stream = streaming::supports(in_format, out_format);
+	match options.strategy {
+		Strategy::Auto if can_stream && !options.needs_object() => {
+			return streaming::transcode(reader, in_format, writer, out_format, options);
+		}
+		Strategy::Streaming if !can_stream => {
+			return Err(Error::Options(format!(
+				"Can't stream from {} to {}.",
+				in_format, out_format
+			)));
+		}
+		Strategy::Streaming if options.reads_documents() => {
+			return Err(Error::Options(
+				"Can't stream multiple documents.".to_string(),
+			));
+		}
+		Strategy::Streaming if options.needs_object() => {
+			return Err(Error::Options(
+				"Can't stream while transforming or checking the document.".to_string(),
+			));
+		}
+		Strategy::Streaming => {
+			return streaming::transcode(reader, in_format, writer, out_format, options);
+		}
+		Strategy::Auto | Strategy::Object => (),
+	}
+
+	check_annotated(in_format, out_format, options)?;
+	let toml_datetimes = toml_datetimes(out_format, options);
+	if options.reads_documents() {
+		return transcode_documents(
+			reader,
+			in_format,
+			writer,
+			out_format,
+			toml_datetimes,
+			options,
+		);
+	}
+	let object = read_document(reader, in_format, toml_datetimes, options)?;
+	write_document(object, writer, out_format, toml_datetimes, options)
+}
+
+fn transcode_documents(
+	reader: impl Read,
+	in_format: In,
+	mut writer: impl Write,
+	out_format: Out,
+	toml_datetimes: TomlDatetimes,
+	options: &Options,
+) -> Result<(), Error> {
+	let documents = read_transformed_documents(reader, in_format, toml_datetimes, options)?;
+	if let Documents::Slurp = options.documents {
+		return write_document(
+			Object::Seq(documents),
+			writer,
+			out_format,
+			toml_datetimes,
+			options,
+		);
+	}
+
+	if !matches!(out_format, Out::Cbor | Out::Json | Out::Yaml) && documents.len() != 1 {
+		return Err(Error::Options(format!(
+			"Can't write {} documents as {}, which holds only one. Slurp them into a sequence instead.",
+			documents.len(),
+			out_format
+		)));
+	}
+	for document in documents {
+		write_document(document, &mut writer, out_format, toml_datetimes, options)?;
+		// JSON Lines. YAML documents start with `---` and CBOR items need no separator.
+		if let Out::Json = out_format {
+			writer.write_all(b"\n").map_err(Error::Output)?;
+		}
+	}
+	writer.flush().map_err(Error::Output)
+}
+
+/// Reads each of `inputs`, merges them into the first one in order and writes the result as `out_format` into `writer`,
+/// applying the transformations requested in `options` to the merged document.
+///
+/// Maps and structs are merged by key, sequences as [`Options::merge`] says and other values are replaced by later ones.
+/// Null values replace anything and can be replaced with anything, but otherwise the kinds of values must match.
+///
+/// `writer` is flushed on success.
+///
+/// # Errors
+///
+/// Iff reading, deserializing, merging, transforming, serializing or writing fails.
+/// See [`Error`] for details.
+pub fn merge<R: Read>(
+	inputs: impl IntoIterator<Item = (R, In)>,
+	writer: impl Write,
+	out_format: Out,
+	options: &Options,
+) -> Result<(), Error> {
+	if let Strategy::Streaming = options.strategy {
+		return Err(Error::Options(
+			"Can't stream while merging documents.".to_string(),
+		));
+	}
+	if options.reads_documents() {
+		return Err(Error::Options(
+			"Can't read multiple documents per input while merging.".to_string(),
+		));
+	}
+
+	let toml_datetimes = toml_datetimes(out_format, options);
+	let mut merged: Option<Object<'static>> = None;
+	for (index, (reader, in_format)) in inputs.into_iter().enumerate() {
+		check_annotated(in_format, out_format, options)?;
+		let object = read_input(reader, in_format, toml_datetimes, options)?;
+		match &mut merged {
+			None => merged = Some(object),
+			Some(merged) => {
+				merge::merge(merged, object, options.merge, &mut vec![]).map_err(|error| {
+					Error::Merge {
+						input: index + 1,
+						source: Box::new(error),
+					}
+				})?;
+			}
+		}
+	}
+	let mut object =
+		merged.ok_or_else(|| Error::Options("There are no documents to merge.".to_string()))?;
+
+	for transform in &options.transforms {
+		transform.apply(&mut object)?;
+	}
+	write_document(object, writer, out_format, toml_datetimes, options)
+}
+
+/// Transforms the document in `reader` and splits it into parts, each written as `out_format`.
+///
+/// Sequences are split into their elements, named by zero-padded index,
+/// maps and structs into their values, named by key.
+/// With [`Options::split_by`], each part is named by the value at that path in it instead.
+/// With [`Documents::Slurp`], the sequence of documents is split.
+///
+/// Returns the names and serialized parts, in order.
+/// Names are unique and safe to use as file names.
+///
+/// # Errors
+///
+/// Iff reading, transforming or writing fails,
+/// the document is not a sequence, map or struct,
+/// or the parts can't be named.
+pub fn split(
+	reader: impl Read,
+	in_format: In,
+	out_format: Out,
+	options: &Options,
+) -> Result<Vec<(String, Vec<u8>)>, Error> {
+	if let Strategy::Streaming = options.strategy {
+		return Err(Error::Options(
+			"Can't stream while splitting documents.".to_string(),
+		));
+	}
+
+	check_annotated(in_format, out_format, options)?;
+	let toml_datetimes = toml_datetimes(out_format, options);
+	let object = match options.documents {
+		Documents::Single => read_document(reader, in_format, toml_datetimes, options)?,
+		Documents::Each => {
+			return Err(Error::Options(
+				"Can't split multiple documents. Slurp them into a sequence instead.".to_string(),
+			))
+		}
+		Documents::Slurp => Object::Seq(read_transformed_documents(
+			reader,
+			in_format,
+			toml_datetimes,
+			options,
+		)?),
+	};
+
+	split::split(object, options.split_by.as_ref())
+		.map_err(|message| Error::Transform {
+			transform: "split",
+			source: message.into(),
+		})?
+		.into_iter()
+		.map(|(name, part)| {
+			let mut output = vec![];
+			write_document(part, &mut output, out_format, toml_datetimes, options)?;
+			Ok((name, output))
+		})
+		.collect()
+}
+
+/// Reads named documents, each in its own format, and assembles them according to [`Options::join`].
+/// The transformations are applied to the result.
+///
+/// # Errors
+///
+/// Iff reading, transforming or writing fails, or, when joining into a map, several documents have the same name.
+pub fn join<R: Read>(
+	inputs: impl IntoIterator<Item = (String, R, In)>,
+	writer: impl Write,
+	out_format: Out,
+	options: &Options,
+) -> Result<(), Error> {
+	if let Strategy::Streaming = options.strategy {
+		return Err(Error::Options(
+			"Can't stream while joining documents.".to_string(),
+		));
+	}
+	if options.reads_documents() {
+		return Err(Error::Options(
+			"Can't read multiple documents per input while joining.".to_string(),
+		));
+	}
+
+	let toml_datetimes = toml_datetimes(out_format, options);
+	let mut entries: Vec<(Object<'static>, Object<'static>)> = vec![];
+	for (name, reader, in_format) in inputs {
+		check_annotated(in_format, out_format, options)?;
+		let object = read_input(reader, in_format, toml_datetimes, options)?;
+		if let Join::Map = options.join {
+			if entries
+				.iter()
+				.any(|(key, _)| path::key_str(key).as_deref() == Some(&name))
+			{
+				return Err(Error::Options(format!(
+					"Several documents are named `{}`.",
+					name
+				)));
+			}
+		}
+		entries.push((Object::String(Cow::Owned(name)), object));
+	}
+	let mut object = match options.join {
+		Join::Sequence => Object::Seq(entries.into_iter().map(|(_, object)| object).collect()),
+		Join::Map => Object::Map(entries),
+	};
+
+	for transform in &options.transforms {
+		transform.apply(&mut object)?;
+	}
+	write_document(object, writer, out_format, toml_datetimes, options)
+}
+
+fn check_annotated(in_format: In, out_format: Out, options: &Options) -> Result<(), Error> {
+	if options.annotated
+		&& !matches!(in_format, In::Json | In::Yaml)
+		&& !matches!(out_format, Out::Json | Out::Yaml)
+	{
+		return Err(Error::Options(
+			"Annotations require JSON or YAML input or output.".to_string(),
+		));
+	}
+	Ok(())
+}
+
+fn toml_datetimes(out_format: Out, options: &Options) -> TomlDatetimes {
+	match out_format {
+		// Tagged datetimes are restored on output.
+		Out::Toml => TomlDatetimes::Tagged,
+		_ => options.toml_datetimes,
+	}
+}
+
+fn write_document(
+	mut object: Object<'static>,
+	mut writer: impl Write,
+	out_format: Out,
+	toml_datetimes: TomlDatetimes,
+	options: &Options,
+) -> Result<(), Error> {
+	let annotated_out = options.annotated && matches!(out_format, Out::Json | Out::Yaml);
+
+	if let Out::Msgpack = out_format {
+		msgpack::untag_ext(&mut object);
+	}
+
+	if annotated_out {
+		object = annotated::annotate(object);
+	}
+
+	if !options.check_round_trip {
+		write_object(&object, &mut writer, out_format, options)?;
+		return writer.flush().map_err(Error::Output);
+	}
+
+	let mut output = vec![];
+	write_object(&object, &mut output, out_format, options)?;
+
+	// Undo the output-specific steps above, so both sides are compared as they'd be read.
+	if annotated_out {
+		object = annotated::deannotate(object).map_err(error::de(out_format.into()))?;
+	}
+	if let Out::Msgpack = out_format {
+		msgpack::tag_ext(&mut object);
+	}
+
+	let mut round_tripped = read_object(&*output, out_format.into(), toml_datetimes, options)?;
+	if annotated_out {
+		round_tripped =
+			annotated::deannotate(round_tripped).map_err(error::de(out_format.into()))?;
+	}
+
+	let differences: Vec<_> = compare::compare(&object, &round_tripped)
+		.iter()
+		.map(compare::Change::difference)
+		.collect();
+	if !differences.is_empty() {
+		return Err(Error::RoundTrip {
+			format: out_format,
+			differences,
+		});
+	}
+
+	writer.write_all(&output).map_err(Error::Output)?;
+	writer.flush().map_err(Error::Output)
+}
+
+/// Reads the documents `a` and `b`, applies `options.transforms` to each
+/// and writes how `b` differs from `a` into `writer` as `diff_format`.
+///
+/// Returns whether the documents differ. `writer` is flushed on success.
+///
+/// Documents are compared the way most formats see them:
+/// Options and newtype structs are transparent, integers of different widths are equal if their values are,
+/// structs are maps and `char`s are strings.
+/// Apply [`Transform::SortKeys`] to ignore the order of map entries.
+///
+/// # Errors
+///
+/// Iff reading, deserializing, transforming or writing fails.
+/// See [`Error`] for details.
+pub fn diff(
+	a: impl Read,
+	a_format: In,
+	b: impl Read,
+	b_format: In,
+	mut writer: impl Write,
+	diff_format: DiffFormat,
+	options: &Options,
+) -> Result<bool, Error> {
+	let a = read_document(a, a_format, options.toml_datetimes, options)?;
+	let b = read_document(b, b_format, options.toml_datetimes, options)?;
+	let changes = compare::compare(&a, &b);
+
+	match diff_format {
+		DiffFormat::Text => {
+			for change in &changes {
+				writeln!(writer, "{}", change.difference()).map_err(Error::Output)?;
+			}
+		}
+		DiffFormat::JsonPatch => {
+			write_json(&compare::json_patch(&changes), &mut writer, options.pretty)?;
+		}
+	}
+	writer.flush().map_err(Error::Output)?;
+	Ok(!changes.is_empty())
+}
+
+/// Reads the documents `from` and `to`, applies `options.transforms` to each
+/// and writes a patch that turns `from` into `to` as `out_format` into `writer`.
+///
+/// The kind of patch is chosen by [`Options::patch_format`].
+/// Documents are compared like by [`diff`], so a patch can be written in any format
+/// but only turns `from` into something equal to `to` in that sense.
+///
+/// `writer` is flushed on success.
+///
+/// # Errors
+///
+/// Iff reading, deserializing, transforming, serializing or writing fails,
+/// or if `to` has a null value in a map and a [`PatchFormat::MergePatch`] is requested.
+/// See [`Error`] for details.
+pub fn make_patch(
+	from: impl Read,
+	from_format: In,
+	to: impl Read,
+	to_format: In,
+	writer: impl Write,
+	out_format: Out,
+	options: &Options,
+) -> Result<(), Error> {
+	check_annotated(from_format, out_format, options)?;
+	check_annotated(to_format, out_format, options)?;
+	let toml_datetimes = toml_datetimes(out_format, options);
+	let from = read_document(from, from_format, toml_datetimes, options)?;
+	let to = read_document(to, to_format, toml_datetimes, options)?;
+
+	let patch = match options.patch_format {
+		PatchFormat::JsonPatch => Object::Seq(
+			compare::json_patch(&compare::compare(&from, &to))
+				.iter()
+				.map(compare::Operation::to_object)
+				.collect(),
+		),
+		PatchFormat::MergePatch => compare::merge_patch(&from, &to, &mut vec![])
+			.map_err(|error| Error::Options(format!("{}. Use a JSON Patch instead.", error)))?
+			.unwrap_or_else(|| Object::Map(vec![])),
+	};
+	write_document(patch, writer, out_format, toml_datetimes, options)
+}
+
+/// Reads a document and applies `options.transforms` to it.
+fn read_document(
+	reader: impl Read,
+	in_format: In,
+	toml_datetimes: TomlDatetimes,
+	options: &Options,
+) -> Result<Object<'static>, Error> {
+	let mut object = read_input(reader, in_format, toml_datetimes, options)?;
+	for transform in &options.transforms {
+		transform.apply(&mut object)?;
+	}
+	Ok(object)
+}
+
+fn read_input(
+	reader: impl Read,
+	in_format: In,
+	toml_datetimes: TomlDatetimes,
+	options: &Options,
+) -> Result<Object<'static>, Error> {
+	let object = read_object(reader, in_format, toml_datetimes, options)?;
+	deannotate_input(object, in_format, options)
+}
+
+fn read_transformed_documents(
+	reader: impl Read,
+	in_format: In,
+	toml_datetimes: TomlDatetimes,
+	options: &Options,
+) -> Result<Vec<Object<'static>>, Error> {
+	let mut documents = read_documents(reader, in_format, toml_datetimes, options)?;
+	for document in &mut documents {
+		for transform in &options.transforms {
+			transform.apply(document)?;
+		}
+	}
+	Ok(documents)
+}
+
+/// Reads every document in a YAML stream, concatenated JSON values or a CBOR sequence,
+/// or else the single document in `reader`.
+fn read_documents(
+	reader: impl Read,
+	in_format: In,
+	toml_datetimes: TomlDatetimes,
+	options: &Options,
+) -> Result<Vec<Object<'static>>, Error> {
+	let documents: Vec<_> = match in_format {
+		In::Cbor => {
+			let mut reader = BufReader::new(reader);
+			let mut documents = vec![];
+			while !reader.fill_buf().map_err(Error::Input)?.is_empty() {
+				documents.push(read_object(
+					&mut reader,
+					in_format,
+					toml_datetimes,
+					options,
+				)?);
+			}
+			documents
+		}
+
+		In::Json => {
+			let text = read_to_string(reader)?;
+			serde_json::Deserializer::from_str(&text)
+				.into_iter::<Detach<Object>>()
+				.map(|document| document.map(detach).map_err(error::de(in_format)))
+				.collect::<Result<_, _>>()?
+		}
+
+		In::Yaml => {
+			let text = read_to_string(reader)?;
+			serde_yaml::Deserializer::from_str(&text)
+				.map(|document| {
+					Detach::<Object>::deserialize(document)
+						.map(detach)
+						.map_err(error::de(in_format))
+				})
+				.collect::<Result<_, _>>()?
+		}
+
+		_ => vec![read_object(reader, in_format, toml_datetimes, options)?],
+	};
+	documents
+		.into_iter()
+		.map(|document| deannotate_input(document, in_format, options))
+		.collect()
+}
+
+fn deannotate_input(
+	object: Object<'static>,
+	in_format: In,
+	options: &Options,
+) -> Result<Object<'static>, Error> {
+	if options.annotated && matches!(in_format, In::Json | In::Yaml) {
+		annotated::deannotate(object).map_err(error::de(in_format))
+	} else {
+		Ok(object)
+	}
+}
+
+fn read_object(
+	mut reader: impl Read,
+	in_format: In,
+	toml_datetimes: TomlDatetimes,
+	options: &Options,
+) -> Result<Object<'static>, Error> {
+	match in_format {
+		In::Bencode => {
+			let mut data = vec![];
+			reader.read_to_end(&mut data).map_err(Error::Input)?;
+			serde_bencode::from_bytes(&data)
+				.map(detach)
+				.map_err(error::de(in_format))
+		}
+
+		In::Bincode => {
+			let schema = options
+				.schema
+				.as_ref()
+				.ok_or_else(|| Error::Options("Reading Bincode requires a schema.".to_string()))?;
+			let mut data = vec![];
+			reader.read_to_end(&mut data).map_err(Error::Input)?;
+			// Matches `bincode::serialize`, which is used for output.
+			bincode::DefaultOptions::new()
+				.with_fixint_encoding()
+				.deserialize_seed(schema.seed(), &data)
+				.map_err(error::de(in_format))
+		}
+
+		In::Cbor => ciborium::de::from_reader(reader).map_err(|error| match error {
+			ciborium::de::Error::Io(error) => Error::Input(error),
+			error => error::de(in_format)(error),
+		}),
+
+		In::Json => {
+			let text = read_to_string(reader)?;
+			serde_json::from_str(&text)
+				.map(detach)
+				.map_err(error::de(in_format))
+		}
+
+		In::Msgpack => {
+			let mut data = vec![];
+			reader.read_to_end(&mut data).map_err(Error::Input)?;
+			let mut object = rmp_serde::from_slice(&data)
+				.map(detach)
+				.map_err(error::de(in_format))?;
+			msgpack::tag_ext(&mut object);
+			Ok(object)
+		}
+
+		In::Taml => {
+			let text = read_to_string(reader)?;
+			let mut diagnostics = vec![];
+			let result = serde_taml::de::from_taml_str(&text, &mut diagnostics, &[]).map(detach);
+
+			let diagnostics = Diagnostics::new(text, diagnostics);
+			if let Some(reporter) = options
+				.reporter
+				.as_ref()
+				.filter(|_| !diagnostics.is_empty())
+			{
+				(reporter.0)(&diagnostics);
+			}
+
+			result.map_err(|error| {
+				if diagnostics.error_count() > 0 {
+					error::de(in_format)(diagnostics)
+				} else {
+					error::de(in_format)(error)
+				}
+			})
+		}
+
+		In::Toml => {
+			let text = read_to_string(reader)?;
+			toml::from_str(&text)
+				.map(|value| toml_format::to_object(value, toml_datetimes))
+				.map_err(error::de(in_format))
+		}
+
+		In::Urlencoded => serde_urlencoded::from_reader(reader)
+			.map(detach)
+			.map_err(error::de(in_format)),
+
+		In::Xml => {
+			let text = read_to_string(reader)?;
+			quick_xml::de::from_str(&text)
+				.map(detach)
+				.map_err(error::de(in_format))
+		}
+
+		In::Yaml => {
+			let text = read_to_string(reader)?;
+			serde_yaml::from_str(&text)
+				.map(detach)
+				.map_err(error::de(in_format))
+		}
+	}
+}
+
+fn read_to_string(mut reader: impl Read) -> Result<String, Error> {
+	let mut text = String::new();
+	reader.read_to_string(&mut text).map_err(Error::Input)?;
+	Ok(text)
+}
+
+fn write_object(
+	object: &Object,
+	mut writer: impl Write,
+	out_format: Out,
+	options: &Options,
+) -> Result<(), Error> {
+	match out_format {
+		Out::Bencode => {
+			let data = serde_bencode::to_bytes(object).map_err(error::ser(out_format))?;
+			writer.write_all(&data).map_err(Error::Output)
+		}
+
+		Out::Bincode => bincode::serialize_into(writer, object).map_err(|error| match *error {
+			bincode::ErrorKind::Io(error) => Error::Output(error),
+			_ => error::ser(out_format)(error),
+		}),
+
+		Out::Cbor => ciborium::ser::into_writer(object, writer).map_err(|error| match error {
+			ciborium::ser::Error::Io(error) => Error::Output(error),
+			error @ ciborium::ser::Error::Value(_) => error::ser(out_format)(error),
+		}),
+
+		Out::Json => write_json(object, writer, options.pretty),
+
+		Out::Msgpack => {
+			let mut serializer = rmp_serde::Serializer::new(writer);
+			match options.msgpack_structs {
+				MsgpackStructs::Named => object.serialize(&mut serializer.with_struct_map()),
+				MsgpackStructs::Compact => object.serialize(&mut serializer),
+			}
+			.map_err(|error| match error {
+				rmp_serde::encode::Error::InvalidValueWrite(
+					rmp::encode::ValueWriteError::InvalidMarkerWrite(error)
+					| rmp::encode::ValueWriteError::InvalidDataWrite(error),
+				) => Error::Output(error),
+				error => error::ser(out_format)(error),
+			})
+		}
+
+		Out::Taml => {
+			let text =
+				taml_format::to_string(object, options.pretty).map_err(error::ser(out_format))?;
+			writer.write_all(text.as_bytes()).map_err(Error::Output)
+		}
+
+		Out::Toml => {
+			let value = toml_format::to_value(object).map_err(error::ser(out_format))?;
+			let text = if options.pretty {
+				toml::to_string_pretty(&value)
+			} else {
+				toml::to_string(&value)
+			}
+			.map_err(error::ser(out_format))?;
+			writer.write_all(text.as_bytes()).map_err(Error::Output)
+		}
+
+		Out::Urlencoded => {
+			let text = serde_urlencoded::to_string(object).map_err(error::ser(out_format))?;
+			writer.write_all(text.as_bytes()).map_err(Error::Output)
+		}
+
+		Out::Xml => quick_xml::se::to_writer(writer, object).map_err(error::ser(out_format)),
+
+		Out::Yaml => serde_yaml::to_writer(writer, object).map_err(error::ser(out_format)),
+	}
+}
+
+fn write_json(value: &impl Serialize, writer: impl Write, pretty: bool) -> Result<(), Error> {
+	if pretty {
+		serde_json::to_writer_pretty(writer, value)
+	} else {
+		serde_json::to_writer(writer, value)
+	}
+	.map_err(|error| {
+		if error.is_io() {
+			Error::Output(error.into())
+		} else {
+			error::ser(Out::Json)(error)
+		}
+	})
+}
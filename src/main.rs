@@ -1,502 +1,838 @@
-#![doc(html_root_url = "https://docs.rs/reserde/0.0.4")]
 #![warn(clippy::pedantic)]
 
-use serde_detach::detach;
-use serde_object::Object;
+use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
+use reserde::{
+	convert_tree, diff, join, make_patch, merge, split, transcode, Diagnostics, DiffFormat,
+	Documents, Encoding, Error, Filter, In, Join, KeyPath, KeyPathError, Merge, MsgpackStructs,
+	Options, Out, Patch, PatchFormat, Reporter, Schema, Strategy, TomlDatetimes, Transform,
+};
 use std::{
-	borrow::Cow,
-	fs::File,
-	io::{stdin, stdout, Read as _, Write as _},
-	path::PathBuf,
+	ffi::OsString,
+	fs::{self, File},
+	io::{self, stdin, stdout, BufWriter, Cursor, Read, Write},
+	path::{Path, PathBuf},
+	process::exit,
+	str::FromStr,
+	sync::{Arc, Mutex},
+};
+use structopt::{
+	clap::{AppSettings, ArgMatches},
+	StructOpt,
 };
-use structopt::StructOpt;
-use strum::{EnumString, EnumVariantNames, VariantNames};
-use tap::Pipe as _;
+use strum::VariantNames;
 
 #[derive(Debug, StructOpt)]
-#[structopt(name = "reserde")]
+#[structopt(name = "reserde", setting = AppSettings::SubcommandsNegateReqs)]
 /// Transcode a self-describing format into a different format.
 ///
-/// Currently supports Bencode, Bincode (--out only), CBOR, JSON (--pretty), TAML (--in only), XML, x-www-form-urlencoded (as urlencoded) and YAML.
+/// Currently supports Bencode, Bincode (--in requires --schema), CBOR, JSON (--pretty), MessagePack (as msgpack), TAML (--pretty), TOML (--pretty), XML, x-www-form-urlencoded (as urlencoded) and YAML.
 /// All names are lowercase.
+#[structopt(after_help = EXIT_CODES)]
+#[allow(clippy::struct_excessive_bools)]
 struct Args {
-	#[structopt(long = "if")]
-	/// where to read input from. Defaults to stdin
-	in_file: Option<PathBuf>,
+	#[structopt(long = "if", number_of_values = 1)]
+	/// where to read input from. Defaults to stdin. Give this more than once to merge several documents, see --merge
+	in_file: Vec<PathBuf>,
 
 	#[structopt(long = "of")]
 	/// where to write output to. Defaults to stdout
 	out_file: Option<PathBuf>,
 
-	#[structopt(short = "i", long = "in", possible_values = In::VARIANTS)]
-	/// what to read
-	in_format: In,
+	#[structopt(short = "i", long = "in", number_of_values = 1, possible_values = In::VARIANTS)]
+	/// what to read. Detected from the --if extension or, failing that, the content if omitted. With several --if, applies to the next one
+	in_format: Vec<In>,
 
-	#[structopt(short = "o", long = "out", possible_values = Out::VARIANTS)]
-	/// what to write
-	out_format: Out,
+	#[structopt(short = "o", long = "out", possible_values = Out::VARIANTS, required_unless = "out-file")]
+	/// what to write. Chosen by the --of extension if omitted
+	out_format: Option<Out>,
 
 	#[structopt(short = "p")]
 	/// pretty-print (where supported)
 	pretty: bool,
 
+	#[structopt(long = "merge", default_value = "replace", possible_values = Merge::VARIANTS)]
+	/// how to merge several --if documents, in order. Maps and structs are merged by key and other values are replaced, except that sequences are appended to with `append`. Values of different kinds can't be merged, except with null
+	merge: Merge,
+
+	#[structopt(long = "split", requires = "out-dir", conflicts_with = "out-file")]
+	/// write each element of a sequence, or each value of a map or struct, to its own file in --out-dir, named by index or key (see --split-by) with the --out format as extension
+	split: bool,
+
+	#[structopt(long = "split-by", requires = "split")]
+	/// name each --split file by the value at this path in it, like `metadata.name` or `/metadata/name`
+	split_by: Option<KeyPath>,
+
+	#[structopt(long = "out-dir")]
+	/// where to write --split files to. Created if missing
+	out_dir: Option<PathBuf>,
+
+	#[structopt(long = "join", conflicts_with_all = &["in-file", "split"])]
+	/// read each file in this directory, in order of name, and join them as --join-into. Hidden files and subdirectories are skipped
+	join: Option<PathBuf>,
+
+	#[structopt(long = "join-into", default_value = "sequence", possible_values = Join::VARIANTS)]
+	/// `sequence` joins the --join files into a sequence, `map` into a map keyed by file name without extension
+	join_into: Join,
+
+	#[structopt(long = "multi-document")]
+	/// read every document of a YAML stream, JSON Lines (or other concatenated JSON values) or CBOR sequence and transform each. Written as a YAML stream, JSON Lines or CBOR sequence
+	multi_document: bool,
+
+	#[structopt(long = "slurp", conflicts_with = "multi-document")]
+	/// like --multi-document, but collect the transformed documents into one sequence
+	slurp: bool,
+
+	#[structopt(flatten)]
+	transforms: TransformArgs,
+
+	#[structopt(long = "strategy", default_value = "auto", possible_values = Strategy::VARIANTS)]
+	/// whether to stream values directly from input to output or go through an in-memory document. `auto` streams where possible
+	strategy: Strategy,
+
+	#[structopt(long = "msgpack-structs", default_value = "named", possible_values = MsgpackStructs::VARIANTS)]
+	/// whether to write structs as maps with field names or as arrays of field values. [try with: --out msgpack]
+	msgpack_structs: MsgpackStructs,
+
+	#[structopt(long = "toml-datetimes", default_value = "string", possible_values = TomlDatetimes::VARIANTS)]
+	/// how to represent TOML datetimes in other formats. `tagged` writes `{"$datetime": "..."}`, which is turned back into a datetime by --out toml
+	toml_datetimes: TomlDatetimes,
+
+	#[structopt(long = "annotated")]
+	/// read and write JSON and YAML in a lossless shape that names each value's kind, like `{"u8": 5}`, for exact round trips through other formats
+	annotated: bool,
+
+	#[structopt(long = "schema")]
+	/// a JSON file describing the input's types, for formats that aren't self-describing. See `reserde::Schema` or the README for its syntax. [try with: --in bincode]
+	schema: Option<PathBuf>,
+
+	#[structopt(long = "check-roundtrip")]
+	/// read the output back and compare it to the input. Lists the changes and exits with code 7 instead of writing the output if there are any
+	check_roundtrip: bool,
+
+	#[structopt(short = "v", long = "verbose")]
+	/// report decisions made automatically, like detected formats, on stderr
+	verbose: bool,
+
+	#[structopt(long = "warnings")]
+	/// also print warnings about the input, even if it can be read. (Errors are always printed.) [try with: --in taml]
+	warnings: bool,
+
+	#[structopt(subcommand)]
+	command: Option<Command>,
+}
+
+// Shared by conversions and `diff`.
+#[derive(Debug, StructOpt)]
+struct TransformArgs {
 	#[structopt(short = "s", possible_values = Encoding::VARIANTS)]
 	/// stringify bytes and non-string value keys into strings where possible. (Tries encodings in the order specified.) [try with: --in bencode]
 	stringify: Vec<Encoding>,
 
+	#[structopt(long = "bytes-from", number_of_values = 1)]
+	/// convert strings back into bytes, as `<encoding>:<path>` (like `hex:info.pieces`) for all strings at `<path>`, or as just `<encoding>` for strings prefixed like `hex:c0ffee`. `*` and `[*]` in paths match any key or index
+	bytes_from: Vec<BytesFrom>,
+
 	#[structopt(long = "enum-bools")]
-	/// case-insensitively convert unit variants with name `true` or `false` into booleans.
+	/// case-insensitively convert unit variants with name `true` or `false` into booleans. (Transformations are applied in the order specified.)
 	enum_bools: bool,
+
+	#[structopt(long = "select", number_of_values = 1)]
+	/// replace the document with the value at a JSON Pointer like `/info/name` or a path like `info.name`. Fails listing the available keys if there's none
+	select: Vec<KeyPath>,
+
+	#[structopt(long = "filter", number_of_values = 1)]
+	/// replace the document with the output of a jq-like filter, like `.info | {name, length}`. Supports paths, `.[]`, `|`, `,`, `//`, comparisons, arithmetic, array and object construction and `length`, `keys`, `map`, `select`, `has`, `type`, `not` and `empty`
+	filter: Vec<Filter>,
+
+	#[structopt(long = "patch", number_of_values = 1, parse(try_from_str = load_patch))]
+	/// apply an RFC 6902 JSON Patch or, unless the file contains an array, an RFC 7386 JSON Merge Patch from a JSON file. JSON Patches are all-or-nothing
+	patch: Vec<Patch>,
+
+	#[structopt(long = "sort-keys")]
+	/// sort the entries of maps and structs by key, numbers by value and everything else by text
+	sort_keys: bool,
 }
 
-#[derive(Debug, EnumString, EnumVariantNames, Clone, Copy)]
-enum In {
-	#[strum(serialize = "bencode")]
-	Bencode,
+#[derive(Debug, StructOpt)]
+enum Command {
+	/// Compare two documents, possibly in different formats, and print how the second differs from the first.
+	///
+	/// Options and newtype structs are transparent, integers of different widths are equal if their values are, and structs count as maps.
+	/// Exits with code 7 if the documents differ.
+	#[structopt(after_help = EXIT_CODES)]
+	Diff(DiffArgs),
+
+	/// Write a patch that turns one document into another, possibly in different formats.
+	///
+	/// Documents are compared like by `reserde diff`. The patch can be written in any output format.
+	#[structopt(after_help = EXIT_CODES)]
+	MakePatch(MakePatchArgs),
+
+	/// Convert every file in a directory tree, mirroring the tree in another directory.
+	///
+	/// Prints a summary of the converted and failed files and exits with the code of the first failure, if any.
+	#[structopt(after_help = EXIT_CODES)]
+	Convert(ConvertArgs),
+}
+
+#[derive(Debug, StructOpt)]
+struct DiffArgs {
+	#[structopt(long = "a")]
+	/// the first document
+	a: PathBuf,
+
+	#[structopt(long = "b")]
+	/// the second document
+	b: PathBuf,
+
+	#[structopt(long = "in-a", possible_values = In::VARIANTS)]
+	/// what the first document is. Detected from its extension or, failing that, its content if omitted
+	in_a: Option<In>,
 
-	#[strum(serialize = "cbor")]
-	Cbor,
+	#[structopt(long = "in-b", possible_values = In::VARIANTS)]
+	/// what the second document is. Detected from its extension or, failing that, its content if omitted
+	in_b: Option<In>,
 
-	#[strum(serialize = "json")]
-	Json,
+	#[structopt(long = "format", default_value = "text", possible_values = DiffFormat::VARIANTS)]
+	/// how to print the differences. `json-patch` writes an RFC 6902 JSON Patch from --a to --b, which can't express key order
+	format: DiffFormat,
 
-	#[strum(serialize = "taml")]
-	Taml,
+	#[structopt(short = "p")]
+	/// pretty-print --format json-patch
+	pretty: bool,
 
-	#[strum(serialize = "urlencoded")]
-	Urlencoded,
+	#[structopt(flatten)]
+	transforms: TransformArgs,
 
-	#[strum(serialize = "xml")]
-	Xml,
+	#[structopt(long = "schema")]
+	/// a JSON file describing Bincode documents' types
+	schema: Option<PathBuf>,
 
-	#[strum(serialize = "yaml")]
-	Yaml,
+	#[structopt(short = "v", long = "verbose")]
+	/// report detected formats on stderr
+	verbose: bool,
 }
 
-#[derive(Debug, EnumString, EnumVariantNames, Clone, Copy)]
-enum Out {
-	#[strum(serialize = "bencode")]
-	Bencode,
+#[derive(Debug, StructOpt)]
+struct MakePatchArgs {
+	#[structopt(long = "from")]
+	/// the original document
+	from: PathBuf,
 
-	#[strum(serialize = "bincode")]
-	Bincode,
+	#[structopt(long = "to")]
+	/// the changed document
+	to: PathBuf,
 
-	#[strum(serialize = "cbor")]
-	Cbor,
+	#[structopt(long = "in-from", possible_values = In::VARIANTS)]
+	/// what the original document is. Detected from its extension or, failing that, its content if omitted
+	in_from: Option<In>,
 
-	#[strum(serialize = "json")]
-	Json,
+	#[structopt(long = "in-to", possible_values = In::VARIANTS)]
+	/// what the changed document is. Detected from its extension or, failing that, its content if omitted
+	in_to: Option<In>,
 
-	#[strum(serialize = "urlencoded")]
-	Urlencoded,
+	#[structopt(long = "patch-format", default_value = "json-patch", possible_values = PatchFormat::VARIANTS)]
+	/// what kind of patch to write. A `json-patch` (RFC 6902) can't express key order, a `merge-patch` (RFC 7386) replaces sequences as a whole and can't set values to null
+	patch_format: PatchFormat,
 
-	#[strum(serialize = "xml")]
-	Xml,
+	#[structopt(long = "of")]
+	/// where to write the patch to. Defaults to stdout
+	out_file: Option<PathBuf>,
 
-	#[strum(serialize = "yaml")]
-	Yaml,
-}
+	#[structopt(short = "o", long = "out", possible_values = Out::VARIANTS, required_unless = "out-file")]
+	/// what to write the patch as. Chosen by the --of extension if omitted
+	out_format: Option<Out>,
+
+	#[structopt(short = "p")]
+	/// pretty-print (where supported)
+	pretty: bool,
+
+	#[structopt(flatten)]
+	transforms: TransformArgs,
 
-#[derive(Debug, EnumString, EnumVariantNames, Clone, Copy)]
-enum Encoding {
-	#[strum(serialize = "utf8")]
-	Utf8,
+	#[structopt(long = "schema")]
+	/// a JSON file describing Bincode documents' types
+	schema: Option<PathBuf>,
+
+	#[structopt(short = "v", long = "verbose")]
+	/// report decisions made automatically, like detected formats, on stderr
+	verbose: bool,
 }
 
-#[allow(clippy::too_many_lines)]
-fn main() {
-	let args: Args = StructOpt::from_args();
+#[derive(Debug, StructOpt)]
+struct ConvertArgs {
+	#[structopt(long = "recursive")]
+	/// the directory to convert, including subdirectories. Hidden files and directories are skipped
+	recursive: PathBuf,
 
-	//TODO: Avoid leaking.
+	#[structopt(long = "out-dir")]
+	/// where to write the converted files to, at the same relative paths but with the --out format as extension
+	out_dir: PathBuf,
 
-	let mut object: Object = match args.in_format {
-		In::Bencode => {
-			let mut data = vec![];
-			if let Some(path) = args.in_file {
-				File::open(path).unwrap().read_to_end(&mut data).unwrap();
-			} else {
-				stdin().read_to_end(&mut data).unwrap();
-			}
-			serde_bencode::from_bytes(&data).map(detach).unwrap()
-		}
+	#[structopt(short = "i", long = "in", possible_values = In::VARIANTS)]
+	/// what to read every file as. If omitted, each file is read as its extension says and files with other extensions are skipped, which counts as failure
+	in_format: Option<In>,
 
-		In::Cbor => args
-			.in_file
-			.map_or_else(
-				|| stdin().pipe(ciborium::de::from_reader),
-				|path| File::open(path).unwrap().pipe(ciborium::de::from_reader),
-			)
-			.unwrap(),
-
-		In::Json => {
-			let mut text = String::new();
-			if let Some(path) = args.in_file {
-				File::open(path).unwrap().read_to_string(&mut text).unwrap();
-			} else {
-				stdin().read_to_string(&mut text).unwrap();
-			}
-			serde_json::from_str(&text).map(detach).unwrap()
-		}
+	#[structopt(short = "o", long = "out", possible_values = Out::VARIANTS)]
+	/// what to write
+	out_format: Out,
 
-		In::Taml => {
-			let diagnostics = vec![];
-			let diagnostics = Box::new(diagnostics);
-			let diagnostics = Box::leak(diagnostics);
-			let mut text = String::new();
-			if let Some(path) = args.in_file {
-				File::open(path).unwrap().read_to_string(&mut text).unwrap();
-			} else {
-				stdin().read_to_string(&mut text).unwrap();
-			}
-			serde_taml::de::from_taml_str(&text, diagnostics, &[])
-				.map(detach)
-				.unwrap()
-		}
+	#[structopt(short = "p")]
+	/// pretty-print (where supported)
+	pretty: bool,
 
-		In::Urlencoded => args
-			.in_file
-			.map_or_else(
-				|| stdin().pipe(serde_urlencoded::from_reader).map(detach),
-				|path| {
-					File::open(path)
-						.unwrap()
-						.pipe(serde_urlencoded::from_reader)
-						.map(detach)
-				},
-			)
-			.unwrap(),
-
-		In::Xml => {
-			let mut text = String::new();
-			if let Some(path) = args.in_file {
-				File::open(path).unwrap().read_to_string(&mut text).unwrap();
-			} else {
-				stdin().read_to_string(&mut text).unwrap();
-			}
-			quick_xml::de::from_str(&text).map(detach).unwrap()
-		}
+	#[structopt(long = "fail-fast")]
+	/// stop at the first file that can't be converted
+	fail_fast: bool,
+
+	#[structopt(short = "j", long = "jobs", default_value = "1")]
+	/// how many files to convert in parallel, at least 1. The summary is in order of path either way
+	jobs: usize,
+
+	#[structopt(flatten)]
+	transforms: TransformArgs,
+
+	#[structopt(long = "msgpack-structs", default_value = "named", possible_values = MsgpackStructs::VARIANTS)]
+	/// whether to write structs as maps with field names or as arrays of field values. [try with: --out msgpack]
+	msgpack_structs: MsgpackStructs,
+
+	#[structopt(long = "toml-datetimes", default_value = "string", possible_values = TomlDatetimes::VARIANTS)]
+	/// how to represent TOML datetimes in other formats. `tagged` writes `{"$datetime": "..."}`, which is turned back into a datetime by --out toml
+	toml_datetimes: TomlDatetimes,
+
+	#[structopt(long = "schema")]
+	/// a JSON file describing Bincode documents' types
+	schema: Option<PathBuf>,
+}
+
+const EXIT_CODES: &str = "EXIT CODES:
+    0    success
+    1    invalid arguments
+    2    failed to read input
+    3    failed to write output
+    4    invalid input document
+    5    the output format can't represent the document
+    6    failed to apply a transformation
+    7    the documents differ (--check-roundtrip, diff)
+    8    the documents can't be merged (several --if)";
 
-		In::Yaml => {
-			let mut text = String::new();
-			if let Some(path) = args.in_file {
-				File::open(path).unwrap().read_to_string(&mut text).unwrap();
-			} else {
-				stdin().read_to_string(&mut text).unwrap();
+fn main() {
+	let matches = Args::clap().get_matches();
+	let mut args = Args::from_clap(&matches);
+
+	let result = match args.command.take() {
+		None => {
+			let transforms = transforms(&args.transforms, &matches);
+			in_formats(&args, &matches)
+				.and_then(|in_formats| run(args, transforms, in_formats))
+				.map(|()| false)
+		}
+		Some(Command::Diff(diff_args)) => {
+			let matches = matches
+				.subcommand_matches("diff")
+				.expect("parsed as `Command::Diff`");
+			let transforms = transforms(&diff_args.transforms, matches);
+			run_diff(&diff_args, transforms)
+		}
+		Some(Command::MakePatch(make_patch_args)) => {
+			let matches = matches
+				.subcommand_matches("make-patch")
+				.expect("parsed as `Command::MakePatch`");
+			let transforms = transforms(&make_patch_args.transforms, matches);
+			run_make_patch(make_patch_args, transforms).map(|()| false)
+		}
+		Some(Command::Convert(convert_args)) => {
+			let matches = matches
+				.subcommand_matches("convert")
+				.expect("parsed as `Command::Convert`");
+			let transforms = transforms(&convert_args.transforms, matches);
+			match run_convert(&convert_args, transforms) {
+				Ok(0) => Ok(false),
+				Ok(exit_code) => exit(exit_code),
+				Err(error) => Err(error),
 			}
-			serde_yaml::from_str(&text).map(detach).unwrap()
 		}
 	};
 
-	for encoding in args.stringify {
-		stringify(&mut object, encoding);
+	match result {
+		Ok(false) => (),
+		Ok(true) => exit(7),
+		Err(error) => {
+			eprintln!("reserde: {}", error);
+			exit(error.exit_code());
+		}
+	}
+}
+
+/// A `--bytes-from` argument.
+#[derive(Debug)]
+struct BytesFrom(Transform);
+
+impl FromStr for BytesFrom {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (encoding, path) = match s.split_once(':') {
+			Some((encoding, path)) => (
+				encoding,
+				Some(
+					path.parse()
+						.map_err(|error: KeyPathError| error.to_string())?,
+				),
+			),
+			None => (s, None),
+		};
+		let encoding = encoding.parse().map_err(|_| {
+			format!(
+				"Unknown encoding `{}`, expected one of: {}",
+				encoding,
+				Encoding::VARIANTS.join(", ")
+			)
+		})?;
+		Ok(Self(Transform::BytesFrom { encoding, path }))
 	}
+}
 
+/// Collects the transformations in the order they were given on the command line.
+fn transforms(args: &TransformArgs, matches: &ArgMatches) -> Vec<Transform> {
+	let mut transforms = vec![];
+	if let Some(indices) = matches.indices_of("stringify") {
+		transforms.extend(
+			indices
+				.zip(&args.stringify)
+				.map(|(index, &encoding)| (index, Transform::Stringify(encoding))),
+		);
+	}
+	if let Some(indices) = matches.indices_of("bytes-from") {
+		transforms.extend(
+			indices
+				.zip(&args.bytes_from)
+				.map(|(index, bytes_from)| (index, bytes_from.0.clone())),
+		);
+	}
+	if let Some(indices) = matches.indices_of("select") {
+		transforms.extend(
+			indices
+				.zip(&args.select)
+				.map(|(index, path)| (index, Transform::Select(path.clone()))),
+		);
+	}
+	if let Some(indices) = matches.indices_of("filter") {
+		transforms.extend(
+			indices
+				.zip(&args.filter)
+				.map(|(index, filter)| (index, Transform::Filter(filter.clone()))),
+		);
+	}
+	if let Some(indices) = matches.indices_of("patch") {
+		transforms.extend(
+			indices
+				.zip(&args.patch)
+				.map(|(index, patch)| (index, Transform::Patch(patch.clone()))),
+		);
+	}
 	if args.enum_bools {
-		convert_bool_variants(&mut object);
+		if let Some(index) = matches.index_of("enum-bools") {
+			transforms.push((index, Transform::EnumBools));
+		}
 	}
+	if args.sort_keys {
+		if let Some(index) = matches.index_of("sort-keys") {
+			transforms.push((index, Transform::SortKeys));
+		}
+	}
+	transforms.sort_by_key(|&(index, _)| index);
+	transforms
+		.into_iter()
+		.map(|(_, transform)| transform)
+		.collect()
+}
 
-	let pretty = args.pretty;
-	match args.out_format {
-		Out::Bencode => {
-			let data = serde_bencode::to_bytes(&object).unwrap();
+/// Pairs each --in with the --if after it, or with the only --if wherever it is.
+fn in_formats(args: &Args, matches: &ArgMatches) -> Result<Vec<Option<In>>, Error> {
+	let once = || Error::Options("--in can only be given once per --if.".to_string());
+	if args.in_file.len() <= 1 {
+		return match args.in_format[..] {
+			[] => Ok(vec![None]),
+			[in_format] => Ok(vec![Some(in_format)]),
+			_ => Err(once()),
+		};
+	}
 
-			if let Some(path) = args.out_file {
-				File::create(path).unwrap().write_all(&data).unwrap();
-			} else {
-				stdout().write_all(&data).unwrap();
+	let files: Vec<_> = matches
+		.indices_of("in-file")
+		.expect("given more than once")
+		.collect();
+	let mut in_formats = vec![None; files.len()];
+	if let Some(indices) = matches.indices_of("in-format") {
+		for (index, &in_format) in indices.zip(&args.in_format) {
+			let file = files.iter().position(|&file| file > index).ok_or_else(|| {
+				Error::Options("Each --in must come before the --if it applies to.".to_string())
+			})?;
+			if in_formats[file].replace(in_format).is_some() {
+				return Err(once());
 			}
 		}
+	}
+	Ok(in_formats)
+}
 
-		Out::Bincode => {
-			if let Some(path) = args.out_file {
-				bincode::serialize_into(File::create(path).unwrap(), &object).unwrap();
-			} else {
-				bincode::serialize_into(stdout(), &object).unwrap();
+fn run(args: Args, transforms: Vec<Transform>, in_formats: Vec<Option<In>>) -> Result<(), Error> {
+	// The input currently being read, for diagnostics.
+	let name = Arc::new(Mutex::new(String::new()));
+	let warnings = args.warnings;
+
+	let schema = args.schema.as_deref().map(load_schema).transpose()?;
+
+	let options = Options {
+		pretty: args.pretty,
+		transforms,
+		strategy: args.strategy,
+		toml_datetimes: args.toml_datetimes,
+		msgpack_structs: args.msgpack_structs,
+		reporter: Some(Reporter(Arc::new({
+			let name = Arc::clone(&name);
+			move |diagnostics: &Diagnostics| {
+				let mut stderr = StandardStream::stderr(if atty::is(atty::Stream::Stderr) {
+					ColorChoice::Auto
+				} else {
+					ColorChoice::Never
+				});
+				// There's nowhere else to report failure to write to stderr.
+				diagnostics
+					.emit(&name.lock().unwrap(), &mut stderr, warnings)
+					.ok();
 			}
-		}
+		}))),
+		annotated: args.annotated,
+		schema,
+		check_round_trip: args.check_roundtrip,
+		merge: args.merge,
+		documents: if args.slurp {
+			Documents::Slurp
+		} else if args.multi_document {
+			Documents::Each
+		} else {
+			Documents::Single
+		},
+		split_by: args.split_by,
+		join: args.join_into,
+		..Options::default()
+	};
 
-		Out::Cbor => args.out_file.map_or_else(
-			|| ciborium::ser::into_writer(&object, stdout()).unwrap(),
-			|path| ciborium::ser::into_writer(&object, File::create(path).unwrap()).unwrap(),
-		),
+	if let Some(dir) = &args.join {
+		let in_format = in_formats.into_iter().next().flatten();
+		let files = directory_files(dir)?;
+		let inputs = open_inputs(&files, vec![in_format; files.len()], args.verbose)?;
+		let out_format = out_format(args.out_format, args.out_file.as_deref(), args.verbose)?;
+		let inputs = files.iter().zip(inputs).map(|(path, input)| {
+			*name.lock().unwrap() = input.name;
+			let stem = path
+				.file_stem()
+				.expect("files have names")
+				.to_string_lossy()
+				.into_owned();
+			(stem, input.reader, input.format)
+		});
+		return write_output(args.out_file, |writer| {
+			join(inputs, writer, out_format, &options)
+		});
+	}
 
-		Out::Json => {
-			if let Some(path) = args.out_file {
-				let file = File::create(path).unwrap();
-				if pretty {
-					serde_json::to_writer_pretty(file, &object).unwrap();
-				} else {
-					serde_json::to_writer(file, &object).unwrap();
-				}
-			} else if pretty {
-				serde_json::to_writer_pretty(stdout(), &object).unwrap();
-			} else {
-				serde_json::to_writer(stdout(), &object).unwrap();
-			}
+	let mut inputs = open_inputs(&args.in_file, in_formats, args.verbose)?;
+
+	let out_format = out_format(args.out_format, args.out_file.as_deref(), args.verbose)?;
+	if args.split {
+		if inputs.len() != 1 {
+			return Err(Error::Options(
+				"--split takes only one document.".to_string(),
+			));
+		}
+		let input = inputs.remove(0);
+		*name.lock().unwrap() = input.name;
+		let parts = split(input.reader, input.format, out_format, &options)?;
+		let out_dir = args.out_dir.expect("required by --split");
+		fs::create_dir_all(&out_dir).map_err(Error::Output)?;
+		for (name, part) in parts {
+			fs::write(out_dir.join(format!("{}.{}", name, out_format)), part)
+				.map_err(Error::Output)?;
 		}
+		Ok(())
+	} else if inputs.len() == 1 {
+		let input = inputs.remove(0);
+		*name.lock().unwrap() = input.name;
+		let (reader, in_format) = (input.reader, input.format);
+		write_output(args.out_file, |writer| {
+			transcode(reader, in_format, writer, out_format, &options)
+		})
+	} else {
+		let inputs = inputs.into_iter().map(|input| {
+			*name.lock().unwrap() = input.name;
+			(input.reader, input.format)
+		});
+		write_output(args.out_file, |writer| {
+			merge(inputs, writer, out_format, &options)
+		})
+	}
+}
 
-		Out::Urlencoded => {
-			let text = serde_urlencoded::to_string(&object).unwrap();
+struct Input {
+	name: String,
+	reader: Box<dyn Read>,
+	format: In,
+}
 
-			if let Some(path) = args.out_file {
-				write!(File::create(path).unwrap(), "{}", text).unwrap();
-			} else {
-				print!("{}", text);
-			}
+/// Opens each of `in_files`, or stdin if there are none, and detects their formats where not given.
+fn open_inputs(
+	in_files: &[PathBuf],
+	in_formats: Vec<Option<In>>,
+	verbose: bool,
+) -> Result<Vec<Input>, Error> {
+	let in_files = if in_files.is_empty() {
+		vec![None]
+	} else {
+		in_files.iter().map(|path| Some(path.as_path())).collect()
+	};
+
+	let mut inputs = vec![];
+	for (in_file, in_format) in in_files.into_iter().zip(in_formats) {
+		let mut reader: Box<dyn Read> = match in_file {
+			Some(path) => Box::new(File::open(path).map_err(Error::Input)?),
+			None => Box::new(stdin()),
+		};
+		let in_format = match in_format {
+			Some(in_format) => in_format,
+			None => detect_in_format(in_file, &mut reader, verbose)?,
+		};
+		let name = in_file.map_or_else(|| "<stdin>".to_string(), |path| path.display().to_string());
+		inputs.push(Input {
+			name,
+			reader,
+			format: in_format,
+		});
+	}
+	Ok(inputs)
+}
+
+/// Lists the files in `dir` by name, skipping hidden files and subdirectories.
+fn directory_files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+	let mut files = vec![];
+	for entry in fs::read_dir(dir).map_err(Error::Input)? {
+		let entry = entry.map_err(Error::Input)?;
+		if !entry.file_type().map_err(Error::Input)?.is_dir()
+			&& !entry.file_name().to_string_lossy().starts_with('.')
+		{
+			files.push(entry.path());
 		}
+	}
+	files.sort();
+	Ok(files)
+}
 
-		Out::Xml => args.out_file.map_or_else(
-			|| quick_xml::se::to_writer(stdout(), &object).unwrap(),
-			|path| {
-				let file = File::create(path).unwrap();
-				quick_xml::se::to_writer(file, &object).unwrap();
-			},
-		),
-
-		Out::Yaml => args.out_file.map_or_else(
-			|| serde_yaml::to_writer(stdout(), &object).unwrap(),
-			|path| {
-				let file = File::create(path).unwrap();
-				serde_yaml::to_writer(file, &object).unwrap();
-			},
-		),
+/// Returns whether the documents differ.
+fn run_diff(args: &DiffArgs, transforms: Vec<Transform>) -> Result<bool, Error> {
+	let options = Options {
+		pretty: args.pretty,
+		transforms,
+		schema: args.schema.as_deref().map(load_schema).transpose()?,
+		..Options::default()
+	};
+
+	let mut a: Box<dyn Read> = Box::new(File::open(&args.a).map_err(Error::Input)?);
+	let a_format = match args.in_a {
+		Some(in_format) => in_format,
+		None => detect_in_format(Some(&args.a), &mut a, args.verbose)?,
+	};
+	let mut b: Box<dyn Read> = Box::new(File::open(&args.b).map_err(Error::Input)?);
+	let b_format = match args.in_b {
+		Some(in_format) => in_format,
+		None => detect_in_format(Some(&args.b), &mut b, args.verbose)?,
 	};
 
-	stdout().flush().unwrap();
+	diff(a, a_format, b, b_format, stdout(), args.format, &options)
 }
 
-// TODO: Simplify all this code by extracting a `recurse` function.
-
-fn convert_bool_variants(object: &mut Object) {
-	#[allow(clippy::match_same_arms)]
-	match object {
-		Object::Bool(_)
-		| Object::I8(_)
-		| Object::I16(_)
-		| Object::I32(_)
-		| Object::I64(_)
-		| Object::I128(_)
-		| Object::U8(_)
-		| Object::U16(_)
-		| Object::U32(_)
-		| Object::U64(_)
-		| Object::U128(_)
-		| Object::F32(_)
-		| Object::F64(_)
-		| Object::Char(_)
-		| Object::String(_)
-		| Object::ByteArray(_)
-		| Object::Option(_)
-		| Object::Unit
-		| Object::UnitStruct { .. }
-		| Object::DualVariantKey { .. } => (), // Do nothing.
-		Object::UnitVariant { name: _, variant } => {
-			convert_bool_variants(variant);
-			match variant.as_ref() {
-				Object::String(cow) if cow.to_ascii_lowercase() == "true" => {
-					*object = Object::Bool(true);
-				}
-				Object::String(cow) if cow.to_ascii_lowercase() == "false" => {
-					*object = Object::Bool(false);
-				}
-				Object::ByteArray(cow) if cow.to_ascii_lowercase() == b"true" => {
-					*object = Object::Bool(true);
+fn run_make_patch(args: MakePatchArgs, transforms: Vec<Transform>) -> Result<(), Error> {
+	let options = Options {
+		pretty: args.pretty,
+		transforms,
+		schema: args.schema.as_deref().map(load_schema).transpose()?,
+		patch_format: args.patch_format,
+		..Options::default()
+	};
+
+	let mut from: Box<dyn Read> = Box::new(File::open(&args.from).map_err(Error::Input)?);
+	let from_format = match args.in_from {
+		Some(in_format) => in_format,
+		None => detect_in_format(Some(&args.from), &mut from, args.verbose)?,
+	};
+	let mut to: Box<dyn Read> = Box::new(File::open(&args.to).map_err(Error::Input)?);
+	let to_format = match args.in_to {
+		Some(in_format) => in_format,
+		None => detect_in_format(Some(&args.to), &mut to, args.verbose)?,
+	};
+
+	let out_format = out_format(args.out_format, args.out_file.as_deref(), args.verbose)?;
+	write_output(args.out_file, |writer| {
+		make_patch(
+			from,
+			from_format,
+			to,
+			to_format,
+			writer,
+			out_format,
+			&options,
+		)
+	})
+}
+
+/// Prints a summary and returns the exit code of the first failure, or 0.
+fn run_convert(args: &ConvertArgs, transforms: Vec<Transform>) -> Result<i32, Error> {
+	let options = Options {
+		pretty: args.pretty,
+		transforms,
+		toml_datetimes: args.toml_datetimes,
+		msgpack_structs: args.msgpack_structs,
+		schema: args.schema.as_deref().map(load_schema).transpose()?,
+		..Options::default()
+	};
+
+	let conversions = convert_tree(
+		&args.recursive,
+		&args.out_dir,
+		args.in_format,
+		args.out_format,
+		args.fail_fast,
+		args.jobs,
+		&options,
+	)?;
+
+	let mut exit_code = 0;
+	let (mut failed, mut skipped) = (0, 0);
+	for conversion in &conversions {
+		match &conversion.result {
+			Ok(()) => println!(
+				"converted {} to {}",
+				conversion.source.display(),
+				conversion.target.display()
+			),
+			Err(error) => {
+				if conversion.in_format.is_some() {
+					println!("failed    {}: {}", conversion.source.display(), error);
+					failed += 1;
+				} else {
+					println!("skipped   {}: {}", conversion.source.display(), error);
+					skipped += 1;
 				}
-				Object::ByteArray(cow) if cow.to_ascii_lowercase() == b"false" => {
-					*object = Object::Bool(false);
+				if exit_code == 0 {
+					exit_code = error.exit_code();
 				}
-				_ => (), // Do nothing.
-			}
-		}
-		Object::NewtypeStruct { name: _, value } => convert_bool_variants(value),
-		Object::NewtypeVariant {
-			name: _,
-			variant,
-			value,
-		} => {
-			convert_bool_variants(variant);
-			convert_bool_variants(value);
-		}
-		Object::Seq(elements)
-		| Object::Tuple(elements)
-		| Object::TupleStruct {
-			name: _,
-			fields: elements,
-		} => convert_bool_variants_iter(elements.iter_mut()),
-		Object::TupleVariant {
-			name: _,
-			variant,
-			fields,
-		} => {
-			convert_bool_variants(variant);
-			convert_bool_variants(fields);
-		}
-		Object::Map(map) => {
-			for (k, v) in map.iter_mut() {
-				convert_bool_variants(k);
-				convert_bool_variants(v);
 			}
 		}
-		Object::Struct { name: _, fields } => {
-			convert_bool_variants_iter(fields.iter_mut().filter_map(|(_, v)| v.as_mut()));
-		}
-		Object::StructVariant {
-			name: _,
-			variant,
-			fields,
-		} => {
-			convert_bool_variants(variant);
-			convert_bool_variants(fields);
-		}
-		Object::FieldMap(map) => {
-			for (k, v) in map.iter_mut() {
-				convert_bool_variants(k);
-				if let Some(v) = v.as_mut() {
-					convert_bool_variants(v);
-				}
+	}
+	println!(
+		"{} converted, {} failed, {} skipped",
+		conversions.len() - failed - skipped,
+		failed,
+		skipped
+	);
+	Ok(exit_code)
+}
+
+/// Uses `out_format` or else chooses one by `out_file`'s extension.
+fn out_format(
+	out_format: Option<Out>,
+	out_file: Option<&Path>,
+	verbose: bool,
+) -> Result<Out, Error> {
+	match (out_format, out_file) {
+		(Some(out_format), _) => Ok(out_format),
+		(None, Some(out_file)) => {
+			let out_format = Out::from_extension(out_file)?;
+			if verbose {
+				eprintln!("reserde: chose output format {} from extension", out_format);
 			}
+			Ok(out_format)
 		}
+		(None, None) => unreachable!("required by the arguments"),
 	}
 }
 
-fn convert_bool_variants_iter<'a, 'b: 'a>(iter: impl IntoIterator<Item = &'a mut Object<'b>>) {
-	for item in iter {
-		convert_bool_variants(item);
+/// Calls `write` with `out_file` or else stdout.
+///
+/// Files are written to a temporary file next to them first, which only replaces them if `write` succeeds,
+/// so that failing to read the input doesn't clobber them, even while streaming.
+fn write_output(
+	out_file: Option<PathBuf>,
+	write: impl FnOnce(&mut dyn Write) -> Result<(), Error>,
+) -> Result<(), Error> {
+	let path = match out_file {
+		Some(path) => path,
+		None => return write(&mut stdout()),
+	};
+	let mut temp_name = OsString::from(".");
+	temp_name.push(path.file_name().unwrap_or_default());
+	temp_name.push(".partial");
+	let temp = path.with_file_name(temp_name);
+
+	let mut file = BufWriter::new(File::create(&temp).map_err(Error::Output)?);
+	let result = write(&mut file)
+		.and_then(|()| file.flush().map_err(Error::Output))
+		.and_then(|()| {
+			drop(file);
+			fs::rename(&temp, &path).map_err(Error::Output)
+		});
+	if result.is_err() {
+		// The error that got here is more relevant.
+		fs::remove_file(&temp).ok();
 	}
+	result
 }
 
-fn stringify(object: &mut Object, encoding: Encoding) {
-	#[allow(clippy::match_same_arms)]
-	match object {
-		Object::Bool(_)
-		| Object::I8(_)
-		| Object::I16(_)
-		| Object::I32(_)
-		| Object::I64(_)
-		| Object::I128(_)
-		| Object::U8(_)
-		| Object::U16(_)
-		| Object::U32(_)
-		| Object::U64(_)
-		| Object::U128(_)
-		| Object::F32(_)
-		| Object::F64(_)
-		| Object::Char(_)
-		| Object::String(_) => (), // Do nothing.
-
-		Object::DualVariantKey { .. } => (), // Do nothing. A well-behaved serializer will get the appropriate version.
-
-		Object::ByteArray(_) => stringify_value(object, encoding),
-		Object::Option(Some(b)) => stringify(b, encoding),
-		Object::Option(None) | Object::Unit | Object::UnitStruct { name: _ } => (), // Do nothing.
-		Object::UnitVariant { name: _, variant } => stringify_value(variant, encoding),
-		Object::NewtypeStruct { name: _, value } => stringify(value, encoding),
-		Object::NewtypeVariant {
-			name: _,
-			variant,
-			value,
-		} => {
-			stringify_value(variant, encoding);
-			stringify(value, encoding);
-		}
-		Object::Seq(list) => stringify_keys_iter(list.iter_mut(), encoding),
-		Object::Tuple(fields) => stringify_keys_iter(fields.iter_mut(), encoding),
-		Object::TupleStruct { name: _, fields } => stringify_keys_iter(fields.iter_mut(), encoding),
-		Object::TupleVariant {
-			name: _,
-			variant,
-			fields,
-		} => {
-			stringify_value(variant, encoding);
-			stringify(fields.as_mut(), encoding);
-		}
-		Object::Map(map) => {
-			for (k, v) in map.iter_mut() {
-				stringify_value(k, encoding);
-				stringify(v, encoding);
-			}
-		}
-		Object::Struct { name: _, fields } => {
-			stringify_keys_iter(fields.iter_mut().filter_map(|(_, v)| v.as_mut()), encoding);
-		}
-		Object::StructVariant {
-			name: _,
-			variant,
-			fields,
-		} => {
-			stringify_value(variant, encoding);
-			stringify(fields, encoding);
-		}
-		Object::FieldMap(map) => {
-			for (k, v) in map.iter_mut() {
-				stringify_value(k, encoding);
-				if let Some(v) = v.as_mut() {
-					stringify(v, encoding);
-				}
-			}
-		}
-	}
+fn load_patch(path: &str) -> Result<Patch, String> {
+	let text = fs::read_to_string(path)
+		.map_err(|error| format!("Failed to read patch {}: {}", path, error))?;
+	text.parse()
+		.map_err(|error: reserde::PatchError| error.to_string())
 }
 
-fn stringify_keys_iter<'a, 'b: 'a>(
-	iter: impl IntoIterator<Item = &'a mut Object<'b>>,
-	encoding: Encoding,
-) {
-	for item in iter {
-		stringify(item, encoding);
-	}
+fn load_schema(path: &Path) -> Result<Schema, Error> {
+	let text = fs::read_to_string(path).map_err(|error| {
+		Error::Options(format!(
+			"Failed to read schema {}: {}",
+			path.display(),
+			error
+		))
+	})?;
+	text.parse()
+		.map_err(|error: reserde::SchemaError| Error::Options(error.to_string()))
 }
 
-fn stringify_value(object: &mut Object, encoding: Encoding) {
-	*object = Object::String(
-		#[allow(clippy::match_same_arms)]
-		match object {
-			Object::Bool(value) => value.to_string(),
-			Object::I8(value) => value.to_string(),
-			Object::I16(value) => value.to_string(),
-			Object::I32(value) => value.to_string(),
-			Object::I64(value) => value.to_string(),
-			Object::I128(value) => value.to_string(),
-			Object::U8(value) => value.to_string(),
-			Object::U16(value) => value.to_string(),
-			Object::U32(value) => value.to_string(),
-			Object::U64(value) => value.to_string(),
-			Object::U128(value) => value.to_string(),
-			Object::F32(value) => value.to_string(),
-			Object::F64(value) => value.to_string(),
-			Object::Char(value) => value.to_string(),
-			Object::String(_) => return,
-			Object::ByteArray(bytes) => match std::str::from_utf8(bytes.as_ref()) {
-				Ok(str) => str.to_string(),
-				Err(_) => {
-					return;
-				}
-			},
-			Object::Option(option) => {
-				if let Some(obj) = option.as_deref_mut() {
-					stringify_value(obj, encoding);
-				}
-				return;
-			}
-			Object::Unit
-			| Object::UnitStruct { .. }
-			| Object::UnitVariant { .. }
-			| Object::NewtypeStruct { .. }
-			| Object::NewtypeVariant { .. }
-			| Object::Seq(_)
-			| Object::Tuple(_)
-			| Object::TupleStruct { .. }
-			| Object::TupleVariant { .. }
-			| Object::Map(_)
-			| Object::Struct { .. }
-			| Object::StructVariant { .. } => {
-				return;
-			}
-			Object::DualVariantKey { index: _, name } => (*name).to_string(),
-			Object::FieldMap(_) => {
-				return;
-			}
-		}
-		.pipe(Cow::Owned),
-	);
+/// Detects the input format from `in_file`'s extension or else the start of `reader`,
+/// which is replaced with an equivalent reader in that case.
+fn detect_in_format(
+	in_file: Option<&Path>,
+	reader: &mut Box<dyn Read>,
+	verbose: bool,
+) -> Result<In, Error> {
+	let (in_format, source) = if let Some(in_format) = in_file.and_then(In::from_extension) {
+		(in_format, "extension")
+	} else {
+		let mut prefix = vec![];
+		reader
+			.take(In::SNIFF_LENGTH as u64)
+			.read_to_end(&mut prefix)
+			.map_err(Error::Input)?;
+		let in_format = In::sniff(&prefix)?;
+		let rest = std::mem::replace(reader, Box::new(io::empty()));
+		*reader = Box::new(Cursor::new(prefix).chain(rest));
+		(in_format, "content")
+	};
+	if verbose {
+		eprintln!(
+			"reserde: detected input format {} from {}",
+			in_format, source
+		);
+	}
+	Ok(in_format)
 }
@@ -0,0 +1,113 @@
+//! Deep merging of documents, as for configuration layered from several files.
+
+use crate::{
+	compare::{self, View},
+	path::{unwrap, unwrap_owned},
+	shape::{shape_error, Segment, ShapeError},
+	Merge,
+};
+use serde_object::Object;
+use std::mem;
+
+/// Merges `overlay` into `base`:
+/// Maps and structs are merged by key, sequences according to `sequences` and other values are replaced.
+/// Null values replace anything and can be replaced with anything, but otherwise the kinds of values must match.
+pub(crate) fn merge(
+	base: &mut Object<'static>,
+	overlay: Object<'static>,
+	sequences: Merge,
+	path: &mut Vec<Segment>,
+) -> Result<(), ShapeError> {
+	let base = unwrap(base);
+	let overlay = unwrap_owned(overlay);
+	match (shape(base), shape(&overlay)) {
+		(Shape::Null, _) | (_, Shape::Null) | (Shape::Scalar, Shape::Scalar) => *base = overlay,
+		(Shape::Seq, Shape::Seq) => match sequences {
+			Merge::Replace => *base = overlay,
+			Merge::Append => match base {
+				Object::Seq(elements)
+				| Object::Tuple(elements)
+				| Object::TupleStruct {
+					fields: elements, ..
+				} => elements.extend(into_elements(overlay)),
+				_ => unreachable!("checked by `shape`"),
+			},
+		},
+		(Shape::Map, Shape::Map) => {
+			let mut entries = into_entries(mem::replace(base, Object::Unit));
+			for (key, value) in into_entries(overlay) {
+				let name = View::of(&key).key();
+				match entries.iter_mut().find(|(k, _)| View::of(k).key() == name) {
+					Some((_, existing)) => {
+						path.push(Segment::Key(name));
+						merge(existing, value, sequences, path)?;
+						path.pop();
+					}
+					None => entries.push((key, value)),
+				}
+			}
+			*base = Object::Map(entries);
+		}
+		_ => {
+			return Err(shape_error(
+				path,
+				format!(
+					"Can't merge {} into {}",
+					compare::kind(&overlay),
+					compare::kind(base)
+				),
+			))
+		}
+	}
+	Ok(())
+}
+
+/// What values can be merged with.
+#[derive(Clone, Copy)]
+pub(crate) enum Shape {
+	Null,
+	Scalar,
+	Seq,
+	Map,
+}
+
+pub(crate) fn shape(object: &Object) -> Shape {
+	match View::of(object) {
+		View::Null => Shape::Null,
+		View::Seq(_) => Shape::Seq,
+		View::Map(_) | View::Fields(_) => Shape::Map,
+		View::Bool(_)
+		| View::Integer(_)
+		| View::Float(_)
+		| View::Text(_)
+		| View::Bytes(_)
+		| View::Variant(..) => Shape::Scalar,
+	}
+}
+
+pub(crate) fn into_elements(object: Object) -> Vec<Object> {
+	match object {
+		Object::Seq(elements)
+		| Object::Tuple(elements)
+		| Object::TupleStruct {
+			fields: elements, ..
+		} => elements,
+		_ => unreachable!("checked by `shape`"),
+	}
+}
+
+/// Turns maps and structs into plain map entries, leaving out absent fields.
+pub(crate) fn into_entries(object: Object) -> Vec<(Object, Object)> {
+	match object {
+		Object::Map(entries) => entries,
+		Object::FieldMap(entries) => entries
+			.into_iter()
+			.filter_map(|(key, value)| Some((key, value?)))
+			.collect(),
+		Object::Struct { fields, .. } => fields
+			.into_iter()
+			.filter_map(|(key, value)| Some((Object::String(key), value?)))
+			.collect(),
+		_ => unreachable!("checked by `shape`"),
+	}
+}
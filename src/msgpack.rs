@@ -0,0 +1,119 @@
+//! MessagePack extension types.
+//!
+//! `rmp_serde` presents ext values as a newtype struct around a `(tag, data)` tuple.
+//! Since no other MessagePack value deserializes as newtype struct,
+//! these are replaced with tagged maps that other formats can represent:
+//!
+//! ```json
+//! {"$ext": {"type": 5, "data": [1, 2, 3]}}
+//! ```
+//!
+//! MessagePack output turns such maps back into ext values.
+
+use crate::transform::{walk, Visitor};
+use serde_object::Object;
+use std::{borrow::Cow, convert::TryFrom};
+
+/// The key of a tagged ext value.
+pub(crate) const EXT_TAG: &str = "$ext";
+
+const EXT_STRUCT_NAME: &str = rmp_serde::MSGPACK_EXT_STRUCT_NAME;
+
+/// Replaces ext values read by `rmp_serde` with tagged maps.
+pub(crate) fn tag_ext(object: &mut Object) {
+	walk(object, &mut TagExt);
+}
+
+/// Turns tagged maps back into ext values `rmp_serde` can write.
+///
+/// Since most formats don't have byte strings, `data` may also be a sequence of bytes.
+pub(crate) fn untag_ext(object: &mut Object) {
+	walk(object, &mut UntagExt);
+}
+
+struct TagExt;
+
+impl Visitor for TagExt {
+	fn value(&mut self, object: &mut Object) {
+		if let Object::NewtypeStruct { value, .. } = object {
+			if let Object::Seq(fields) | Object::Tuple(fields) = value.as_mut() {
+				if let [Object::I8(tag), Object::ByteArray(data)] = fields.as_mut_slice() {
+					let tag = *tag;
+					let data = std::mem::take(data);
+					*object = Object::Map(vec![(
+						Object::String(Cow::Borrowed(EXT_TAG)),
+						Object::Map(vec![
+							(Object::String(Cow::Borrowed("type")), Object::I8(tag)),
+							(
+								Object::String(Cow::Borrowed("data")),
+								Object::ByteArray(data),
+							),
+						]),
+					)]);
+				}
+			}
+		}
+	}
+}
+
+struct UntagExt;
+
+impl Visitor for UntagExt {
+	fn value(&mut self, object: &mut Object) {
+		if let Object::Map(map) = object {
+			if let [(Object::String(key), Object::Map(ext))] = map.as_slice() {
+				if key == EXT_TAG {
+					if let Some((tag, data)) = ext_fields(ext) {
+						*object = Object::NewtypeStruct {
+							name: Cow::Borrowed(EXT_STRUCT_NAME),
+							value: Box::new(Object::Tuple(vec![
+								Object::I8(tag),
+								Object::ByteArray(Cow::Owned(data)),
+							])),
+						};
+					}
+				}
+			}
+		}
+	}
+}
+
+fn ext_fields(ext: &[(Object, Object)]) -> Option<(i8, Vec<u8>)> {
+	let mut tag = None;
+	let mut data = None;
+	for (key, value) in ext {
+		match key {
+			Object::String(key) if key == "type" => tag = Some(integer(value)?),
+			Object::String(key) if key == "data" => data = Some(bytes(value)?),
+			_ => return None,
+		}
+	}
+	Some((i8::try_from(tag?).ok()?, data?))
+}
+
+fn integer(object: &Object) -> Option<i128> {
+	Some(match *object {
+		Object::I8(value) => value.into(),
+		Object::I16(value) => value.into(),
+		Object::I32(value) => value.into(),
+		Object::I64(value) => value.into(),
+		Object::I128(value) => value,
+		Object::U8(value) => value.into(),
+		Object::U16(value) => value.into(),
+		Object::U32(value) => value.into(),
+		Object::U64(value) => value.into(),
+		Object::U128(value) => i128::try_from(value).ok()?,
+		_ => return None,
+	})
+}
+
+fn bytes(object: &Object) -> Option<Vec<u8>> {
+	match object {
+		Object::ByteArray(bytes) => Some(bytes.to_vec()),
+		Object::Seq(elements) => elements
+			.iter()
+			.map(|element| u8::try_from(integer(element)?).ok())
+			.collect(),
+		_ => None,
+	}
+}
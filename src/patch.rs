@@ -0,0 +1,446 @@
+//! RFC 6902 JSON Patches and RFC 7386 JSON Merge Patches.
+
+use crate::{
+	compare::{self, View},
+	path::{key_str, unwrap, KeyPath},
+	transform::duplicate,
+};
+use serde_detach::detach;
+use serde_object::Object;
+use std::{
+	borrow::Cow,
+	error::Error as StdError,
+	fmt::{self, Display, Formatter},
+	str::FromStr,
+	sync::Arc,
+};
+
+/// A JSON Patch or JSON Merge Patch, as used by [`Transform::Patch`](`crate::Transform::Patch`).
+///
+/// An array is read as [RFC 6902](https://tools.ietf.org/html/rfc6902) JSON Patch,
+/// anything else as [RFC 7386](https://tools.ietf.org/html/rfc7386) JSON Merge Patch.
+///
+/// Keys match map entries, struct fields and enum variants (as in externally tagged JSON).
+/// Options and newtype structs are looked through.
+#[derive(Debug, Clone)]
+pub struct Patch {
+	source: String,
+	kind: Arc<Kind>,
+}
+
+impl PartialEq for Patch {
+	fn eq(&self, other: &Self) -> bool {
+		self.source == other.source
+	}
+}
+
+impl Eq for Patch {}
+
+#[derive(Debug)]
+enum Kind {
+	Json(Vec<Operation>),
+	Merge(Object<'static>),
+}
+
+#[derive(Debug)]
+struct Operation {
+	op: Op,
+	path: KeyPath,
+	from: Option<KeyPath>,
+	value: Option<Object<'static>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+	Add,
+	Remove,
+	Replace,
+	Move,
+	Copy,
+	Test,
+}
+
+const OPS: &[(&str, Op)] = &[
+	("add", Op::Add),
+	("remove", Op::Remove),
+	("replace", Op::Replace),
+	("move", Op::Move),
+	("copy", Op::Copy),
+	("test", Op::Test),
+];
+
+impl Display for Op {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let &(name, _) = OPS
+			.iter()
+			.find(|(_, op)| op == self)
+			.expect("all ops are listed");
+		f.write_str(name)
+	}
+}
+
+/// A [`Patch`] couldn't be parsed.
+#[derive(Debug)]
+pub struct PatchError(String);
+
+impl Display for PatchError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "Invalid patch: {}", self.0)
+	}
+}
+
+impl StdError for PatchError {}
+
+impl FromStr for Patch {
+	type Err = PatchError;
+
+	fn from_str(json: &str) -> Result<Self, Self::Err> {
+		let document: Object = serde_json::from_str(json)
+			.map(detach)
+			.map_err(|error| PatchError(error.to_string()))?;
+		let kind = match document {
+			Object::Seq(operations) => Kind::Json(
+				operations
+					.iter()
+					.enumerate()
+					.map(|(index, operation)| parse_operation(index + 1, operation))
+					.collect::<Result<_, _>>()?,
+			),
+			document => Kind::Merge(document),
+		};
+		Ok(Self {
+			source: json.to_string(),
+			kind: Arc::new(kind),
+		})
+	}
+}
+
+fn parse_operation(number: usize, operation: &Object) -> Result<Operation, PatchError> {
+	let entries = match operation {
+		Object::Map(entries) => entries,
+		_ => {
+			return Err(PatchError(format!(
+				"Operation {} must be a JSON object",
+				number
+			)))
+		}
+	};
+	let field = |name: &str| {
+		entries
+			.iter()
+			.find(|(key, _)| key_str(key).as_deref() == Some(name))
+			.map(|(_, value)| value)
+	};
+	let missing = |name: &str| PatchError(format!("Operation {} has no `{}`", number, name));
+	let pointer = |name: &str| -> Result<KeyPath, PatchError> {
+		match field(name) {
+			Some(Object::String(pointer)) if pointer.is_empty() || pointer.starts_with('/') => {
+				Ok(pointer.parse().expect("JSON Pointers always parse"))
+			}
+			Some(_) => Err(PatchError(format!(
+				"`{}` of operation {} must be a JSON Pointer",
+				name, number
+			))),
+			None => Err(missing(name)),
+		}
+	};
+
+	let op = match field("op") {
+		Some(Object::String(op)) => OPS
+			.iter()
+			.find(|(name, _)| name == op)
+			.map(|&(_, op)| op)
+			.ok_or_else(|| PatchError(format!("Unknown op `{}` in operation {}", op, number)))?,
+		_ => return Err(missing("op")),
+	};
+	let path = pointer("path")?;
+	let from = match op {
+		Op::Move | Op::Copy => Some(pointer("from")?),
+		_ => None,
+	};
+	let value = match op {
+		Op::Add | Op::Replace | Op::Test => {
+			Some(duplicate(field("value").ok_or_else(|| missing("value"))?))
+		}
+		_ => None,
+	};
+	if let (Op::Move, Some(from)) = (op, &from) {
+		if from.is_proper_prefix_of(&path) {
+			return Err(PatchError(format!(
+				"Operation {} moves `{}` into itself",
+				number, from
+			)));
+		}
+	}
+	Ok(Operation {
+		op,
+		path,
+		from,
+		value,
+	})
+}
+
+impl Patch {
+	/// Applies the patch to `document`.
+	///
+	/// JSON Patches are atomic: If an operation fails, `document` is left as it was.
+	pub(crate) fn apply(&self, document: &mut Object) -> Result<(), String> {
+		match &*self.kind {
+			Kind::Json(operations) => {
+				let mut patched = duplicate(document);
+				for (index, operation) in operations.iter().enumerate() {
+					operation.apply(&mut patched).map_err(|message| {
+						format!(
+							"Operation {} (`{}` at `{}`) failed: {}",
+							index + 1,
+							operation.op,
+							operation.path,
+							message
+						)
+					})?;
+				}
+				*document = patched;
+			}
+			Kind::Merge(patch) => merge_patch(document, patch)?,
+		}
+		Ok(())
+	}
+}
+
+impl Operation {
+	fn apply(&self, document: &mut Object) -> Result<(), String> {
+		let target = |path: &KeyPath, document| -> Result<_, String> {
+			path.get_mut(document).map_err(|error| error.to_string())
+		};
+		match self.op {
+			Op::Add => add(document, &self.path, self.value()),
+			Op::Remove => remove(document, &self.path).map(drop),
+			Op::Replace => {
+				*target(&self.path, document)? = self.value();
+				Ok(())
+			}
+			Op::Move => {
+				let value = remove(document, self.from())?;
+				add(document, &self.path, value)
+			}
+			Op::Copy => {
+				let value = duplicate(target(self.from(), document)?);
+				add(document, &self.path, value)
+			}
+			Op::Test => {
+				let actual = target(&self.path, document)?;
+				let expected = self.value.as_ref().expect("checked while parsing");
+				if compare::compare(actual, expected).is_empty() {
+					Ok(())
+				} else {
+					Err(format!(
+						"Expected {} but found {}",
+						compare::preview(expected),
+						compare::preview(actual)
+					))
+				}
+			}
+		}
+	}
+
+	fn value(&self) -> Object<'static> {
+		duplicate(self.value.as_ref().expect("checked while parsing"))
+	}
+
+	fn from(&self) -> &KeyPath {
+		self.from.as_ref().expect("checked while parsing")
+	}
+}
+
+/// Adds `value` at `path`, replacing map entries but inserting into sequences, as in RFC 6902.
+fn add<'a>(document: &mut Object<'a>, path: &KeyPath, value: Object<'a>) -> Result<(), String> {
+	let (parent, token) = if let Some(split) = path.split_pointer() {
+		split
+	} else {
+		*document = value;
+		return Ok(());
+	};
+	let parent = unwrap(
+		parent
+			.get_mut(document)
+			.map_err(|error| error.to_string())?,
+	);
+	match parent {
+		Object::Seq(elements)
+		| Object::Tuple(elements)
+		| Object::TupleStruct {
+			fields: elements, ..
+		} => {
+			let index = if token == "-" {
+				elements.len()
+			} else {
+				index(token)
+					.filter(|&index| index <= elements.len())
+					.ok_or_else(|| {
+						format!(
+							"Can't insert at `{}` in a sequence of length {}",
+							token,
+							elements.len()
+						)
+					})?
+			};
+			elements.insert(index, value);
+		}
+		parent => match entry_mut(parent, token) {
+			Some(entry) => *entry = value,
+			None => insert_entry(parent, token, value)?,
+		},
+	}
+	Ok(())
+}
+
+/// Removes the value at `path`, which must exist.
+fn remove<'a>(document: &mut Object<'a>, path: &KeyPath) -> Result<Object<'a>, String> {
+	// Fails with a helpful message if there's no such value.
+	path.get_mut(document).map_err(|error| error.to_string())?;
+	let (parent, token) = path
+		.split_pointer()
+		.ok_or_else(|| "Can't remove the whole document".to_string())?;
+	let parent = unwrap(
+		parent
+			.get_mut(document)
+			.map_err(|error| error.to_string())?,
+	);
+	let removed = match parent {
+		Object::Seq(elements)
+		| Object::Tuple(elements)
+		| Object::TupleStruct {
+			fields: elements, ..
+		} => index(token)
+			.filter(|&index| index < elements.len())
+			.map(|index| elements.remove(index)),
+		parent => remove_entry(parent, token)?,
+	};
+	removed.ok_or_else(|| format!("Nothing to remove at `{}`", token))
+}
+
+/// Parses an array index without leading zeros, as in RFC 6901.
+fn index(token: &str) -> Option<usize> {
+	token
+		.parse()
+		.ok()
+		.filter(|index: &usize| index.to_string() == token)
+}
+
+/// Applies a merge patch as described in RFC 7386.
+///
+/// Enum variants with values are patched like single-entry maps, but can't be removed or added to.
+fn merge_patch(target: &mut Object, patch: &Object) -> Result<(), String> {
+	let entries = match patch {
+		Object::Map(entries) => entries,
+		patch => {
+			*target = duplicate(patch);
+			return Ok(());
+		}
+	};
+
+	let mut target = unwrap(target);
+	if !matches!(
+		View::of(target),
+		View::Map(_) | View::Fields(_) | View::Variant(_, Some(_))
+	) {
+		*target = Object::Map(vec![]);
+		target = unwrap(target);
+	}
+	for (key, value) in entries {
+		let key = key_str(key).ok_or_else(|| "Merge Patch keys must be strings".to_string())?;
+		if let View::Null = View::of(value) {
+			remove_entry(target, &key)?;
+		} else if let Some(entry) = entry_mut(target, &key) {
+			merge_patch(entry, value)?;
+		} else {
+			let mut entry = Object::Unit;
+			merge_patch(&mut entry, value)?;
+			insert_entry(target, &key, entry)?;
+		}
+	}
+	Ok(())
+}
+
+fn entry_mut<'a, 'b>(object: &'b mut Object<'a>, key: &str) -> Option<&'b mut Object<'a>> {
+	match object {
+		Object::Map(entries) => entries
+			.iter_mut()
+			.find(|(k, _)| key_str(k).as_deref() == Some(key))
+			.map(|(_, value)| value),
+		Object::FieldMap(entries) => entries
+			.iter_mut()
+			.find(|(k, _)| key_str(k).as_deref() == Some(key))
+			.and_then(|(_, value)| value.as_mut()),
+		Object::Struct { fields, .. } => fields
+			.iter_mut()
+			.find(|(name, _)| name == key)
+			.and_then(|(_, value)| value.as_mut()),
+		Object::NewtypeVariant {
+			variant,
+			value: fields,
+			..
+		}
+		| Object::TupleVariant {
+			variant, fields, ..
+		}
+		| Object::StructVariant {
+			variant, fields, ..
+		} if key_str(variant).as_deref() == Some(key) => Some(fields),
+		_ => None,
+	}
+}
+
+fn insert_entry<'a>(object: &mut Object<'a>, key: &str, value: Object<'a>) -> Result<(), String> {
+	let name = Object::String(Cow::Owned(key.to_string()));
+	match object {
+		Object::Map(entries) => entries.push((name, value)),
+		Object::FieldMap(entries) => match entries
+			.iter_mut()
+			.find(|(k, _)| key_str(k).as_deref() == Some(key))
+		{
+			Some((_, absent)) => *absent = Some(value),
+			None => entries.push((name, Some(value))),
+		},
+		Object::Struct { fields, .. } => match fields.iter_mut().find(|(k, _)| k == key) {
+			Some((_, absent)) => *absent = Some(value),
+			None => fields.push((Cow::Owned(key.to_string()), Some(value))),
+		},
+		object => {
+			return Err(format!(
+				"Can't add key `{}` to {}",
+				key,
+				compare::kind(object)
+			))
+		}
+	}
+	Ok(())
+}
+
+/// Removes the entry at `key`, if there is one.
+///
+/// Fails for the variant of an enum, which can't be left without one.
+fn remove_entry<'a>(object: &mut Object<'a>, key: &str) -> Result<Option<Object<'a>>, String> {
+	Ok(match object {
+		Object::Map(entries) => entries
+			.iter()
+			.position(|(k, _)| key_str(k).as_deref() == Some(key))
+			.map(|index| entries.remove(index).1),
+		Object::FieldMap(entries) => entries
+			.iter()
+			.position(|(k, v)| v.is_some() && key_str(k).as_deref() == Some(key))
+			.and_then(|index| entries.remove(index).1),
+		Object::Struct { fields, .. } => fields
+			.iter()
+			.position(|(k, v)| v.is_some() && k == key)
+			.and_then(|index| fields.remove(index).1),
+		Object::NewtypeVariant { variant, .. }
+		| Object::TupleVariant { variant, .. }
+		| Object::StructVariant { variant, .. }
+			if key_str(variant).as_deref() == Some(key) =>
+		{
+			return Err(format!("Can't remove enum variant `{}`", key));
+		}
+		_ => None,
+	})
+}
@@ -0,0 +1,452 @@
+//! Paths addressing values inside a document, like `info.files[*].path`.
+
+use crate::{
+	compare,
+	shape::{shape_error, Segment, ShapeError},
+};
+use serde_object::Object;
+use std::{
+	error::Error as StdError,
+	fmt::{self, Display, Formatter},
+	str::FromStr,
+};
+
+/// A path to values inside a document, as used by [`Transform::BytesFrom`](`crate::Transform::BytesFrom`)
+/// and [`Transform::Select`](`crate::Transform::Select`).
+///
+/// Keys are separated by `.` and indices are written as `[0]`.
+/// `*` and `[*]` match any key or index, respectively.
+/// Keys that aren't plain identifiers can be quoted, as in `info."piece length"`.
+/// The empty path addresses the whole document.
+///
+/// Paths starting with `/` are RFC 6901 JSON Pointers instead, like `/info/piece length`.
+/// There, `~1` and `~0` stand for `/` and `~`, and tokens that are numbers also match indices.
+///
+/// Keys match map entries with string keys, struct fields and enum variants (as in externally tagged JSON).
+/// Options and newtype structs are looked through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPath(Vec<Step>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+	Key(String),
+	Index(usize),
+	AnyKey,
+	AnyIndex,
+	/// A JSON Pointer reference token, matching a key or, if it's a number, an index.
+	Reference(String),
+}
+
+/// A [`KeyPath`] couldn't be parsed.
+#[derive(Debug)]
+pub struct KeyPathError(String);
+
+impl Display for KeyPathError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "Invalid path: {}", self.0)
+	}
+}
+
+impl StdError for KeyPathError {}
+
+impl FromStr for KeyPath {
+	type Err = KeyPathError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if let Some(pointer) = s.strip_prefix('/') {
+			return Ok(Self(
+				pointer
+					.split('/')
+					.map(|token| Step::Reference(token.replace("~1", "/").replace("~0", "~")))
+					.collect(),
+			));
+		}
+
+		let mut steps = vec![];
+		let mut chars = s.chars().peekable();
+		let mut expect_key = true;
+		while let Some(&c) = chars.peek() {
+			match c {
+				'[' => {
+					chars.next();
+					let mut index = String::new();
+					loop {
+						match chars.next() {
+							Some(']') => break,
+							Some(c) => index.push(c),
+							None => {
+								return Err(KeyPathError(format!("Unterminated `[` in `{}`", s)))
+							}
+						}
+					}
+					steps.push(match index.trim() {
+						"*" => Step::AnyIndex,
+						index => Step::Index(index.parse().map_err(|_| {
+							KeyPathError(format!("`[{}]` in `{}` is not an index", index, s))
+						})?),
+					});
+					expect_key = false;
+				}
+				'.' if !expect_key => {
+					chars.next();
+					expect_key = true;
+				}
+				'"' if expect_key => {
+					chars.next();
+					let mut key = String::new();
+					loop {
+						match chars.next() {
+							Some('"') => break,
+							Some('\\') => key.extend(chars.next()),
+							Some(c) => key.push(c),
+							None => {
+								return Err(KeyPathError(format!("Unterminated quote in `{}`", s)))
+							}
+						}
+					}
+					steps.push(Step::Key(key));
+					expect_key = false;
+				}
+				_ if expect_key => {
+					let mut key = String::new();
+					while let Some(&c) = chars.peek() {
+						if matches!(c, '.' | '[' | '"') {
+							break;
+						}
+						key.push(c);
+						chars.next();
+					}
+					if key.is_empty() {
+						return Err(KeyPathError(format!("Empty key in `{}`", s)));
+					}
+					steps.push(if key == "*" {
+						Step::AnyKey
+					} else {
+						Step::Key(key)
+					});
+					expect_key = false;
+				}
+				c => {
+					return Err(KeyPathError(format!(
+						"Unexpected `{}` in `{}`, expected `.` or `[`",
+						c, s
+					)))
+				}
+			}
+		}
+		if expect_key && !steps.is_empty() {
+			return Err(KeyPathError(format!("`{}` ends with `.`", s)));
+		}
+		Ok(Self(steps))
+	}
+}
+
+impl Display for KeyPath {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		for (i, step) in self.0.iter().enumerate() {
+			if let Step::Reference(token) = step {
+				write!(f, "/{}", token.replace('~', "~0").replace('/', "~1"))?;
+				continue;
+			}
+			match step {
+				Step::Key(key) => {
+					if i > 0 {
+						f.write_str(".")?;
+					}
+					if !key.is_empty()
+						&& !key
+							.contains(|c: char| matches!(c, '.' | '[' | '"') || c.is_whitespace())
+						&& key != "*"
+					{
+						f.write_str(key)?;
+					} else {
+						write!(f, "\"{}\"", key.replace('\\', "\\\\").replace('"', "\\\""))?;
+					}
+				}
+				Step::AnyKey if i > 0 => f.write_str(".*")?,
+				Step::AnyKey => f.write_str("*")?,
+				Step::Index(index) => write!(f, "[{}]", index)?,
+				Step::AnyIndex => f.write_str("[*]")?,
+				Step::Reference(_) => unreachable!("handled above"),
+			}
+		}
+		Ok(())
+	}
+}
+
+impl KeyPath {
+	/// Calls `f` on each value in `object` matched by this path,
+	/// along with the concrete path to it.
+	pub(crate) fn for_each_mut(
+		&self,
+		object: &mut Object,
+		f: &mut impl FnMut(&mut Object, &[Segment]),
+	) {
+		visit(&self.0, object, &mut vec![], f);
+	}
+
+	/// Takes the one value in `object` this path addresses out of it.
+	///
+	/// Unlike [`KeyPath::for_each_mut`], this fails if there's no such value, listing what's there instead.
+	pub(crate) fn select<'a>(&self, object: &mut Object<'a>) -> Result<Object<'a>, ShapeError> {
+		Ok(std::mem::replace(self.get_mut(object)?, Object::Unit))
+	}
+
+	/// Finds the one value in `object` this path addresses, failing like [`KeyPath::select`].
+	pub(crate) fn get_mut<'a, 'b>(
+		&self,
+		object: &'b mut Object<'a>,
+	) -> Result<&'b mut Object<'a>, ShapeError> {
+		let mut path = vec![];
+		let mut object = object;
+		for step in &self.0 {
+			if let Step::AnyKey | Step::AnyIndex = step {
+				return Err(shape_error(&path, "Can't select with wildcards"));
+			}
+			let (segment, child) =
+				child(step, unwrap(object)).map_err(|message| shape_error(&path, message))?;
+			path.push(segment);
+			object = child;
+		}
+		Ok(object)
+	}
+
+	/// Splits a JSON Pointer into the path to its parent and its last reference token.
+	pub(crate) fn split_pointer(&self) -> Option<(Self, &str)> {
+		match self.0.split_last() {
+			Some((Step::Reference(token), parent)) => Some((Self(parent.to_vec()), token)),
+			_ => None,
+		}
+	}
+
+	/// Whether `other` is this path followed by more steps.
+	pub(crate) fn is_proper_prefix_of(&self, other: &Self) -> bool {
+		other.0.len() > self.0.len() && other.0.starts_with(&self.0)
+	}
+}
+
+/// Looks through options and newtype structs.
+pub(crate) fn unwrap<'a, 'b>(object: &'b mut Object<'a>) -> &'b mut Object<'a> {
+	match object {
+		Object::Option(Some(value)) | Object::NewtypeStruct { value, .. } => unwrap(value),
+		object => object,
+	}
+}
+
+/// Looks through options and newtype structs, like [`unwrap`].
+pub(crate) fn unwrap_owned(object: Object) -> Object {
+	match object {
+		Object::Option(Some(value)) | Object::NewtypeStruct { value, .. } => unwrap_owned(*value),
+		object => object,
+	}
+}
+
+/// Finds the one child of `object` matching `step`, or explains why there's none.
+fn child<'a, 'b>(
+	step: &Step,
+	object: &'b mut Object<'a>,
+) -> Result<(Segment, &'b mut Object<'a>), String> {
+	let wanted = match step {
+		Step::Reference(token) if object_is_seq(object) && token.parse::<usize>().is_ok() => {
+			format!("index {}", token)
+		}
+		Step::Key(key) | Step::Reference(key) => format!("key `{}`", key),
+		Step::Index(index) => format!("index {}", index),
+		Step::AnyKey | Step::AnyIndex => unreachable!("rejected by `select`"),
+	};
+	let kind = compare::kind(object);
+	let mut keys = vec![];
+
+	match object {
+		Object::Map(map) => {
+			for (key, value) in map {
+				match key_str(key) {
+					Some(key) if matches_key(step, &key) => return Ok((Segment::Key(key), value)),
+					Some(key) => keys.push(format!("`{}`", key)),
+					None => (),
+				}
+			}
+		}
+		Object::FieldMap(map) => {
+			for (key, value) in map {
+				match (key_str(key), value) {
+					(Some(key), Some(value)) if matches_key(step, &key) => {
+						return Ok((Segment::Key(key), value))
+					}
+					(Some(key), Some(_)) => keys.push(format!("`{}`", key)),
+					_ => (),
+				}
+			}
+		}
+		Object::Struct { fields, .. } => {
+			for (key, value) in fields {
+				match value {
+					Some(value) if matches_key(step, key) => {
+						return Ok((Segment::Key(key.clone().into_owned()), value))
+					}
+					Some(_) => keys.push(format!("`{}`", key)),
+					None => (),
+				}
+			}
+		}
+		Object::NewtypeVariant {
+			variant,
+			value: fields,
+			..
+		}
+		| Object::TupleVariant {
+			variant, fields, ..
+		}
+		| Object::StructVariant {
+			variant, fields, ..
+		} => {
+			if let Some(key) = key_str(variant) {
+				if matches_key(step, &key) {
+					return Ok((Segment::Key(key), fields));
+				}
+				keys.push(format!("`{}`", key));
+			}
+		}
+		Object::Seq(elements)
+		| Object::Tuple(elements)
+		| Object::TupleStruct {
+			fields: elements, ..
+		} => {
+			let len = elements.len();
+			return elements
+				.iter_mut()
+				.enumerate()
+				.find(|(index, _)| matches_index(step, *index))
+				.map(|(index, element)| (Segment::Index(index), element))
+				.ok_or_else(|| format!("No {} in a sequence of length {}", wanted, len));
+		}
+		_ => (),
+	}
+
+	Err(if keys.is_empty() {
+		format!("No {} in {}", wanted, kind)
+	} else {
+		format!(
+			"No {} in {}, available keys are: {}",
+			wanted,
+			kind,
+			keys.join(", ")
+		)
+	})
+}
+
+fn object_is_seq(object: &Object) -> bool {
+	matches!(
+		object,
+		Object::Seq(_) | Object::Tuple(_) | Object::TupleStruct { .. }
+	)
+}
+
+fn visit(
+	steps: &[Step],
+	object: &mut Object,
+	path: &mut Vec<Segment>,
+	f: &mut impl FnMut(&mut Object, &[Segment]),
+) {
+	let (step, rest) = match steps.split_first() {
+		Some(split) => split,
+		None => return f(object, path),
+	};
+
+	let mut descend = |key: Segment, child: &mut Object| {
+		path.push(key);
+		visit(rest, child, path, f);
+		path.pop();
+	};
+
+	match object {
+		Object::Option(Some(value)) | Object::NewtypeStruct { value, .. } => {
+			visit(steps, value, path, f);
+		}
+		Object::Map(map) => {
+			for (key, value) in map {
+				if let Some(key) = key_str(key) {
+					if matches_key(step, &key) {
+						descend(Segment::Key(key), value);
+					}
+				}
+			}
+		}
+		Object::FieldMap(map) => {
+			for (key, value) in map {
+				if let (Some(key), Some(value)) = (key_str(key), value) {
+					if matches_key(step, &key) {
+						descend(Segment::Key(key), value);
+					}
+				}
+			}
+		}
+		Object::Struct { fields, .. } => {
+			for (key, value) in fields {
+				if let Some(value) = value {
+					if matches_key(step, key) {
+						descend(Segment::Key(key.clone().into_owned()), value);
+					}
+				}
+			}
+		}
+		Object::NewtypeVariant {
+			variant,
+			value: fields,
+			..
+		}
+		| Object::TupleVariant {
+			variant, fields, ..
+		}
+		| Object::StructVariant {
+			variant, fields, ..
+		} => {
+			if let Some(key) = key_str(variant) {
+				if matches_key(step, &key) {
+					descend(Segment::Key(key), fields);
+				}
+			}
+		}
+		Object::Seq(elements)
+		| Object::Tuple(elements)
+		| Object::TupleStruct {
+			fields: elements, ..
+		} => {
+			for (index, element) in elements.iter_mut().enumerate() {
+				if matches_index(step, index) {
+					descend(Segment::Index(index), element);
+				}
+			}
+		}
+		_ => (),
+	}
+}
+
+fn matches_key(step: &Step, key: &str) -> bool {
+	match step {
+		Step::Key(k) | Step::Reference(k) => k == key,
+		Step::AnyKey => true,
+		Step::Index(_) | Step::AnyIndex => false,
+	}
+}
+
+fn matches_index(step: &Step, index: usize) -> bool {
+	match step {
+		Step::Index(i) => *i == index,
+		Step::AnyIndex => true,
+		// No leading zeros, as in RFC 6901.
+		Step::Reference(token) => *token == index.to_string(),
+		Step::Key(_) | Step::AnyKey => false,
+	}
+}
+
+/// Keys as they appear in formats with string keys.
+pub(crate) fn key_str(key: &Object) -> Option<String> {
+	match key {
+		Object::String(string) => Some(string.to_string()),
+		Object::Char(char) => Some(char.to_string()),
+		Object::ByteArray(bytes) => std::str::from_utf8(bytes).ok().map(str::to_string),
+		Object::DualVariantKey { name, .. } => Some(name.to_string()),
+		_ => None,
+	}
+}
@@ -0,0 +1,734 @@
+//! Type descriptions for formats that aren't self-describing, currently only Bincode.
+
+use serde::de::{self, DeserializeSeed, EnumAccess, VariantAccess};
+use serde_object::Object;
+use std::{
+	borrow::Cow,
+	collections::{HashMap, HashSet},
+	convert::TryFrom,
+	error::Error as StdError,
+	fmt::{self, Display, Formatter},
+	str::FromStr,
+	sync::Arc,
+};
+
+/// Describes how to read a document that isn't self-describing.
+///
+/// Schemas are parsed from JSON objects with the type of the whole input as `root`
+/// and optionally a map of named `types`, which can be referred to by name
+/// and may be recursive through an option, sequence, map or enum:
+///
+/// ```json
+/// {
+///     "root": "Header",
+///     "types": {
+///         "Header": {"struct": {"magic": "u32", "name": "string", "items": {"seq": "Item"}}},
+///         "Item": {"enum": {
+///             "Empty": "unit",
+///             "Id": {"newtype": "u64"},
+///             "Pair": {"tuple": ["i16", "i16"]},
+///             "Named": {"struct": {"label": "string", "next": {"option": "Item"}}}
+///         }}
+///     }
+/// }
+/// ```
+///
+/// Type expressions are one of:
+///
+/// - a primitive: `"bool"`, `"i8"` through `"i128"`, `"u8"` through `"u128"`, `"f32"`, `"f64"`,
+///   `"char"`, `"string"`, `"bytes"` or `"unit"`,
+/// - the name of an entry in `types`, which is also used as struct or enum name in the output,
+/// - `{"option": T}`, `{"seq": T}`, `{"map": [K, V]}`, `{"tuple": [T, …]}`, `{"array": [T, length]}`,
+/// - `{"newtype": T}`, `{"struct": {"field": T, …}}` or `{"enum": {"Variant": payload, …}}`,
+///   where each variant's payload is `"unit"`, `{"newtype": T}`, `{"tuple": [T, …]}` or `{"struct": {…}}`.
+///
+/// Fields and variants are listed in declaration order.
+/// Enum variants are written by name, so Bincode output will use placeholder indices for them.
+#[derive(Debug, Clone)]
+pub struct Schema {
+	root: Type,
+	types: Arc<HashMap<String, Type>>,
+}
+
+/// A schema couldn't be parsed.
+#[derive(Debug)]
+pub struct SchemaError(String);
+
+impl Display for SchemaError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "Invalid schema: {}", self.0)
+	}
+}
+
+impl StdError for SchemaError {}
+
+#[derive(Debug, Clone)]
+enum Type {
+	Bool,
+	I8,
+	I16,
+	I32,
+	I64,
+	I128,
+	U8,
+	U16,
+	U32,
+	U64,
+	U128,
+	F32,
+	F64,
+	Char,
+	String,
+	Bytes,
+	Unit,
+	Named(String),
+	Option(Box<Type>),
+	Seq(Box<Type>),
+	Map(Box<Type>, Box<Type>),
+	Tuple(Vec<Type>),
+	Array(Box<Type>, usize),
+	Newtype(Box<Type>),
+	Struct(Vec<(String, Type)>),
+	Enum(Vec<(String, Payload)>),
+}
+
+#[derive(Debug, Clone)]
+enum Payload {
+	Unit,
+	Newtype(Type),
+	Tuple(Vec<Type>),
+	Struct(Vec<(String, Type)>),
+}
+
+impl FromStr for Schema {
+	type Err = SchemaError;
+
+	fn from_str(json: &str) -> Result<Self, Self::Err> {
+		let document: Object =
+			serde_json::from_str(json).map_err(|error| SchemaError(error.to_string()))?;
+		let entries = match &document {
+			Object::Map(entries) => entries,
+			_ => return Err(SchemaError("Expected a JSON object".to_string())),
+		};
+
+		let mut root = None;
+		let mut types = HashMap::new();
+		for (key, value) in entries {
+			match string(key) {
+				Some("root") => root = Some(parse_type(value, "root")?),
+				Some("types") => {
+					let definitions = match value {
+						Object::Map(definitions) => definitions,
+						_ => return Err(SchemaError("`types` must be a JSON object".to_string())),
+					};
+					for (name, definition) in definitions {
+						let name = string(name).expect("JSON keys are strings");
+						if primitive(name).is_some() {
+							return Err(SchemaError(format!(
+								"`types.{}` shadows a primitive type",
+								name
+							)));
+						}
+						let path = format!("types.{}", name);
+						types.insert(name.to_string(), parse_type(definition, &path)?);
+					}
+				}
+				Some(key) => return Err(SchemaError(format!("Unexpected key `{}`", key))),
+				None => unreachable!("JSON keys are strings"),
+			}
+		}
+
+		let schema = Schema {
+			root: root.ok_or_else(|| SchemaError("Missing `root`".to_string()))?,
+			types: Arc::new(types),
+		};
+		schema.check(&schema.root, "root")?;
+		for (name, definition) in schema.types.iter() {
+			schema.check(definition, &format!("types.{}", name))?;
+		}
+		let mut names: Vec<_> = schema.types.keys().collect();
+		names.sort();
+		let mut finite = HashSet::new();
+		for name in names {
+			schema.check_finite(name, &mut vec![], &mut finite)?;
+		}
+		Ok(schema)
+	}
+}
+
+impl Schema {
+	/// Reads one value as described by this schema from a Bincode deserializer.
+	pub(crate) fn seed<'de>(&self) -> impl DeserializeSeed<'de, Value = Object<'static>> + '_ {
+		Seed {
+			schema: self,
+			ty: &self.root,
+			name: "",
+		}
+	}
+
+	/// Makes sure all names refer to an entry in `types`.
+	fn check(&self, ty: &Type, path: &str) -> Result<(), SchemaError> {
+		match ty {
+			Type::Named(name) if !self.types.contains_key(name) => Err(SchemaError(format!(
+				"Unknown type `{}` at `{}`",
+				name, path
+			))),
+			Type::Option(ty) | Type::Seq(ty) | Type::Array(ty, _) | Type::Newtype(ty) => {
+				self.check(ty, path)
+			}
+			Type::Map(key, value) => {
+				self.check(key, path)?;
+				self.check(value, path)
+			}
+			Type::Tuple(types) => types.iter().try_for_each(|ty| self.check(ty, path)),
+			Type::Struct(fields) => fields
+				.iter()
+				.try_for_each(|(field, ty)| self.check(ty, &format!("{}.{}", path, field))),
+			Type::Enum(variants) => {
+				variants
+					.iter()
+					.try_for_each(|(variant, payload)| match payload {
+						Payload::Unit => Ok(()),
+						Payload::Newtype(ty) => self.check(ty, &format!("{}.{}", path, variant)),
+						Payload::Tuple(types) => types
+							.iter()
+							.try_for_each(|ty| self.check(ty, &format!("{}.{}", path, variant))),
+						Payload::Struct(fields) => fields.iter().try_for_each(|(field, ty)| {
+							self.check(ty, &format!("{}.{}.{}", path, variant, field))
+						}),
+					})
+			}
+			_ => Ok(()),
+		}
+	}
+}
+
+impl Schema {
+	/// Makes sure reading the type `name` doesn't always read `name` again,
+	/// which would recurse forever without consuming input.
+	///
+	/// Only options, sequences, maps and enums can stop the recursion.
+	fn check_finite<'a>(
+		&'a self,
+		name: &'a str,
+		reading: &mut Vec<&'a str>,
+		finite: &mut HashSet<&'a str>,
+	) -> Result<(), SchemaError> {
+		if finite.contains(name) {
+			return Ok(());
+		}
+		if reading.contains(&name) {
+			return Err(SchemaError(format!(
+				"Type `{}` contains itself without an option, sequence, map or enum in between: `{}` -> `{}`",
+				name,
+				reading.join("` -> `"),
+				name
+			)));
+		}
+		reading.push(name);
+		self.check_finite_type(&self.types[name], reading, finite)?;
+		reading.pop();
+		finite.insert(name);
+		Ok(())
+	}
+
+	fn check_finite_type<'a>(
+		&'a self,
+		ty: &'a Type,
+		reading: &mut Vec<&'a str>,
+		finite: &mut HashSet<&'a str>,
+	) -> Result<(), SchemaError> {
+		match ty {
+			Type::Named(name) => self.check_finite(name, reading, finite),
+			Type::Newtype(ty) => self.check_finite_type(ty, reading, finite),
+			Type::Array(ty, length) if *length > 0 => self.check_finite_type(ty, reading, finite),
+			Type::Tuple(types) => types
+				.iter()
+				.try_for_each(|ty| self.check_finite_type(ty, reading, finite)),
+			Type::Struct(fields) => fields
+				.iter()
+				.try_for_each(|(_, ty)| self.check_finite_type(ty, reading, finite)),
+			_ => Ok(()),
+		}
+	}
+}
+
+fn string<'a>(object: &'a Object) -> Option<&'a str> {
+	match object {
+		Object::String(string) => Some(string),
+		_ => None,
+	}
+}
+
+fn primitive(name: &str) -> Option<Type> {
+	Some(match name {
+		"bool" => Type::Bool,
+		"i8" => Type::I8,
+		"i16" => Type::I16,
+		"i32" => Type::I32,
+		"i64" => Type::I64,
+		"i128" => Type::I128,
+		"u8" => Type::U8,
+		"u16" => Type::U16,
+		"u32" => Type::U32,
+		"u64" => Type::U64,
+		"u128" => Type::U128,
+		"f32" => Type::F32,
+		"f64" => Type::F64,
+		"char" => Type::Char,
+		"string" => Type::String,
+		"bytes" => Type::Bytes,
+		"unit" => Type::Unit,
+		_ => return None,
+	})
+}
+
+/// Splits a `{"kind": argument}` type expression.
+fn single_entry<'a, 'b>(
+	object: &'a Object<'b>,
+	path: &str,
+) -> Result<(&'a str, &'a Object<'b>), SchemaError> {
+	match object {
+		Object::Map(entries) if entries.len() == 1 => {
+			let (key, value) = &entries[0];
+			Ok((string(key).expect("JSON keys are strings"), value))
+		}
+		_ => Err(SchemaError(format!(
+			"Expected a type name or an object with a single key at `{}`",
+			path
+		))),
+	}
+}
+
+fn parse_type(object: &Object, path: &str) -> Result<Type, SchemaError> {
+	if let Some(name) = string(object) {
+		return Ok(primitive(name).unwrap_or_else(|| Type::Named(name.to_string())));
+	}
+
+	let (kind, argument) = single_entry(object, path)?;
+	let path = &format!("{}.{}", path, kind);
+	Ok(match kind {
+		"option" => Type::Option(Box::new(parse_type(argument, path)?)),
+		"seq" => Type::Seq(Box::new(parse_type(argument, path)?)),
+		"newtype" => Type::Newtype(Box::new(parse_type(argument, path)?)),
+		"tuple" => Type::Tuple(parse_types(argument, path)?),
+		"struct" => Type::Struct(parse_fields(argument, path)?),
+		"map" => match list(argument, 2, path)? {
+			[key, value] => Type::Map(
+				Box::new(parse_type(key, path)?),
+				Box::new(parse_type(value, path)?),
+			),
+			_ => unreachable!(),
+		},
+		"array" => match list(argument, 2, path)? {
+			[ty, length] => Type::Array(
+				Box::new(parse_type(ty, path)?),
+				length_value(length).ok_or_else(|| {
+					SchemaError(format!("Expected an array length at `{}`", path))
+				})?,
+			),
+			_ => unreachable!(),
+		},
+		"enum" => {
+			let variants = match argument {
+				Object::Map(variants) => variants,
+				_ => {
+					return Err(SchemaError(format!(
+						"Expected an object of variants at `{}`",
+						path
+					)))
+				}
+			};
+			let mut parsed = vec![];
+			for (variant, payload) in variants {
+				let variant = string(variant).expect("JSON keys are strings");
+				let path = &format!("{}.{}", path, variant);
+				let payload = if string(payload) == Some("unit") {
+					Payload::Unit
+				} else {
+					match single_entry(payload, path)? {
+						("newtype", ty) => {
+							Payload::Newtype(parse_type(ty, &format!("{}.newtype", path))?)
+						}
+						("tuple", types) => {
+							Payload::Tuple(parse_types(types, &format!("{}.tuple", path))?)
+						}
+						("struct", fields) => {
+							Payload::Struct(parse_fields(fields, &format!("{}.struct", path))?)
+						}
+						(kind, _) => {
+							return Err(SchemaError(format!(
+								"Unknown variant kind `{}` at `{}`",
+								kind, path
+							)))
+						}
+					}
+				};
+				parsed.push((variant.to_string(), payload));
+			}
+			Type::Enum(parsed)
+		}
+		kind => {
+			return Err(SchemaError(format!(
+				"Unknown type kind `{}` at `{}`",
+				kind, path
+			)))
+		}
+	})
+}
+
+fn list<'a, 'b>(
+	object: &'a Object<'b>,
+	length: usize,
+	path: &str,
+) -> Result<&'a [Object<'b>], SchemaError> {
+	match object {
+		Object::Seq(elements) if elements.len() == length => Ok(elements),
+		_ => Err(SchemaError(format!(
+			"Expected an array of length {} at `{}`",
+			length, path
+		))),
+	}
+}
+
+fn length_value(object: &Object) -> Option<usize> {
+	match *object {
+		Object::U64(length) => usize::try_from(length).ok(),
+		Object::I64(length) => usize::try_from(length).ok(),
+		_ => None,
+	}
+}
+
+fn parse_types(object: &Object, path: &str) -> Result<Vec<Type>, SchemaError> {
+	match object {
+		Object::Seq(elements) => elements
+			.iter()
+			.enumerate()
+			.map(|(index, element)| parse_type(element, &format!("{}[{}]", path, index)))
+			.collect(),
+		_ => Err(SchemaError(format!("Expected an array at `{}`", path))),
+	}
+}
+
+fn parse_fields(object: &Object, path: &str) -> Result<Vec<(String, Type)>, SchemaError> {
+	match object {
+		Object::Map(fields) => fields
+			.iter()
+			.map(|(field, ty)| {
+				let field = string(field).expect("JSON keys are strings");
+				Ok((
+					field.to_string(),
+					parse_type(ty, &format!("{}.{}", path, field))?,
+				))
+			})
+			.collect(),
+		_ => Err(SchemaError(format!("Expected an object at `{}`", path))),
+	}
+}
+
+/// Drives a Bincode deserializer according to a [`Type`].
+///
+/// Bincode ignores names and reads structs as tuples, so those are passed as tuples here.
+#[derive(Clone, Copy)]
+struct Seed<'a> {
+	schema: &'a Schema,
+	ty: &'a Type,
+	/// The name of the current [`Type::Named`] definition, if it was just resolved.
+	name: &'a str,
+}
+
+impl<'a> Seed<'a> {
+	fn with(self, ty: &'a Type) -> Self {
+		Self {
+			schema: self.schema,
+			ty,
+			name: "",
+		}
+	}
+}
+
+macro_rules! primitive {
+	($deserializer:ident, $ty:ty, $variant:ident) => {
+		<$ty as de::Deserialize>::deserialize($deserializer).map(Object::$variant)
+	};
+}
+
+impl<'de> DeserializeSeed<'de> for Seed<'_> {
+	type Value = Object<'static>;
+
+	fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+	where
+		D: de::Deserializer<'de>,
+	{
+		let name = || Cow::Owned(self.name.to_string());
+		match self.ty {
+			Type::Bool => primitive!(deserializer, bool, Bool),
+			Type::I8 => primitive!(deserializer, i8, I8),
+			Type::I16 => primitive!(deserializer, i16, I16),
+			Type::I32 => primitive!(deserializer, i32, I32),
+			Type::I64 => primitive!(deserializer, i64, I64),
+			Type::I128 => primitive!(deserializer, i128, I128),
+			Type::U8 => primitive!(deserializer, u8, U8),
+			Type::U16 => primitive!(deserializer, u16, U16),
+			Type::U32 => primitive!(deserializer, u32, U32),
+			Type::U64 => primitive!(deserializer, u64, U64),
+			Type::U128 => primitive!(deserializer, u128, U128),
+			Type::F32 => primitive!(deserializer, f32, F32),
+			Type::F64 => primitive!(deserializer, f64, F64),
+			Type::Char => primitive!(deserializer, char, Char),
+			Type::String => <String as de::Deserialize>::deserialize(deserializer)
+				.map(|string| Object::String(Cow::Owned(string))),
+			Type::Bytes => deserializer.deserialize_byte_buf(Visitor::Bytes),
+			Type::Unit => <() as de::Deserialize>::deserialize(deserializer).map(|()| Object::Unit),
+			Type::Named(name) => Seed {
+				schema: self.schema,
+				ty: &self.schema.types[name],
+				name,
+			}
+			.deserialize(deserializer),
+			Type::Option(ty) => deserializer.deserialize_option(Visitor::Option(self.with(ty))),
+			Type::Seq(ty) => deserializer.deserialize_seq(Visitor::Seq(self.with(ty))),
+			Type::Map(key, value) => {
+				deserializer.deserialize_map(Visitor::Map(self.with(key), self.with(value)))
+			}
+			Type::Tuple(types) => deserializer
+				.deserialize_tuple(
+					types.len(),
+					Visitor::Fields(self, Fields::Listed(types.iter().collect())),
+				)
+				.map(|values| Object::Tuple(seq(values))),
+			Type::Array(ty, length) => deserializer
+				.deserialize_tuple(
+					*length,
+					Visitor::Fields(self, Fields::Repeated(ty, *length)),
+				)
+				.map(|values| Object::Tuple(seq(values))),
+			Type::Newtype(ty) => deserializer
+				.deserialize_newtype_struct("", Visitor::Newtype(self.with(ty)))
+				.map(|value| Object::NewtypeStruct {
+					name: name(),
+					value: Box::new(value),
+				}),
+			Type::Struct(fields) => deserializer
+				.deserialize_tuple(
+					fields.len(),
+					Visitor::Fields(
+						self,
+						Fields::Listed(fields.iter().map(|(_, ty)| ty).collect()),
+					),
+				)
+				.map(|values| Object::Struct {
+					name: name(),
+					fields: fields
+						.iter()
+						.zip(seq(values))
+						.map(|((field, _), value)| (Cow::Owned(field.clone()), Some(value)))
+						.collect(),
+				}),
+			Type::Enum(variants) => {
+				deserializer.deserialize_enum("", &[], Visitor::Enum(self, variants))
+			}
+		}
+	}
+}
+
+enum Visitor<'a> {
+	Bytes,
+	Option(Seed<'a>),
+	Seq(Seed<'a>),
+	Map(Seed<'a>, Seed<'a>),
+	Fields(Seed<'a>, Fields<'a>),
+	Newtype(Seed<'a>),
+	Enum(Seed<'a>, &'a [(String, Payload)]),
+}
+
+/// The types of a tuple's fields, in order.
+enum Fields<'a> {
+	Listed(Vec<&'a Type>),
+	/// An array, whose length isn't allocated for up front since it comes straight from the schema.
+	Repeated(&'a Type, usize),
+}
+
+impl<'a> Fields<'a> {
+	fn len(&self) -> usize {
+		match self {
+			Fields::Listed(types) => types.len(),
+			&Fields::Repeated(_, length) => length,
+		}
+	}
+
+	fn get(&self, index: usize) -> &'a Type {
+		match self {
+			Fields::Listed(types) => types[index],
+			Fields::Repeated(ty, _) => ty,
+		}
+	}
+}
+
+impl<'de> de::Visitor<'de> for Visitor<'_> {
+	type Value = Object<'static>;
+
+	fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+		formatter.write_str(match self {
+			Visitor::Bytes => "bytes",
+			Visitor::Option(_) => "an option",
+			Visitor::Seq(_) => "a sequence",
+			Visitor::Map(..) => "a map",
+			Visitor::Fields(..) => "a tuple",
+			Visitor::Newtype(_) => "a newtype struct",
+			Visitor::Enum(..) => "an enum",
+		})
+	}
+
+	fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+		Ok(Object::ByteArray(Cow::Owned(v)))
+	}
+
+	fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+		self.visit_byte_buf(v.to_vec())
+	}
+
+	fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+		Ok(Object::Option(None))
+	}
+
+	fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+	where
+		D: de::Deserializer<'de>,
+	{
+		match self {
+			Visitor::Option(seed) => seed
+				.deserialize(deserializer)
+				.map(|value| Object::Option(Some(Box::new(value)))),
+			_ => Err(de::Error::invalid_type(de::Unexpected::Option, &self)),
+		}
+	}
+
+	fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+	where
+		D: de::Deserializer<'de>,
+	{
+		match self {
+			Visitor::Newtype(seed) => seed.deserialize(deserializer),
+			_ => Err(de::Error::invalid_type(
+				de::Unexpected::NewtypeStruct,
+				&self,
+			)),
+		}
+	}
+
+	fn visit_seq<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+	where
+		A: de::SeqAccess<'de>,
+	{
+		let mut elements = vec![];
+		match &self {
+			Visitor::Seq(seed) => {
+				while let Some(element) = access.next_element_seed(*seed)? {
+					elements.push(element);
+				}
+				Ok(Object::Seq(elements))
+			}
+			Visitor::Fields(seed, fields) => {
+				for index in 0..fields.len() {
+					elements.push(
+						access
+							.next_element_seed(seed.with(fields.get(index)))?
+							.ok_or_else(|| de::Error::invalid_length(index, &self))?,
+					);
+				}
+				// Unwrapped by the caller as needed.
+				Ok(Object::Seq(elements))
+			}
+			_ => Err(de::Error::invalid_type(de::Unexpected::Seq, &self)),
+		}
+	}
+
+	fn visit_map<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+	where
+		A: de::MapAccess<'de>,
+	{
+		match self {
+			Visitor::Map(key, value) => {
+				let mut entries = vec![];
+				while let Some(entry) = access.next_entry_seed(key, value)? {
+					entries.push(entry);
+				}
+				Ok(Object::Map(entries))
+			}
+			_ => Err(de::Error::invalid_type(de::Unexpected::Map, &self)),
+		}
+	}
+
+	fn visit_enum<A>(self, access: A) -> Result<Self::Value, A::Error>
+	where
+		A: EnumAccess<'de>,
+	{
+		let (seed, variants) = match &self {
+			Visitor::Enum(seed, variants) => (*seed, *variants),
+			_ => return Err(de::Error::invalid_type(de::Unexpected::Enum, &self)),
+		};
+
+		let (index, access) = access.variant::<u32>()?;
+		let (variant, payload) = usize::try_from(index)
+			.ok()
+			.and_then(|index| variants.get(index))
+			.ok_or_else(|| {
+				de::Error::invalid_value(
+					de::Unexpected::Unsigned(index.into()),
+					&&*format!("a variant index below {}", variants.len()),
+				)
+			})?;
+
+		let name = Cow::Owned(seed.name.to_string());
+		let variant = Box::new(Object::String(Cow::Owned(variant.clone())));
+		Ok(match payload {
+			Payload::Unit => {
+				access.unit_variant()?;
+				Object::UnitVariant { name, variant }
+			}
+			Payload::Newtype(ty) => Object::NewtypeVariant {
+				name,
+				variant,
+				value: Box::new(access.newtype_variant_seed(seed.with(ty))?),
+			},
+			Payload::Tuple(types) => Object::TupleVariant {
+				name,
+				variant,
+				fields: Box::new(access.tuple_variant(
+					types.len(),
+					Visitor::Fields(seed, Fields::Listed(types.iter().collect())),
+				)?),
+			},
+			Payload::Struct(fields) => {
+				let values = access.tuple_variant(
+					fields.len(),
+					Visitor::Fields(
+						seed,
+						Fields::Listed(fields.iter().map(|(_, ty)| ty).collect()),
+					),
+				)?;
+				Object::StructVariant {
+					name,
+					variant,
+					fields: Box::new(Object::Map(
+						fields
+							.iter()
+							.zip(seq(values))
+							.map(|((field, _), value)| {
+								(Object::String(Cow::Owned(field.clone())), value)
+							})
+							.collect(),
+					)),
+				}
+			}
+		})
+	}
+}
+
+fn seq(object: Object<'static>) -> Vec<Object<'static>> {
+	match object {
+		Object::Seq(elements) => elements,
+		_ => unreachable!("`Visitor::Fields` produces sequences"),
+	}
+}
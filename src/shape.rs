@@ -0,0 +1,71 @@
+//! Errors for documents that can't be represented in a particular output format.
+
+use std::{
+	error::Error as StdError,
+	fmt::{self, Display, Formatter, Write as _},
+};
+
+/// An [`Object`](`serde_object::Object`) that can't be represented in the output format,
+/// along with where in the document the problem is.
+#[derive(Debug)]
+pub(crate) struct ShapeError {
+	path: String,
+	message: String,
+}
+
+impl Display for ShapeError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		if self.path.is_empty() {
+			write!(f, "{}", self.message)
+		} else {
+			write!(f, "{} (at `{}`)", self.message, self.path)
+		}
+	}
+}
+
+impl StdError for ShapeError {}
+
+/// One step from the document root towards the problematic value.
+#[derive(Clone)]
+pub(crate) enum Segment {
+	Key(String),
+	Index(usize),
+}
+
+pub(crate) fn shape_error(path: &[Segment], message: impl Into<String>) -> ShapeError {
+	ShapeError {
+		path: render_path(path),
+		message: message.into(),
+	}
+}
+
+/// Renders `path` like `a.b[1]`.
+pub(crate) fn render_path(path: &[Segment]) -> String {
+	let mut rendered = String::new();
+	for segment in path {
+		match segment {
+			Segment::Key(key) if rendered.is_empty() => rendered.push_str(key),
+			Segment::Key(key) => {
+				rendered.push('.');
+				rendered.push_str(key);
+			}
+			// Writing to a `String` can't fail.
+			Segment::Index(index) => write!(rendered, "[{}]", index).unwrap(),
+		}
+	}
+	rendered
+}
+
+/// Renders `path` as RFC 6901 JSON Pointer, like `/a/b/1`.
+pub(crate) fn render_pointer(path: &[Segment]) -> String {
+	let mut rendered = String::new();
+	for segment in path {
+		rendered.push('/');
+		match segment {
+			Segment::Key(key) => rendered.push_str(&key.replace('~', "~0").replace('/', "~1")),
+			// Writing to a `String` can't fail.
+			Segment::Index(index) => write!(rendered, "{}", index).unwrap(),
+		}
+	}
+	rendered
+}
@@ -0,0 +1,88 @@
+//! Splitting documents into named parts, as for one file per element.
+
+use crate::{
+	compare::{self, View},
+	merge::{into_elements, into_entries, shape, Shape},
+	path::{unwrap_owned, KeyPath},
+};
+use serde_object::Object;
+use std::collections::HashSet;
+
+/// Splits a sequence into its elements, named by index, or a map or struct into its values, named by key.
+///
+/// With `name_by`, each part is named by the value at that path in it instead.
+pub(crate) fn split(
+	object: Object<'static>,
+	name_by: Option<&KeyPath>,
+) -> Result<Vec<(String, Object<'static>)>, String> {
+	let object = unwrap_owned(object);
+	let parts: Vec<_> = match shape(&object) {
+		Shape::Seq => {
+			let elements = into_elements(object);
+			// Padded so that the files sort in order.
+			let width = elements.len().saturating_sub(1).to_string().len();
+			elements
+				.into_iter()
+				.enumerate()
+				.map(|(index, element)| (format!("{:0width$}", index, width = width), element))
+				.collect()
+		}
+		Shape::Map => into_entries(object)
+			.into_iter()
+			.map(|(key, value)| (View::of(&key).key(), value))
+			.collect(),
+		Shape::Null | Shape::Scalar => {
+			return Err(format!(
+				"Can only split sequences, maps and structs, not {}",
+				compare::kind(&object)
+			))
+		}
+	};
+
+	let mut names = HashSet::new();
+	parts
+		.into_iter()
+		.map(|(name, mut part)| {
+			let name = match name_by {
+				Some(path) => {
+					let value = path
+						.get_mut(&mut part)
+						.map_err(|error| format!("Can't name part `{}`: {}", name, error))?;
+					match View::of(value) {
+						View::Text(_)
+						| View::Integer(_)
+						| View::Bool(_)
+						| View::Variant(_, None) => View::of(value).key(),
+						_ => {
+							return Err(format!(
+								"Can't name part `{}` after {} (at `{}`)",
+								name,
+								compare::kind(value),
+								path
+							))
+						}
+					}
+				}
+				None => name,
+			};
+			check_name(&name)?;
+			if !names.insert(name.clone()) {
+				return Err(format!("Several parts are named `{}`", name));
+			}
+			Ok((name, part))
+		})
+		.collect()
+}
+
+/// Rejects names that aren't a single path component.
+fn check_name(name: &str) -> Result<(), String> {
+	if name.is_empty()
+		|| name == "."
+		|| name == ".."
+		|| name.contains(|c| matches!(c, '/' | '\\' | '\0'))
+	{
+		Err(format!("`{}` can't be a file name", name))
+	} else {
+		Ok(())
+	}
+}
@@ -0,0 +1,386 @@
+//! Conversions that pass values straight from a deserializer to a serializer,
+//! without materialising the document as [`Object`](`serde_object::Object`).
+//!
+//! This works like [`serde-transcode`](https://lib.rs/crates/serde-transcode),
+//! but keeps track of which side failed first and with what message,
+//! since errors otherwise get stringified and nested once per level.
+
+use crate::{Error, In, Options, Out};
+use serde::{
+	de,
+	ser::{self, SerializeMap as _, SerializeSeq as _},
+};
+use std::{
+	cell::{Cell, RefCell},
+	error::Error as StdError,
+	fmt::{self, Display, Formatter},
+	io::{self, BufReader, Read, Write},
+};
+
+/// Whether `in_format` can be converted to `out_format` without going through [`Object`](`serde_object::Object`).
+///
+/// CBOR can't be read this way, since `ciborium` doesn't expose its deserializer.
+pub(crate) fn supports(in_format: In, out_format: Out) -> bool {
+	matches!(
+		(in_format, out_format),
+		(In::Bencode | In::Json, Out::Cbor | Out::Json)
+	)
+}
+
+pub(crate) fn transcode(
+	reader: impl Read,
+	in_format: In,
+	writer: impl Write,
+	out_format: Out,
+	options: &Options,
+) -> Result<(), Error> {
+	debug_assert!(supports(in_format, out_format));
+
+	let mut reader = Intercept::new(reader);
+	let mut writer = Intercept::new(writer);
+	let log = Log::default();
+	let result = match in_format {
+		In::Bencode => {
+			let mut deserializer =
+				serde_bencode::de::Deserializer::new(BufReader::new(&mut reader));
+			serialize(&mut deserializer, &log, &mut writer, out_format, options)
+		}
+
+		In::Json => {
+			let mut deserializer =
+				serde_json::Deserializer::from_reader(BufReader::new(&mut reader));
+			serialize(&mut deserializer, &log, &mut writer, out_format, options).and_then(|()| {
+				deserializer
+					.end()
+					.map_err(|error| log.record(Side::Deserializer, &error))
+			})
+		}
+
+		In::Bincode
+		| In::Cbor
+		| In::Msgpack
+		| In::Taml
+		| In::Toml
+		| In::Urlencoded
+		| In::Xml
+		| In::Yaml => {
+			unreachable!("Unsupported input format for streaming: {}", in_format)
+		}
+	};
+
+	if result.is_err() {
+		return Err(if let Some(error) = writer.error.take() {
+			Error::Output(error)
+		} else if let Some(error) = reader.error.take() {
+			Error::Input(error)
+		} else {
+			match log.0.into_inner() {
+				Some((Side::Deserializer, message)) => Error::Deserialize {
+					format: in_format,
+					source: Box::new(message),
+				},
+				Some((Side::Serializer, message)) => Error::Serialize {
+					format: out_format,
+					source: Box::new(message),
+				},
+				None => unreachable!("Unlogged streaming error"),
+			}
+		});
+	}
+	writer.flush().map_err(Error::Output)
+}
+
+/// Errors are [logged](`Log`), so the error value is meaningless.
+fn serialize<'de>(
+	deserializer: impl de::Deserializer<'de>,
+	log: &Log,
+	writer: impl Write,
+	out_format: Out,
+	options: &Options,
+) -> Result<(), ()> {
+	let transcoder = Transcoder::new(deserializer, log);
+	match out_format {
+		Out::Cbor => ciborium::ser::into_writer(&transcoder, writer).map_err(drop),
+
+		Out::Json => if options.pretty {
+			serde_json::to_writer_pretty(writer, &transcoder)
+		} else {
+			serde_json::to_writer(writer, &transcoder)
+		}
+		.map_err(drop),
+
+		Out::Bencode
+		| Out::Bincode
+		| Out::Msgpack
+		| Out::Taml
+		| Out::Toml
+		| Out::Urlencoded
+		| Out::Xml
+		| Out::Yaml => {
+			unreachable!("Unsupported output format for streaming: {}", out_format)
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Side {
+	Deserializer,
+	Serializer,
+}
+
+/// Remembers the first error that occurred during transcoding.
+#[derive(Default)]
+struct Log(RefCell<Option<(Side, Message)>>);
+
+impl Log {
+	fn record(&self, side: Side, error: &dyn Display) {
+		self.0
+			.borrow_mut()
+			.get_or_insert_with(|| (side, Message(error.to_string())));
+	}
+
+	fn d2s<S: ser::Error>(&self, error: impl de::Error) -> S {
+		self.record(Side::Deserializer, &error);
+		S::custom(error)
+	}
+
+	fn s2d<D: de::Error>(&self, error: impl ser::Error) -> D {
+		self.record(Side::Serializer, &error);
+		D::custom(error)
+	}
+}
+
+#[derive(Debug)]
+struct Message(String);
+
+impl Display for Message {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		self.0.fmt(f)
+	}
+}
+
+impl StdError for Message {}
+
+struct Transcoder<'a, D> {
+	deserializer: Cell<Option<D>>,
+	log: &'a Log,
+}
+
+impl<'a, 'de, D: de::Deserializer<'de>> Transcoder<'a, D> {
+	fn new(deserializer: D, log: &'a Log) -> Self {
+		Self {
+			deserializer: Cell::new(Some(deserializer)),
+			log,
+		}
+	}
+}
+
+impl<'de, D: de::Deserializer<'de>> ser::Serialize for Transcoder<'_, D> {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: ser::Serializer,
+	{
+		self.deserializer
+			.take()
+			.expect("`Transcoder` may only be serialized once")
+			.deserialize_any(Visitor {
+				serializer,
+				log: self.log,
+			})
+			.map_err(|error| self.log.d2s(error))
+	}
+}
+
+struct Visitor<'a, S> {
+	serializer: S,
+	log: &'a Log,
+}
+
+macro_rules! visit {
+	($($visit:ident($ty:ty) => $serialize:ident$((&$ref:tt))?),*$(,)?) => {$(
+		fn $visit<E: de::Error>(self, v: $ty) -> Result<Self::Value, E> {
+			let log = self.log;
+			self.serializer.$serialize(visit!(@arg v $($ref)?)).map_err(|error| log.s2d(error))
+		}
+	)*};
+	(@arg $v:ident) => ($v);
+	(@arg $v:ident _) => (&$v);
+}
+
+impl<'de, S: ser::Serializer> de::Visitor<'de> for Visitor<'_, S> {
+	type Value = S::Ok;
+
+	fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+		write!(formatter, "any value")
+	}
+
+	visit! {
+		visit_bool(bool) => serialize_bool,
+		visit_i8(i8) => serialize_i8,
+		visit_i16(i16) => serialize_i16,
+		visit_i32(i32) => serialize_i32,
+		visit_i64(i64) => serialize_i64,
+		visit_i128(i128) => serialize_i128,
+		visit_u8(u8) => serialize_u8,
+		visit_u16(u16) => serialize_u16,
+		visit_u32(u32) => serialize_u32,
+		visit_u64(u64) => serialize_u64,
+		visit_u128(u128) => serialize_u128,
+		visit_f32(f32) => serialize_f32,
+		visit_f64(f64) => serialize_f64,
+		visit_char(char) => serialize_char,
+		visit_str(&str) => serialize_str,
+		visit_string(String) => serialize_str(&_),
+		visit_bytes(&[u8]) => serialize_bytes,
+		visit_byte_buf(Vec<u8>) => serialize_bytes(&_),
+	}
+
+	fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+		let log = self.log;
+		self.serializer
+			.serialize_none()
+			.map_err(|error| log.s2d(error))
+	}
+
+	fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+	where
+		D: de::Deserializer<'de>,
+	{
+		let log = self.log;
+		self.serializer
+			.serialize_some(&Transcoder::new(deserializer, log))
+			.map_err(|error| log.s2d(error))
+	}
+
+	fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+		let log = self.log;
+		self.serializer
+			.serialize_unit()
+			.map_err(|error| log.s2d(error))
+	}
+
+	fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+	where
+		D: de::Deserializer<'de>,
+	{
+		let log = self.log;
+		self.serializer
+			.serialize_newtype_struct("UNKNOWN", &Transcoder::new(deserializer, log))
+			.map_err(|error| log.s2d(error))
+	}
+
+	fn visit_seq<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+	where
+		A: de::SeqAccess<'de>,
+	{
+		let log = self.log;
+		let mut seq = self
+			.serializer
+			.serialize_seq(access.size_hint())
+			.map_err(|error| log.s2d(error))?;
+		while let Some(()) = access.next_element_seed(ElementSeed(&mut seq, log))? {}
+		seq.end().map_err(|error| log.s2d(error))
+	}
+
+	fn visit_map<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+	where
+		A: de::MapAccess<'de>,
+	{
+		let log = self.log;
+		let mut map = self
+			.serializer
+			.serialize_map(access.size_hint())
+			.map_err(|error| log.s2d(error))?;
+		while let Some(()) = access.next_key_seed(KeySeed(&mut map, log))? {
+			access.next_value_seed(ValueSeed(&mut map, log))?;
+		}
+		map.end().map_err(|error| log.s2d(error))
+	}
+}
+
+struct ElementSeed<'a, S>(&'a mut S, &'a Log);
+
+impl<'de, S: ser::SerializeSeq> de::DeserializeSeed<'de> for ElementSeed<'_, S> {
+	type Value = ();
+
+	fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+	where
+		D: de::Deserializer<'de>,
+	{
+		let Self(seq, log) = self;
+		seq.serialize_element(&Transcoder::new(deserializer, log))
+			.map_err(|error| log.s2d(error))
+	}
+}
+
+struct KeySeed<'a, S>(&'a mut S, &'a Log);
+
+impl<'de, S: ser::SerializeMap> de::DeserializeSeed<'de> for KeySeed<'_, S> {
+	type Value = ();
+
+	fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+	where
+		D: de::Deserializer<'de>,
+	{
+		let Self(map, log) = self;
+		map.serialize_key(&Transcoder::new(deserializer, log))
+			.map_err(|error| log.s2d(error))
+	}
+}
+
+struct ValueSeed<'a, S>(&'a mut S, &'a Log);
+
+impl<'de, S: ser::SerializeMap> de::DeserializeSeed<'de> for ValueSeed<'_, S> {
+	type Value = ();
+
+	fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+	where
+		D: de::Deserializer<'de>,
+	{
+		let Self(map, log) = self;
+		map.serialize_value(&Transcoder::new(deserializer, log))
+			.map_err(|error| log.s2d(error))
+	}
+}
+
+/// Keeps the first I/O error that occurs, since it would otherwise be reduced to a message.
+struct Intercept<T> {
+	inner: T,
+	error: Option<io::Error>,
+}
+
+impl<T> Intercept<T> {
+	fn new(inner: T) -> Self {
+		Self { inner, error: None }
+	}
+
+	fn intercept<R>(&mut self, result: io::Result<R>) -> io::Result<R> {
+		result.map_err(|error| {
+			if error.kind() == io::ErrorKind::Interrupted {
+				return error;
+			}
+			let copy = io::Error::new(error.kind(), error.to_string());
+			self.error.get_or_insert(error);
+			copy
+		})
+	}
+}
+
+impl<R: Read> Read for Intercept<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let result = self.inner.read(buf);
+		self.intercept(result)
+	}
+}
+
+impl<W: Write> Write for Intercept<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let result = self.inner.write(buf);
+		self.intercept(result)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		let result = self.inner.flush();
+		self.intercept(result)
+	}
+}
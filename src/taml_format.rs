@@ -0,0 +1,453 @@
+//! Writes [`Object`]s as TAML.
+//!
+//! There is no TAML serializer, since the same data can be laid out in many ways.
+//! This module picks one: Plain values are written as `key: value` lines first,
+//! then nested maps and structured enum variants get their own headings.
+//! Lists of maps become tabular sections (`# [[list].{a, b}]`) where all rows have the same plain fields,
+//! and a `# [list]` heading per element otherwise.
+//!
+//! When pretty-printing, tables are used more aggressively:
+//! Nested maps with matching fields become multi-segment columns (`{a, b.{c, d}}`)
+//! and lists of plain values that don't fit on one line get a row per element.
+//!
+//! TAML has no null, so absent values are left out of maps, like TAML's own deserializer expects.
+//! Byte arrays are written as lists of integers, since reading data literals requires a matching encoder.
+
+use crate::shape::{shape_error, Segment, ShapeError};
+use serde_object::Object;
+use std::fmt::Write as _;
+
+/// Lists of plain values longer than this are split into rows when pretty-printing.
+const LINE_WIDTH: usize = 80;
+
+pub(crate) fn to_string(object: &Object, pretty: bool) -> Result<String, ShapeError> {
+	let mut path = vec![];
+	let entries = match value(object, &mut path)? {
+		Some(Value::Map(entries)) => entries,
+		_ => return Err(shape_error(&path, "The top level must be a map or struct")),
+	};
+
+	let mut writer = Writer {
+		output: String::new(),
+		pretty,
+		path,
+	};
+	writer.section(0, &entries)?;
+	Ok(writer.output)
+}
+
+/// A simplified document model closer to TAML's.
+enum Value {
+	/// Already rendered.
+	Plain(String),
+	List(Vec<Value>),
+	Map(Vec<(String, Value)>),
+	TupleVariant(String, Vec<Value>),
+	StructVariant(String, Vec<(String, Value)>),
+}
+
+impl Value {
+	/// Whether this value can be written after a colon or inside a row, as opposed to under a heading.
+	fn is_inline(&self) -> bool {
+		match self {
+			Value::Plain(_) => true,
+			Value::List(elements) | Value::TupleVariant(_, elements) => {
+				elements.iter().all(Value::is_inline)
+			}
+			Value::Map(_) | Value::StructVariant(..) => false,
+		}
+	}
+
+	/// Only valid if [`Value::is_inline`].
+	fn render(&self) -> String {
+		match self {
+			Value::Plain(plain) => plain.clone(),
+			Value::List(elements) => format!("({})", render_all(elements)),
+			Value::TupleVariant(variant, fields) => {
+				format!("{}({})", identifier(variant), render_all(fields))
+			}
+			Value::Map(_) | Value::StructVariant(..) => unreachable!("Rendered non-inline value"),
+		}
+	}
+}
+
+fn render_all(values: &[Value]) -> String {
+	values
+		.iter()
+		.map(Value::render)
+		.collect::<Vec<_>>()
+		.join(", ")
+}
+
+/// [`None`] means the value is absent and should be skipped.
+fn value(object: &Object, path: &mut Vec<Segment>) -> Result<Option<Value>, ShapeError> {
+	Ok(Some(match object {
+		Object::Bool(bool) => Value::Plain(bool.to_string()),
+		Object::I8(integer) => Value::Plain(integer.to_string()),
+		Object::I16(integer) => Value::Plain(integer.to_string()),
+		Object::I32(integer) => Value::Plain(integer.to_string()),
+		Object::I64(integer) => Value::Plain(integer.to_string()),
+		Object::I128(integer) => Value::Plain(integer.to_string()),
+		Object::U8(integer) => Value::Plain(integer.to_string()),
+		Object::U16(integer) => Value::Plain(integer.to_string()),
+		Object::U32(integer) => Value::Plain(integer.to_string()),
+		Object::U64(integer) => Value::Plain(integer.to_string()),
+		Object::U128(integer) => Value::Plain(integer.to_string()),
+		Object::F32(float) => Value::Plain(decimal(f64::from(*float), path)?),
+		Object::F64(float) => Value::Plain(decimal(*float, path)?),
+		Object::Char(char) => Value::Plain(string(&char.to_string())),
+		Object::String(str) => Value::Plain(string(str)),
+		Object::ByteArray(bytes) => Value::List(
+			bytes
+				.iter()
+				.map(|byte| Value::Plain(byte.to_string()))
+				.collect(),
+		),
+		Object::Option(Some(object)) | Object::NewtypeStruct { value: object, .. } => {
+			return value(object, path)
+		}
+		Object::Option(None) => return Ok(None),
+		Object::Unit | Object::UnitStruct { .. } => Value::List(vec![]),
+		Object::UnitVariant { variant, .. } => {
+			Value::Plain(identifier(&variant_name(variant, path)?))
+		}
+		Object::DualVariantKey { name, .. } => Value::Plain(identifier(name)),
+		Object::NewtypeVariant { variant, value, .. } => {
+			let variant = variant_name(variant, path)?;
+			match self::value(value, path)? {
+				Some(Value::Map(fields)) => Value::StructVariant(variant, fields),
+				Some(value) => Value::TupleVariant(variant, vec![value]),
+				None => Value::TupleVariant(variant, vec![]),
+			}
+		}
+		Object::TupleVariant {
+			variant, fields, ..
+		} => {
+			let variant = variant_name(variant, path)?;
+			match value(fields, path)? {
+				Some(Value::List(fields)) => Value::TupleVariant(variant, fields),
+				_ => return Err(shape_error(path, "Malformed tuple variant")),
+			}
+		}
+		Object::StructVariant {
+			variant, fields, ..
+		} => {
+			let variant = variant_name(variant, path)?;
+			match value(fields, path)? {
+				Some(Value::Map(fields)) => Value::StructVariant(variant, fields),
+				_ => return Err(shape_error(path, "Malformed struct variant")),
+			}
+		}
+		Object::Seq(elements)
+		| Object::Tuple(elements)
+		| Object::TupleStruct {
+			fields: elements, ..
+		} => {
+			let mut list = vec![];
+			for (index, element) in elements.iter().enumerate() {
+				path.push(Segment::Index(index));
+				match value(element, path)? {
+					Some(value) => list.push(value),
+					None => return Err(shape_error(path, "Lists can't contain absent values")),
+				}
+				path.pop();
+			}
+			Value::List(list)
+		}
+		Object::Map(map) => map_value(map.iter().map(|(k, v)| (k, Some(v))), path)?,
+		Object::FieldMap(map) => map_value(map.iter().map(|(k, v)| (k, v.as_ref())), path)?,
+		Object::Struct { fields, .. } => {
+			let mut entries = vec![];
+			for (key, v) in fields {
+				if let Some(v) = v {
+					if let Some(v) = entry_value(key.to_string(), v, path)? {
+						entries.push((key.to_string(), v));
+					}
+				}
+			}
+			Value::Map(entries)
+		}
+	}))
+}
+
+fn map_value<'a, 'b: 'a>(
+	entries: impl IntoIterator<Item = (&'a Object<'b>, Option<&'a Object<'b>>)>,
+	path: &mut Vec<Segment>,
+) -> Result<Value, ShapeError> {
+	let mut map = vec![];
+	for (k, v) in entries {
+		let k = key(k).ok_or_else(|| {
+			shape_error(
+				path,
+				"Map keys must be strings (try stringifying them with -s)",
+			)
+		})?;
+		if let Some(v) = v {
+			if let Some(v) = entry_value(k.clone(), v, path)? {
+				map.push((k, v));
+			}
+		}
+	}
+	Ok(Value::Map(map))
+}
+
+fn entry_value(
+	key: String,
+	object: &Object,
+	path: &mut Vec<Segment>,
+) -> Result<Option<Value>, ShapeError> {
+	path.push(Segment::Key(key));
+	let value = value(object, path)?;
+	path.pop();
+	Ok(value)
+}
+
+fn key(object: &Object) -> Option<String> {
+	match object {
+		Object::String(string) => Some(string.to_string()),
+		Object::Char(char) => Some(char.to_string()),
+		Object::DualVariantKey { name, .. } => Some(name.to_string()),
+		_ => None,
+	}
+}
+
+fn variant_name(variant: &Object, path: &[Segment]) -> Result<String, ShapeError> {
+	key(variant).ok_or_else(|| shape_error(path, "Enum variant names must be strings"))
+}
+
+fn decimal(float: f64, path: &[Segment]) -> Result<String, ShapeError> {
+	if !float.is_finite() {
+		return Err(shape_error(
+			path,
+			format!("TAML decimals must be finite, not {}", float),
+		));
+	}
+	// `Display` never uses exponents, but leaves out the fractional part of whole numbers.
+	let mut decimal = float.to_string();
+	if !decimal.contains('.') {
+		decimal.push_str(".0");
+	}
+	Ok(decimal)
+}
+
+fn string(string: &str) -> String {
+	format!("\"{}\"", escape(string, '"'))
+}
+
+fn identifier(identifier: &str) -> String {
+	let mut chars = identifier.chars();
+	let plain = matches!(chars.next(), Some(first) if first.is_ascii_alphabetic() || first == '_')
+		&& chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+	if plain {
+		identifier.to_string()
+	} else {
+		format!("`{}`", escape(identifier, '`'))
+	}
+}
+
+fn escape(text: &str, delimiter: char) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'\r' => escaped.push_str("\\r"),
+			c if c == '\\' || c == delimiter => {
+				escaped.push('\\');
+				escaped.push(c);
+			}
+			c => escaped.push(c),
+		}
+	}
+	escaped
+}
+
+/// The columns of a tabular section.
+enum Column {
+	Leaf(String),
+	Nested(String, Vec<Column>),
+}
+
+fn render_columns(columns: &[Column]) -> String {
+	columns
+		.iter()
+		.map(|column| match column {
+			Column::Leaf(key) => identifier(key),
+			Column::Nested(key, columns) => {
+				format!("{}.{{{}}}", identifier(key), render_columns(columns))
+			}
+		})
+		.collect::<Vec<_>>()
+		.join(", ")
+}
+
+/// Renders the cells of `row` in the order given by `columns`, which must have been derived from it.
+fn cells_into(cells: &mut Vec<String>, columns: &[Column], row: &Value) {
+	let entries = match row {
+		Value::Map(entries) => entries,
+		_ => unreachable!("Tabulated non-map row"),
+	};
+	for (column, (_, value)) in columns.iter().zip(entries) {
+		match column {
+			Column::Leaf(_) => cells.push(value.render()),
+			Column::Nested(_, columns) => cells_into(cells, columns, value),
+		}
+	}
+}
+
+struct Writer {
+	output: String,
+	pretty: bool,
+	path: Vec<Segment>,
+}
+
+impl Writer {
+	/// Writes `entries` into the section opened by the last heading, which is `depth` levels deep.
+	fn section(&mut self, depth: usize, entries: &[(String, Value)]) -> Result<(), ShapeError> {
+		for (key, value) in entries.iter().filter(|(_, value)| value.is_inline()) {
+			if let (true, Value::List(elements)) = (self.pretty, value) {
+				if value.render().len() > LINE_WIDTH && !elements.is_empty() {
+					continue;
+				}
+			}
+			// Writing to a `String` can't fail.
+			writeln!(self.output, "{}: {}", identifier(key), value.render()).unwrap();
+		}
+
+		for (key, value) in entries {
+			self.path.push(Segment::Key(key.clone()));
+			match value {
+				Value::Map(entries) => {
+					self.heading(depth + 1, &identifier(key));
+					self.section(depth + 1, entries)?;
+				}
+				Value::StructVariant(variant, fields) => {
+					self.heading(
+						depth + 1,
+						&format!("{}:{}", identifier(key), identifier(variant)),
+					);
+					self.section(depth + 1, fields)?;
+				}
+				Value::List(elements) if !value.is_inline() => {
+					self.list(depth + 1, key, elements)?;
+				}
+				Value::List(elements)
+					if self.pretty && !elements.is_empty() && value.render().len() > LINE_WIDTH =>
+				{
+					self.rows(depth + 1, key, elements);
+				}
+				Value::TupleVariant(..) if !value.is_inline() => {
+					return Err(shape_error(
+						&self.path,
+						"Tuple variants can't contain maps in TAML",
+					));
+				}
+				Value::Plain(_) | Value::List(_) | Value::TupleVariant(..) => (),
+			}
+			self.path.pop();
+		}
+		Ok(())
+	}
+
+	fn heading(&mut self, depth: usize, path: &str) {
+		if !self.output.is_empty() {
+			self.output.push('\n');
+		}
+		writeln!(self.output, "{} {}", "#".repeat(depth), path).unwrap();
+	}
+
+	/// Writes a list that contains at least one map or structured enum variant.
+	fn list(&mut self, depth: usize, key: &str, elements: &[Value]) -> Result<(), ShapeError> {
+		if let Some(columns) = self.columns(&elements.iter().collect::<Vec<_>>()) {
+			self.heading(
+				depth,
+				&format!("[[{}].{{{}}}]", identifier(key), render_columns(&columns)),
+			);
+			for element in elements {
+				let mut cells = vec![];
+				cells_into(&mut cells, &columns, element);
+				writeln!(self.output, "{}", cells.join(", ")).unwrap();
+			}
+			return Ok(());
+		}
+
+		let mut elements = elements.iter().enumerate().peekable();
+		while let Some((index, element)) = elements.next() {
+			self.path.push(Segment::Index(index));
+			match element {
+				Value::Map(entries) => {
+					self.heading(depth, &format!("[{}]", identifier(key)));
+					self.section(depth, entries)?;
+				}
+				Value::StructVariant(variant, fields) => {
+					self.heading(
+						depth,
+						&format!("[{}]:{}", identifier(key), identifier(variant)),
+					);
+					self.section(depth, fields)?;
+				}
+				element if element.is_inline() => {
+					// Consecutive plain elements share a table.
+					self.heading(depth, &format!("[[{}]]", identifier(key)));
+					writeln!(self.output, "{}", element.render()).unwrap();
+					while let Some((_, element)) =
+						elements.next_if(|(_, element)| element.is_inline())
+					{
+						writeln!(self.output, "{}", element.render()).unwrap();
+					}
+				}
+				Value::Plain(_) | Value::List(_) | Value::TupleVariant(..) => {
+					return Err(shape_error(
+						&self.path,
+						"Maps can only be nested directly inside lists in TAML",
+					));
+				}
+			}
+			self.path.pop();
+		}
+		Ok(())
+	}
+
+	/// Writes a list of plain values with one row per element.
+	fn rows(&mut self, depth: usize, key: &str, elements: &[Value]) {
+		self.heading(depth, &format!("[[{}]]", identifier(key)));
+		for element in elements {
+			writeln!(self.output, "{}", element.render()).unwrap();
+		}
+	}
+
+	/// The columns for a tabular section containing `rows`, if they are uniform enough.
+	fn columns(&self, rows: &[&Value]) -> Option<Vec<Column>> {
+		let first = match rows.first()? {
+			Value::Map(entries) if !entries.is_empty() => entries,
+			_ => return None,
+		};
+
+		let mut columns = vec![];
+		for (index, (key, _)) in first.iter().enumerate() {
+			let cells = rows
+				.iter()
+				.map(|row| match row {
+					Value::Map(entries) => {
+						entries.get(index).filter(|(k, _)| k == key).map(|(_, v)| v)
+					}
+					_ => None,
+				})
+				.collect::<Option<Vec<_>>>()?;
+
+			if cells.iter().all(|cell| cell.is_inline()) {
+				columns.push(Column::Leaf(key.clone()));
+			} else if self.pretty {
+				columns.push(Column::Nested(key.clone(), self.columns(&cells)?));
+			} else {
+				return None;
+			}
+		}
+
+		for row in rows {
+			match row {
+				Value::Map(entries) if entries.len() == first.len() => (),
+				_ => return None,
+			}
+		}
+		Some(columns)
+	}
+}
@@ -0,0 +1,205 @@
+//! Conversions between [`Object`] and [`toml::Value`].
+//!
+//! TOML is handled through [`toml::Value`] rather than directly through Serde,
+//! since `toml`'s serializer requires plain values to come before tables
+//! and represents datetimes as a private magic struct.
+
+use crate::{
+	shape::{shape_error, Segment, ShapeError},
+	TomlDatetimes,
+};
+use serde_object::Object;
+use std::{borrow::Cow, convert::TryFrom};
+use toml::{value::Datetime, Value};
+
+/// The key of a tagged datetime, as in `{"$datetime": "1979-05-27T07:32:00Z"}`.
+pub(crate) const DATETIME_TAG: &str = "$datetime";
+
+pub(crate) fn to_object(value: Value, datetimes: TomlDatetimes) -> Object<'static> {
+	match value {
+		Value::String(string) => Object::String(Cow::Owned(string)),
+		Value::Integer(integer) => Object::I64(integer),
+		Value::Float(float) => Object::F64(float),
+		Value::Boolean(bool) => Object::Bool(bool),
+		Value::Datetime(datetime) => {
+			let string = Object::String(Cow::Owned(datetime.to_string()));
+			match datetimes {
+				TomlDatetimes::String => string,
+				TomlDatetimes::Tagged => {
+					Object::Map(vec![(Object::String(Cow::Borrowed(DATETIME_TAG)), string)])
+				}
+			}
+		}
+		Value::Array(array) => Object::Seq(
+			array
+				.into_iter()
+				.map(|value| to_object(value, datetimes))
+				.collect(),
+		),
+		Value::Table(table) => Object::Map(
+			table
+				.into_iter()
+				.map(|(key, value)| (Object::String(Cow::Owned(key)), to_object(value, datetimes)))
+				.collect(),
+		),
+	}
+}
+
+/// Converts `object` into a TOML document.
+///
+/// Tagged datetimes are converted back into TOML datetimes.
+/// Absent values are left out of tables, like `toml` does for [`None`] fields.
+pub(crate) fn to_value(object: &Object) -> Result<Value, ShapeError> {
+	let mut path = vec![];
+	match value(object, &mut path)? {
+		Some(value @ Value::Table(_)) => Ok(value),
+		Some(value) => Err(shape_error(
+			&path,
+			format!("The top level must be a table, not {}", value.type_str()),
+		)),
+		None => Err(shape_error(
+			&path,
+			"The top level must be a table, not an absent value",
+		)),
+	}
+}
+
+/// [`None`] means the value is absent and should be skipped.
+fn value(object: &Object, path: &mut Vec<Segment>) -> Result<Option<Value>, ShapeError> {
+	Ok(Some(match object {
+		Object::Bool(bool) => Value::Boolean(*bool),
+		Object::I8(integer) => Value::Integer((*integer).into()),
+		Object::I16(integer) => Value::Integer((*integer).into()),
+		Object::I32(integer) => Value::Integer((*integer).into()),
+		Object::I64(integer) => Value::Integer(*integer),
+		Object::U8(integer) => Value::Integer((*integer).into()),
+		Object::U16(integer) => Value::Integer((*integer).into()),
+		Object::U32(integer) => Value::Integer((*integer).into()),
+		Object::I128(integer) => integer_value(i64::try_from(*integer), path)?,
+		Object::U64(integer) => integer_value(i64::try_from(*integer), path)?,
+		Object::U128(integer) => integer_value(i64::try_from(*integer), path)?,
+		Object::F32(float) => Value::Float((*float).into()),
+		Object::F64(float) => Value::Float(*float),
+		Object::Char(char) => Value::String(char.to_string()),
+		Object::String(string) => Value::String(string.to_string()),
+		Object::ByteArray(bytes) => Value::Array(
+			bytes
+				.iter()
+				.map(|&byte| Value::Integer(byte.into()))
+				.collect(),
+		),
+		Object::Option(Some(object)) | Object::NewtypeStruct { value: object, .. } => {
+			return value(object, path)
+		}
+		Object::Option(None) | Object::Unit | Object::UnitStruct { .. } => return Ok(None),
+		Object::UnitVariant { variant, .. } => Value::String(variant_name(variant, path)?),
+		Object::DualVariantKey { name, .. } => Value::String(name.to_string()),
+		Object::NewtypeVariant { variant, value, .. }
+		| Object::TupleVariant {
+			variant,
+			fields: value,
+			..
+		}
+		| Object::StructVariant {
+			variant,
+			fields: value,
+			..
+		} => {
+			let name = variant_name(variant, path)?;
+			let mut table = toml::map::Map::new();
+			if let Some(value) = entry_value(name.clone(), value, path)? {
+				table.insert(name, value);
+			}
+			Value::Table(table)
+		}
+		Object::Seq(elements)
+		| Object::Tuple(elements)
+		| Object::TupleStruct {
+			fields: elements, ..
+		} => {
+			let mut array = vec![];
+			for (index, element) in elements.iter().enumerate() {
+				path.push(Segment::Index(index));
+				match value(element, path)? {
+					Some(value) => array.push(value),
+					None => return Err(shape_error(path, "Arrays can't contain absent values")),
+				}
+				path.pop();
+			}
+			Value::Array(array)
+		}
+		Object::Map(map) => {
+			if let [(Object::String(tag), Object::String(datetime))] = map.as_slice() {
+				if tag == DATETIME_TAG {
+					if let Ok(datetime) = datetime.parse::<Datetime>() {
+						return Ok(Some(Value::Datetime(datetime)));
+					}
+				}
+			}
+			table(map.iter().map(|(k, v)| (k, Some(v))), path)?
+		}
+		Object::FieldMap(map) => table(map.iter().map(|(k, v)| (k, v.as_ref())), path)?,
+		Object::Struct { fields, .. } => {
+			let mut table = toml::map::Map::new();
+			for (key, v) in fields {
+				if let Some(v) = v {
+					if let Some(v) = entry_value(key.to_string(), v, path)? {
+						table.insert(key.to_string(), v);
+					}
+				}
+			}
+			Value::Table(table)
+		}
+	}))
+}
+
+fn integer_value<E>(integer: Result<i64, E>, path: &[Segment]) -> Result<Value, ShapeError> {
+	integer
+		.map(Value::Integer)
+		.map_err(|_| shape_error(path, "TOML integers must fit into 64 bits (signed)"))
+}
+
+fn variant_name(variant: &Object, path: &[Segment]) -> Result<String, ShapeError> {
+	key(variant).ok_or_else(|| shape_error(path, "Enum variant names must be strings"))
+}
+
+fn key(object: &Object) -> Option<String> {
+	match object {
+		Object::String(string) => Some(string.to_string()),
+		Object::Char(char) => Some(char.to_string()),
+		Object::DualVariantKey { name, .. } => Some(name.to_string()),
+		_ => None,
+	}
+}
+
+fn entry_value(
+	key: String,
+	object: &Object,
+	path: &mut Vec<Segment>,
+) -> Result<Option<Value>, ShapeError> {
+	path.push(Segment::Key(key));
+	let value = value(object, path)?;
+	path.pop();
+	Ok(value)
+}
+
+fn table<'a, 'b: 'a>(
+	entries: impl IntoIterator<Item = (&'a Object<'b>, Option<&'a Object<'b>>)>,
+	path: &mut Vec<Segment>,
+) -> Result<Value, ShapeError> {
+	let mut table = toml::map::Map::new();
+	for (k, v) in entries {
+		let k = key(k).ok_or_else(|| {
+			shape_error(
+				path,
+				"Table keys must be strings (try stringifying them with -s)",
+			)
+		})?;
+		if let Some(v) = v {
+			if let Some(v) = entry_value(k.clone(), v, path)? {
+				table.insert(k, v);
+			}
+		}
+	}
+	Ok(Value::Table(table))
+}
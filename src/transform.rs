@@ -0,0 +1,488 @@
+//! Transformations applied to documents between reading and writing.
+//!
+//! Each transformation is a [`Visitor`] that only handles the parts of an [`Object`] it cares about,
+//! while [`walk`] takes care of reaching them.
+
+use crate::{compare, shape::shape_error, Encoding, Error, Filter, KeyPath, Patch};
+use serde_object::Object;
+use std::{borrow::Cow, convert::TryFrom};
+use tap::Pipe as _;
+
+/// A transformation applied to the whole document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transform {
+	/// Stringify bytes and non-string keys and variant names into strings where possible.
+	///
+	/// Apply this repeatedly to try several encodings in order.
+	Stringify(Encoding),
+
+	/// Case-insensitively convert unit variants with name `true` or `false` into booleans.
+	EnumBools,
+
+	/// Convert strings back into byte arrays, reversing [`Transform::Stringify`].
+	///
+	/// With a `path`, all strings there are converted and must be valid in `encoding`.
+	/// Otherwise, only strings prefixed with the encoding's name and a colon, like `"hex:c0ffee"`, are converted.
+	BytesFrom {
+		encoding: Encoding,
+		path: Option<KeyPath>,
+	},
+
+	/// Sort the entries of maps and structs by key.
+	///
+	/// Numeric keys are sorted by value, all others by their text.
+	SortKeys,
+
+	/// Replace the document with the one value at a path, failing if there's none.
+	Select(KeyPath),
+
+	/// Replace the document with the output of a jq-like [`Filter`], which must be exactly one value.
+	Filter(Filter),
+
+	/// Apply a JSON Patch or JSON Merge Patch to the document.
+	Patch(Patch),
+}
+
+impl Transform {
+	pub(crate) fn apply(&self, object: &mut Object) -> Result<(), Error> {
+		match self {
+			&Transform::Stringify(encoding) => walk(object, &mut Stringify(encoding)),
+			Transform::EnumBools => walk(object, &mut EnumBools),
+			Transform::SortKeys => walk(object, &mut SortKeys),
+			Transform::Filter(filter) => {
+				let error = |message: String| Error::Transform {
+					transform: "filter",
+					source: message.into(),
+				};
+				let mut outputs = filter.run(object).map_err(error)?;
+				if outputs.len() != 1 {
+					return Err(error(format!(
+						"The filter produced {} values, but only one document can be written. Wrap it in `[…]` to collect them.",
+						outputs.len()
+					)));
+				}
+				*object = outputs.remove(0);
+			}
+			Transform::Patch(patch) => {
+				patch.apply(object).map_err(|message| Error::Transform {
+					transform: "patch",
+					source: message.into(),
+				})?;
+			}
+			Transform::Select(path) => {
+				*object = path.select(object).map_err(|error| Error::Transform {
+					transform: "select",
+					source: Box::new(error),
+				})?;
+			}
+			&Transform::BytesFrom {
+				encoding,
+				path: Some(ref path),
+			} => {
+				let mut result = Ok(());
+				path.for_each_mut(object, &mut |object, path| {
+					if let (Ok(()), Object::String(string)) = (&result, &*object) {
+						match encode(string, encoding) {
+							Ok(bytes) => *object = Object::ByteArray(Cow::Owned(bytes)),
+							Err(message) => result = Err(shape_error(path, message)),
+						}
+					}
+				});
+				result.map_err(|error| Error::Transform {
+					transform: "bytes-from",
+					source: Box::new(error),
+				})?;
+			}
+			&Transform::BytesFrom {
+				encoding,
+				path: None,
+			} => {
+				let mut visitor = BytesFrom {
+					encoding,
+					error: None,
+				};
+				walk(object, &mut visitor);
+				if let Some(message) = visitor.error {
+					return Err(Error::Transform {
+						transform: "bytes-from",
+						source: message.into(),
+					});
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Hooks called by [`walk`]. All of them do nothing by default.
+///
+/// Each part of a document is visited only after everything inside it,
+/// so replacing it doesn't cause its replacement to be visited again.
+pub(crate) trait Visitor {
+	/// Called on every value, including keys and variant names, after their other hooks.
+	fn value(&mut self, _object: &mut Object) {}
+
+	/// Called on map keys.
+	fn key(&mut self, _key: &mut Object) {}
+
+	/// Called on enum variant names (or indices).
+	fn variant(&mut self, _variant: &mut Object) {}
+}
+
+/// Deeply copies `object`, which doesn't implement [`Clone`].
+pub(crate) fn duplicate(object: &Object) -> Object<'static> {
+	let name = |name: &Cow<str>| Cow::Owned(name.to_string());
+	let boxed = |object: &Object| Box::new(duplicate(object));
+	let all = |objects: &[Object]| objects.iter().map(duplicate).collect();
+	match object {
+		Object::Bool(value) => Object::Bool(*value),
+		Object::I8(value) => Object::I8(*value),
+		Object::I16(value) => Object::I16(*value),
+		Object::I32(value) => Object::I32(*value),
+		Object::I64(value) => Object::I64(*value),
+		Object::I128(value) => Object::I128(*value),
+		Object::U8(value) => Object::U8(*value),
+		Object::U16(value) => Object::U16(*value),
+		Object::U32(value) => Object::U32(*value),
+		Object::U64(value) => Object::U64(*value),
+		Object::U128(value) => Object::U128(*value),
+		Object::F32(value) => Object::F32(*value),
+		Object::F64(value) => Object::F64(*value),
+		Object::Char(value) => Object::Char(*value),
+		Object::String(value) => Object::String(name(value)),
+		Object::ByteArray(bytes) => Object::ByteArray(Cow::Owned(bytes.to_vec())),
+		Object::Option(value) => Object::Option(value.as_deref().map(boxed)),
+		Object::Unit => Object::Unit,
+		Object::UnitStruct { name: n } => Object::UnitStruct { name: name(n) },
+		Object::UnitVariant { name: n, variant } => Object::UnitVariant {
+			name: name(n),
+			variant: boxed(variant),
+		},
+		Object::NewtypeStruct { name: n, value } => Object::NewtypeStruct {
+			name: name(n),
+			value: boxed(value),
+		},
+		Object::NewtypeVariant {
+			name: n,
+			variant,
+			value,
+		} => Object::NewtypeVariant {
+			name: name(n),
+			variant: boxed(variant),
+			value: boxed(value),
+		},
+		Object::Seq(elements) => Object::Seq(all(elements)),
+		Object::Tuple(elements) => Object::Tuple(all(elements)),
+		Object::TupleStruct { name: n, fields } => Object::TupleStruct {
+			name: name(n),
+			fields: all(fields),
+		},
+		Object::TupleVariant {
+			name: n,
+			variant,
+			fields,
+		} => Object::TupleVariant {
+			name: name(n),
+			variant: boxed(variant),
+			fields: boxed(fields),
+		},
+		Object::Map(map) => Object::Map(
+			map.iter()
+				.map(|(k, v)| (duplicate(k), duplicate(v)))
+				.collect(),
+		),
+		Object::Struct { name: n, fields } => Object::Struct {
+			name: name(n),
+			fields: fields
+				.iter()
+				.map(|(k, v)| (name(k), v.as_ref().map(duplicate)))
+				.collect(),
+		},
+		Object::StructVariant {
+			name: n,
+			variant,
+			fields,
+		} => Object::StructVariant {
+			name: name(n),
+			variant: boxed(variant),
+			fields: boxed(fields),
+		},
+		Object::DualVariantKey { index, name: n } => Object::DualVariantKey {
+			index: *index,
+			name: name(n),
+		},
+		Object::FieldMap(map) => Object::FieldMap(
+			map.iter()
+				.map(|(k, v)| (duplicate(k), v.as_ref().map(duplicate)))
+				.collect(),
+		),
+	}
+}
+
+/// Visits `object` and everything inside it with `visitor`, depth-first.
+pub(crate) fn walk<V: Visitor + ?Sized>(object: &mut Object, visitor: &mut V) {
+	match object {
+		Object::Bool(_)
+		| Object::I8(_)
+		| Object::I16(_)
+		| Object::I32(_)
+		| Object::I64(_)
+		| Object::I128(_)
+		| Object::U8(_)
+		| Object::U16(_)
+		| Object::U32(_)
+		| Object::U64(_)
+		| Object::U128(_)
+		| Object::F32(_)
+		| Object::F64(_)
+		| Object::Char(_)
+		| Object::String(_)
+		| Object::ByteArray(_)
+		| Object::Option(None)
+		| Object::Unit
+		| Object::UnitStruct { .. }
+		| Object::DualVariantKey { .. } => (),
+		Object::Option(Some(value)) | Object::NewtypeStruct { value, .. } => walk(value, visitor),
+		Object::UnitVariant { variant, .. } => walk_variant(variant, visitor),
+		Object::NewtypeVariant {
+			variant,
+			value: fields,
+			..
+		}
+		| Object::TupleVariant {
+			variant, fields, ..
+		}
+		| Object::StructVariant {
+			variant, fields, ..
+		} => {
+			walk_variant(variant, visitor);
+			walk(fields, visitor);
+		}
+		Object::Seq(elements)
+		| Object::Tuple(elements)
+		| Object::TupleStruct {
+			fields: elements, ..
+		} => {
+			for element in elements {
+				walk(element, visitor);
+			}
+		}
+		Object::Map(map) => {
+			for (k, v) in map {
+				walk_key(k, visitor);
+				walk(v, visitor);
+			}
+		}
+		Object::Struct { fields, .. } => {
+			for (_, v) in fields {
+				if let Some(v) = v {
+					walk(v, visitor);
+				}
+			}
+		}
+		Object::FieldMap(map) => {
+			for (k, v) in map {
+				walk_key(k, visitor);
+				if let Some(v) = v {
+					walk(v, visitor);
+				}
+			}
+		}
+	}
+	visitor.value(object);
+}
+
+fn walk_key<V: Visitor + ?Sized>(key: &mut Object, visitor: &mut V) {
+	walk(key, visitor);
+	visitor.key(key);
+}
+
+fn walk_variant<V: Visitor + ?Sized>(variant: &mut Object, visitor: &mut V) {
+	walk(variant, visitor);
+	visitor.variant(variant);
+}
+
+struct Stringify(Encoding);
+
+impl Visitor for Stringify {
+	fn value(&mut self, object: &mut Object) {
+		if let Object::ByteArray(_) = object {
+			stringify_value(object, self.0);
+		}
+	}
+
+	fn key(&mut self, key: &mut Object) {
+		stringify_value(key, self.0);
+	}
+
+	fn variant(&mut self, variant: &mut Object) {
+		stringify_value(variant, self.0);
+	}
+}
+
+fn stringify_value(object: &mut Object, encoding: Encoding) {
+	*object = Object::String(
+		#[allow(clippy::match_same_arms)]
+		match object {
+			Object::Bool(value) => value.to_string(),
+			Object::I8(value) => value.to_string(),
+			Object::I16(value) => value.to_string(),
+			Object::I32(value) => value.to_string(),
+			Object::I64(value) => value.to_string(),
+			Object::I128(value) => value.to_string(),
+			Object::U8(value) => value.to_string(),
+			Object::U16(value) => value.to_string(),
+			Object::U32(value) => value.to_string(),
+			Object::U64(value) => value.to_string(),
+			Object::U128(value) => value.to_string(),
+			Object::F32(value) => value.to_string(),
+			Object::F64(value) => value.to_string(),
+			Object::Char(value) => value.to_string(),
+			Object::String(_) => return,
+			Object::ByteArray(bytes) => match decode(bytes, encoding) {
+				Some(string) => string,
+				None => {
+					return;
+				}
+			},
+			Object::Option(option) => {
+				if let Some(obj) = option.as_deref_mut() {
+					stringify_value(obj, encoding);
+				}
+				return;
+			}
+			Object::Unit
+			| Object::UnitStruct { .. }
+			| Object::UnitVariant { .. }
+			| Object::NewtypeStruct { .. }
+			| Object::NewtypeVariant { .. }
+			| Object::Seq(_)
+			| Object::Tuple(_)
+			| Object::TupleStruct { .. }
+			| Object::TupleVariant { .. }
+			| Object::Map(_)
+			| Object::Struct { .. }
+			| Object::StructVariant { .. } => {
+				return;
+			}
+			Object::DualVariantKey { index: _, name } => (*name).to_string(),
+			Object::FieldMap(_) => {
+				return;
+			}
+		}
+		.pipe(Cow::Owned),
+	);
+}
+
+struct EnumBools;
+
+impl Visitor for EnumBools {
+	fn value(&mut self, object: &mut Object) {
+		if let Object::UnitVariant { variant, .. } = object {
+			let bool = match variant.as_ref() {
+				Object::String(name) if name.eq_ignore_ascii_case("true") => true,
+				Object::String(name) if name.eq_ignore_ascii_case("false") => false,
+				Object::ByteArray(name) if name.eq_ignore_ascii_case(b"true") => true,
+				Object::ByteArray(name) if name.eq_ignore_ascii_case(b"false") => false,
+				_ => return,
+			};
+			*object = Object::Bool(bool);
+		}
+	}
+}
+
+struct SortKeys;
+
+impl Visitor for SortKeys {
+	fn value(&mut self, object: &mut Object) {
+		match object {
+			Object::Map(map) => map.sort_by(|(a, _), (b, _)| compare::key_cmp(a, b)),
+			Object::FieldMap(map) => map.sort_by(|(a, _), (b, _)| compare::key_cmp(a, b)),
+			Object::Struct { fields, .. } => fields.sort_by(|(a, _), (b, _)| a.cmp(b)),
+			_ => (),
+		}
+	}
+}
+
+fn decode(bytes: &[u8], encoding: Encoding) -> Option<String> {
+	match encoding {
+		Encoding::Utf8 => std::str::from_utf8(bytes).ok().map(str::to_string),
+		Encoding::Latin1 => Some(bytes.iter().map(|&byte| char::from(byte)).collect()),
+		Encoding::Cp1252 => Some(
+			encoding_rs::WINDOWS_1252
+				.decode_without_bom_handling(bytes)
+				.0
+				.into_owned(),
+		),
+		Encoding::Utf16Le | Encoding::Utf16Be => {
+			// A byte order mark stays in the string as U+FEFF, so `encode` writes it back.
+			let (utf16, reversed_bom) = match encoding {
+				Encoding::Utf16Le => (encoding_rs::UTF_16LE, [0xFE, 0xFF]),
+				_ => (encoding_rs::UTF_16BE, [0xFF, 0xFE]),
+			};
+			if bytes.starts_with(&reversed_bom) {
+				return None;
+			}
+			utf16
+				.decode_without_bom_handling_and_without_replacement(bytes)
+				.map(Cow::into_owned)
+		}
+		Encoding::Hex => Some(hex::encode(bytes)),
+		Encoding::Base64 => Some(base64::encode(bytes)),
+	}
+}
+
+/// Converts prefixed strings into byte arrays.
+struct BytesFrom {
+	encoding: Encoding,
+	error: Option<String>,
+}
+
+impl Visitor for BytesFrom {
+	fn value(&mut self, object: &mut Object) {
+		if let Object::String(string) = object {
+			if let Some(rest) = string
+				.strip_prefix(<&str>::from(self.encoding))
+				.and_then(|rest| rest.strip_prefix(':'))
+			{
+				match encode(rest, self.encoding) {
+					Ok(bytes) => *object = Object::ByteArray(Cow::Owned(bytes)),
+					Err(message) => {
+						self.error.get_or_insert(message);
+					}
+				}
+			}
+		}
+	}
+}
+
+/// The reverse of [`decode`].
+fn encode(string: &str, encoding: Encoding) -> Result<Vec<u8>, String> {
+	let unrepresentable =
+		|c: char| format!("`{}` can't be represented in {}", c, <&str>::from(encoding));
+	match encoding {
+		Encoding::Utf8 => Ok(string.as_bytes().to_vec()),
+		Encoding::Latin1 => string
+			.chars()
+			.map(|c| u8::try_from(u32::from(c)).map_err(|_| unrepresentable(c)))
+			.collect(),
+		Encoding::Cp1252 => {
+			let (bytes, _, unmappable) = encoding_rs::WINDOWS_1252.encode(string);
+			if unmappable {
+				// `encoding_rs` doesn't report which character it was.
+				let c = string
+					.chars()
+					.find(|&c| encoding_rs::WINDOWS_1252.encode(&c.to_string()).2)
+					.unwrap_or(char::REPLACEMENT_CHARACTER);
+				return Err(unrepresentable(c));
+			}
+			Ok(bytes.into_owned())
+		}
+		Encoding::Utf16Le => Ok(string.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+		Encoding::Utf16Be => Ok(string.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+		Encoding::Hex => hex::decode(string).map_err(|error| format!("Invalid hex: {}", error)),
+		Encoding::Base64 => {
+			base64::decode(string).map_err(|error| format!("Invalid Base64: {}", error))
+		}
+	}
+}
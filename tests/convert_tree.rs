@@ -0,0 +1,198 @@
+use reserde::{convert_tree, In, Options, Out};
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+/// A fresh directory for this test, filled with `files`.
+fn tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("reserde-{}-{}", name, std::process::id()));
+	fs::remove_dir_all(&dir).ok();
+	for (path, content) in files {
+		let path = dir.join("src").join(path);
+		fs::create_dir_all(path.parent().unwrap()).unwrap();
+		fs::write(path, content).unwrap();
+	}
+	dir
+}
+
+fn summary(dir: &Path, fail_fast: bool, jobs: usize) -> Vec<(String, Result<(), String>)> {
+	convert_tree(
+		&dir.join("src"),
+		&dir.join("out"),
+		None,
+		Out::Json,
+		fail_fast,
+		jobs,
+		&Options::default(),
+	)
+	.unwrap()
+	.into_iter()
+	.map(|conversion| {
+		(
+			conversion
+				.target
+				.strip_prefix(dir)
+				.unwrap()
+				.to_string_lossy()
+				.replace('\\', "/"),
+			conversion.result.map_err(|error| error.to_string()),
+		)
+	})
+	.collect()
+}
+
+#[test]
+fn mirrors_tree() {
+	let dir = tree(
+		"mirrors-tree",
+		&[
+			("a.yaml", "name: a\n"),
+			("nested/b.toml", "x = 1\n"),
+			("nested/c.json", "{bad"),
+			("nested/deeper/d.yml", "- 1\n"),
+			("notes.txt", "not a document"),
+			(".hidden/e.json", "{}"),
+		],
+	);
+
+	assert_eq!(
+		summary(&dir, false, 1),
+		[
+			("out/a.json".to_string(), Ok(())),
+			("out/nested/b.json".to_string(), Ok(())),
+			(
+				"out/nested/c.json".to_string(),
+				Err(
+					"Failed to deserialize input as json: key must be a string at line 1 column 2"
+						.to_string()
+				)
+			),
+			("out/nested/deeper/d.json".to_string(), Ok(())),
+			(
+				"out/notes.json".to_string(),
+				Err(
+					"Invalid options: Unknown input file extension `.txt`. Specify the format with --in."
+						.to_string()
+				)
+			),
+		]
+	);
+	assert_eq!(
+		fs::read_to_string(dir.join("out/nested/b.json")).unwrap(),
+		r#"{"x":1}"#
+	);
+	assert!(!dir.join("out/nested/c.json").exists());
+
+	assert_eq!(summary(&dir, true, 1).len(), 3);
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unknown_extensions() {
+	let dir = tree(
+		"unknown-extensions",
+		&[("Makefile", "all: build\n"), ("notes.json", "{}")],
+	);
+	let skipped = "Invalid options: The file has no extension to choose the input format by. Specify it with --in.";
+	assert_eq!(
+		summary(&dir, false, 1),
+		[
+			("out/Makefile.json".to_string(), Err(skipped.to_string())),
+			("out/notes.json".to_string(), Ok(())),
+		]
+	);
+	assert!(!dir.join("out/Makefile.json").exists());
+	assert_eq!(summary(&dir, true, 1).len(), 1);
+
+	let conversions = convert_tree(
+		&dir.join("src"),
+		&dir.join("out"),
+		Some(In::Yaml),
+		Out::Json,
+		false,
+		1,
+		&Options::default(),
+	)
+	.unwrap();
+	assert_eq!(
+		conversions[0]
+			.in_format
+			.map(|format| format.to_string())
+			.as_deref(),
+		Some("yaml")
+	);
+	assert!(conversions[0].result.is_ok());
+	assert_eq!(
+		fs::read_to_string(dir.join("out/Makefile.json")).unwrap(),
+		r#"{"all":"build"}"#
+	);
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn parallel() {
+	let files: Vec<_> = (0..100)
+		.map(|i| {
+			(
+				format!("{}/{:02}.json", i % 7, i),
+				if i % 10 == 3 { "{bad" } else { "[1, 2]" },
+			)
+		})
+		.collect();
+	let dir = tree(
+		"parallel",
+		&files
+			.iter()
+			.map(|(path, content)| (path.as_str(), *content))
+			.collect::<Vec<_>>(),
+	);
+
+	let sequential = summary(&dir, false, 1);
+	assert_eq!(sequential.len(), 100);
+	assert_eq!(
+		sequential
+			.iter()
+			.filter(|(_, result)| result.is_err())
+			.count(),
+		10
+	);
+	for _ in 0..3 {
+		assert_eq!(summary(&dir, false, 8), sequential);
+	}
+
+	assert_eq!(
+		convert_tree(
+			&dir.join("src"),
+			&dir.join("out"),
+			None,
+			Out::Json,
+			false,
+			0,
+			&Options::default(),
+		)
+		.unwrap_err()
+		.to_string(),
+		"Invalid options: Can't convert files with 0 jobs."
+	);
+
+	let fail_fast = summary(&dir, true, 8);
+	assert!(fail_fast.iter().any(|(_, result)| result.is_err()));
+	assert!(fail_fast.windows(2).all(|pair| pair[0].0 < pair[1].0));
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn conflicting_targets() {
+	let dir = tree("conflicting-targets", &[("a.json", "1"), ("a.yaml", "2\n")]);
+	assert_eq!(
+		summary(&dir, false, 1)[1].1.as_ref().unwrap_err(),
+		&format!(
+			"Invalid options: {} is converted from {} already.",
+			dir.join("out").join("a.json").display(),
+			dir.join("src").join("a.json").display()
+		)
+	);
+	assert_eq!(fs::read_to_string(dir.join("out/a.json")).unwrap(), "1");
+	fs::remove_dir_all(dir).unwrap();
+}
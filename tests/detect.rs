@@ -0,0 +1,53 @@
+use reserde::{Error, In, Out};
+use std::path::Path;
+
+#[test]
+fn from_extension() {
+	let detect = |path: &str| In::from_extension(Path::new(path)).map(|format| format.to_string());
+	assert_eq!(detect("ubuntu.iso.torrent").as_deref(), Some("bencode"));
+	assert_eq!(detect("config.YML").as_deref(), Some("yaml"));
+	assert_eq!(detect("Cargo.toml").as_deref(), Some("toml"));
+	assert_eq!(detect("notes.txt"), None);
+	assert_eq!(detect("README"), None);
+}
+
+#[test]
+fn sniff() {
+	let sniff = |prefix: &[u8]| In::sniff(prefix).unwrap().to_string();
+	assert_eq!(sniff(b"\xd9\xd9\xf7\xa0"), "cbor");
+	assert_eq!(sniff(b"\xa1\x61a\x01"), "cbor");
+	assert_eq!(sniff(b"\xde\x00\x00"), "msgpack");
+	assert_eq!(sniff(b"d8:announce35:udp://tracker.example"), "bencode");
+	assert_eq!(sniff(b"li1ei2ee"), "bencode");
+	assert_eq!(sniff(b"de"), "bencode");
+	assert_eq!(sniff(b"d1: hello\nd2: world\n"), "yaml");
+	assert_eq!(sniff(b"l1: x\n"), "yaml");
+	assert_eq!(sniff(b"d8:announce"), "yaml");
+	assert_eq!(sniff(b"de: 1\n"), "yaml");
+	assert_eq!(sniff(b"\xef\xbb\xbf<?xml version=\"1.0\"?>"), "xml");
+	assert_eq!(sniff(b"\n  {\"a\": 1}"), "json");
+	assert_eq!(sniff(b"description: not bencode"), "yaml");
+	assert_eq!(sniff(b"list:\n  - 1"), "yaml");
+
+	let error = In::sniff(b"\x81\x01").unwrap_err();
+	assert!(matches!(error, Error::Options(_)));
+	assert_eq!(
+		error.to_string(),
+		"Invalid options: Couldn't detect the input format, which may be any of: cbor, msgpack. Specify it with --in."
+	);
+}
+
+#[test]
+fn out_from_extension() {
+	let choose = |path: &str| Out::from_extension(Path::new(path)).map(|format| format.to_string());
+	assert_eq!(choose("a.yaml").unwrap(), "yaml");
+	assert_eq!(choose("data.MPK").unwrap(), "msgpack");
+
+	let error = choose("a.txt").unwrap_err();
+	assert_eq!(error.exit_code(), 1);
+	assert_eq!(
+		error.to_string(),
+		"Invalid options: Unknown output file extension `.txt`. Specify the format with --out."
+	);
+	assert!(matches!(choose("Makefile"), Err(Error::Options(_))));
+}
@@ -0,0 +1,82 @@
+use reserde::{diff, DiffFormat, In, Options, Transform};
+
+const A: &str = "name: app\nport: 8080\nflags: [a, b, c]\nnested: {x: 1, y: 2}\n";
+const B: &str =
+	r#"{"port": 8081, "name": "app", "flags": ["a", "c"], "nested": {"x": 1.0, "z": [true]}}"#;
+
+fn run(a: &str, b: &str, diff_format: DiffFormat, options: &Options) -> (bool, String) {
+	let mut output = vec![];
+	let differ = diff(
+		a.as_bytes(),
+		In::Yaml,
+		b.as_bytes(),
+		In::Json,
+		&mut output,
+		diff_format,
+		options,
+	)
+	.unwrap();
+	(differ, String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn text() {
+	assert_eq!(
+		run(A, B, DiffFormat::Text, &Options::default()),
+		(
+			true,
+			concat!(
+				"changed from 8080 to 8081 (at `port`)\n",
+				"changed from \"b\" to \"c\" (at `flags[1]`)\n",
+				"removed (at `flags[2]`)\n",
+				"an integer became a float (at `nested.x`)\n",
+				"removed (at `nested.y`)\n",
+				"added [true] (at `nested.z`)\n",
+				"key order changed from `name`, `port`, `flags`, `nested` to `port`, `name`, `flags`, `nested`\n",
+			)
+			.to_string()
+		)
+	);
+
+	let sorted = Options {
+		transforms: vec![Transform::SortKeys],
+		..Options::default()
+	};
+	assert_eq!(
+		run(
+			"b: 1\na: 2\n",
+			r#"{"a": 2, "b": 1}"#,
+			DiffFormat::Text,
+			&sorted
+		),
+		(false, String::new())
+	);
+}
+
+#[test]
+fn json_patch() {
+	let (differ, patch) = run(A, B, DiffFormat::JsonPatch, &Options::default());
+	assert!(differ);
+	assert_eq!(
+		patch,
+		concat!(
+			r#"[{"op":"replace","path":"/port","value":8081},"#,
+			r#"{"op":"replace","path":"/flags/1","value":"c"},"#,
+			r#"{"op":"remove","path":"/flags/2"},"#,
+			r#"{"op":"replace","path":"/nested/x","value":1.0},"#,
+			r#"{"op":"remove","path":"/nested/y"},"#,
+			r#"{"op":"add","path":"/nested/z","value":[true]}]"#,
+		)
+	);
+
+	let (_, patch) = run(
+		"a/b: [1, 2, 3, 4]\n",
+		r#"{"a/b": [1]}"#,
+		DiffFormat::JsonPatch,
+		&Options::default(),
+	);
+	assert_eq!(
+		patch,
+		r#"[{"op":"remove","path":"/a~1b/3"},{"op":"remove","path":"/a~1b/2"},{"op":"remove","path":"/a~1b/1"}]"#
+	);
+}
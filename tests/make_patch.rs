@@ -0,0 +1,92 @@
+use reserde::{make_patch, transcode, In, Options, Out, PatchFormat, Transform};
+
+const FROM: &str = "name: app\nserver:\n  port: 8080\n  hosts: [a, b]\nold: 1\n";
+const TO: &str = r#"{"name": "app", "server": {"port": 9090, "hosts": ["a"]}, "new": {"x": true}}"#;
+
+fn run(
+	from: &str,
+	to: &str,
+	out_format: Out,
+	patch_format: PatchFormat,
+) -> Result<Vec<u8>, String> {
+	let mut output = vec![];
+	make_patch(
+		from.as_bytes(),
+		In::Yaml,
+		to.as_bytes(),
+		In::Json,
+		&mut output,
+		out_format,
+		&Options {
+			patch_format,
+			..Options::default()
+		},
+	)
+	.map(|()| output)
+	.map_err(|error| error.to_string())
+}
+
+/// Applies the JSON `patch` to [`FROM`].
+fn apply(patch: &[u8]) -> String {
+	let mut output = vec![];
+	transcode(
+		FROM.as_bytes(),
+		In::Yaml,
+		&mut output,
+		Out::Json,
+		&Options {
+			transforms: vec![Transform::Patch(
+				std::str::from_utf8(patch).unwrap().parse().unwrap(),
+			)],
+			..Options::default()
+		},
+	)
+	.unwrap();
+	String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn json_patch() {
+	let patch = run(FROM, TO, Out::Json, PatchFormat::JsonPatch).unwrap();
+	assert_eq!(
+		patch,
+		concat!(
+			r#"[{"op":"replace","path":"/server/port","value":9090},"#,
+			r#"{"op":"remove","path":"/server/hosts/1"},"#,
+			r#"{"op":"remove","path":"/old"},"#,
+			r#"{"op":"add","path":"/new","value":{"x":true}}]"#,
+		)
+		.as_bytes()
+	);
+	assert_eq!(
+		apply(&patch),
+		r#"{"name":"app","server":{"port":9090,"hosts":["a"]},"new":{"x":true}}"#
+	);
+
+	assert_eq!(
+		run(FROM, TO, Out::Yaml, PatchFormat::JsonPatch).unwrap(),
+		b"---\n- op: replace\n  path: /server/port\n  value: 9090\n- op: remove\n  path: /server/hosts/1\n- op: remove\n  path: /old\n- op: add\n  path: /new\n  value:\n    x: true\n"
+	);
+}
+
+#[test]
+fn merge_patch() {
+	let patch = run(FROM, TO, Out::Json, PatchFormat::MergePatch).unwrap();
+	assert_eq!(
+		patch,
+		br#"{"server":{"port":9090,"hosts":["a"]},"old":null,"new":{"x":true}}"#
+	);
+	assert_eq!(
+		apply(&patch),
+		r#"{"name":"app","server":{"port":9090,"hosts":["a"]},"new":{"x":true}}"#
+	);
+
+	assert_eq!(
+		run(TO, TO, Out::Json, PatchFormat::MergePatch).unwrap(),
+		b"{}"
+	);
+	assert_eq!(
+		run(FROM, r#"{"name": null}"#, Out::Json, PatchFormat::MergePatch).unwrap_err(),
+		"Invalid options: Merge Patches can't set values to null (at `name`). Use a JSON Patch instead."
+	);
+}
@@ -0,0 +1,88 @@
+use reserde::{merge, In, Merge, Options, Out, Transform};
+
+const BASE: &str = "name: app\nserver:\n  port: 8080\n  hosts: [a, b]\ndatabase: ~\n";
+const OVERRIDE: &str = r#"{"server": {"hosts": ["c"], "port": 9090}, "database": {"url": "db"}}"#;
+
+fn run(inputs: &[(&str, In)], options: &Options) -> Result<String, String> {
+	let mut output = vec![];
+	merge(
+		inputs
+			.iter()
+			.map(|&(input, in_format)| (input.as_bytes(), in_format)),
+		&mut output,
+		Out::Json,
+		options,
+	)
+	.map(|()| String::from_utf8(output).unwrap())
+	.map_err(|error| error.to_string())
+}
+
+#[test]
+fn deep() {
+	assert_eq!(
+		run(
+			&[
+				(BASE, In::Yaml),
+				(OVERRIDE, In::Json),
+				("[server]\ntls = true\n", In::Toml)
+			],
+			&Options::default()
+		)
+		.unwrap(),
+		r#"{"name":"app","server":{"port":9090,"hosts":["c"],"tls":true},"database":{"url":"db"}}"#
+	);
+
+	assert_eq!(
+		run(
+			&[(BASE, In::Yaml), (OVERRIDE, In::Json)],
+			&Options {
+				merge: Merge::Append,
+				transforms: vec![Transform::Select("server".parse().unwrap())],
+				..Options::default()
+			}
+		)
+		.unwrap(),
+		r#"{"port":9090,"hosts":["a","b","c"]}"#
+	);
+
+	assert_eq!(
+		run(
+			&[
+				(BASE, In::Yaml),
+				(r#"{"name": null, "server": {"hosts": null}}"#, In::Json),
+				(
+					"server: null
+",
+					In::Yaml
+				),
+			],
+			&Options::default()
+		)
+		.unwrap(),
+		r#"{"name":null,"server":null,"database":null}"#
+	);
+}
+
+#[test]
+fn conflicts() {
+	let error = merge(
+		vec![
+			(BASE.as_bytes(), In::Yaml),
+			(r#"{"server": {"hosts": "c"}}"#.as_bytes(), In::Json),
+		],
+		vec![],
+		Out::Json,
+		&Options::default(),
+	)
+	.unwrap_err();
+	assert_eq!(error.exit_code(), 8);
+	assert_eq!(
+		error.to_string(),
+		"Failed to merge input 2: Can't merge a string into a sequence (at `server.hosts`)"
+	);
+
+	assert_eq!(
+		run(&[], &Options::default()).unwrap_err(),
+		"Invalid options: There are no documents to merge."
+	);
+}
@@ -0,0 +1,134 @@
+use reserde::{join, split, Documents, In, Join, Options, Out, Transform};
+
+const BUNDLE: &str =
+	"items:\n  - metadata: {name: web}\n    port: 80\n  - metadata: {name: db}\n    port: 5432\n";
+
+fn names(parts: &[(String, Vec<u8>)]) -> Vec<&str> {
+	parts.iter().map(|(name, _)| name.as_str()).collect()
+}
+
+#[test]
+fn split_by_index_and_key() {
+	let select = vec![Transform::Select("items".parse().unwrap())];
+	let parts = split(
+		BUNDLE.as_bytes(),
+		In::Yaml,
+		Out::Json,
+		&Options {
+			transforms: select.clone(),
+			..Options::default()
+		},
+	)
+	.unwrap();
+	assert_eq!(names(&parts), ["0", "1"]);
+	assert_eq!(parts[0].1, br#"{"metadata":{"name":"web"},"port":80}"#);
+
+	let parts = split(
+		BUNDLE.as_bytes(),
+		In::Yaml,
+		Out::Yaml,
+		&Options {
+			transforms: select.clone(),
+			split_by: Some("/metadata/name".parse().unwrap()),
+			..Options::default()
+		},
+	)
+	.unwrap();
+	assert_eq!(names(&parts), ["web", "db"]);
+	assert_eq!(parts[1].1, b"---\nmetadata:\n  name: db\nport: 5432\n");
+
+	let parts = split(
+		"{\"a\": 1}\n{\"a\": 2}\n".repeat(6).as_bytes(),
+		In::Json,
+		Out::Toml,
+		&Options {
+			documents: Documents::Slurp,
+			..Options::default()
+		},
+	)
+	.unwrap();
+	assert_eq!(names(&parts)[..3], ["00", "01", "02"]);
+	assert_eq!(parts[11], ("11".to_string(), b"a = 2\n".to_vec()));
+
+	let error = |options: &Options| {
+		split(BUNDLE.as_bytes(), In::Yaml, Out::Json, options)
+			.unwrap_err()
+			.to_string()
+	};
+	assert_eq!(
+		split(
+			&b"web: {port: 80}\nproxy: {port: 80}\n"[..],
+			In::Yaml,
+			Out::Json,
+			&Options {
+				split_by: Some("port".parse().unwrap()),
+				..Options::default()
+			}
+		)
+		.unwrap_err()
+		.to_string(),
+		"Failed to apply split: Several parts are named `80`"
+	);
+	assert_eq!(
+		error(&Options {
+			transforms: select,
+			split_by: Some("metadata".parse().unwrap()),
+			..Options::default()
+		}),
+		"Failed to apply split: Can't name part `0` after a map (at `metadata`)"
+	);
+	assert_eq!(
+		error(&Options {
+			transforms: vec![Transform::Select("items[0].port".parse().unwrap())],
+			..Options::default()
+		}),
+		"Failed to apply split: Can only split sequences, maps and structs, not an integer"
+	);
+}
+
+#[test]
+fn join_documents() {
+	let run = |join_into: Join| {
+		let mut output = vec![];
+		join(
+			vec![
+				("web".to_string(), &b"port: 80\n"[..], In::Yaml),
+				("db".to_string(), &b"port = 5432\n"[..], In::Toml),
+			],
+			&mut output,
+			Out::Json,
+			&Options {
+				join: join_into,
+				..Options::default()
+			},
+		)
+		.map(|()| String::from_utf8(output).unwrap())
+		.map_err(|error| error.to_string())
+	};
+	assert_eq!(
+		run(Join::Sequence).unwrap(),
+		r#"[{"port":80},{"port":5432}]"#
+	);
+	assert_eq!(
+		run(Join::Map).unwrap(),
+		r#"{"web":{"port":80},"db":{"port":5432}}"#
+	);
+
+	assert_eq!(
+		join(
+			vec![
+				("a".to_string(), &b"1"[..], In::Json),
+				("a".to_string(), &b"2"[..], In::Json),
+			],
+			vec![],
+			Out::Json,
+			&Options {
+				join: Join::Map,
+				..Options::default()
+			},
+		)
+		.unwrap_err()
+		.to_string(),
+		"Invalid options: Several documents are named `a`."
+	);
+}
@@ -0,0 +1,1090 @@
+use codespan_reporting::term::termcolor::Buffer;
+use reserde::{
+	transcode, Diagnostics, Documents, Encoding, Error, Filter, In, KeyPath, Options, Out, Patch,
+	Reporter, Schema, Strategy, TomlDatetimes, Transform,
+};
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn json_to_yaml() {
+	let mut output = vec![];
+	transcode(
+		r#"{"list": [1, 2], "flag": true}"#.as_bytes(),
+		In::Json,
+		&mut output,
+		Out::Yaml,
+		&Options::default(),
+	)
+	.unwrap();
+	assert_eq!(
+		String::from_utf8(output).unwrap(),
+		"---\nlist:\n  - 1\n  - 2\nflag: true\n"
+	);
+}
+
+#[test]
+fn stringify_bencode() {
+	let mut output = vec![];
+	transcode(
+		&b"d4:name5:hello6:lengthi5ee"[..],
+		In::Bencode,
+		&mut output,
+		Out::Json,
+		&Options {
+			transforms: vec![Transform::Stringify(Encoding::Utf8)],
+			..Options::default()
+		},
+	)
+	.unwrap();
+	assert_eq!(
+		String::from_utf8(output).unwrap(),
+		r#"{"name":"hello","length":5}"#
+	);
+}
+
+#[test]
+fn invalid_input() {
+	let error = transcode(
+		"{".as_bytes(),
+		In::Json,
+		vec![],
+		Out::Json,
+		&Options::default(),
+	)
+	.unwrap_err();
+	assert!(matches!(
+		error,
+		Error::Deserialize {
+			format: In::Json,
+			..
+		}
+	));
+	assert_eq!(error.exit_code(), 4);
+}
+
+#[test]
+fn unrepresentable_output() {
+	let error = transcode(
+		"[1, 2]".as_bytes(),
+		In::Json,
+		vec![],
+		Out::Urlencoded,
+		&Options::default(),
+	)
+	.unwrap_err();
+	assert!(matches!(
+		error,
+		Error::Serialize {
+			format: Out::Urlencoded,
+			..
+		}
+	));
+	assert_eq!(error.exit_code(), 5);
+}
+
+#[test]
+fn streaming_matches_object() {
+	let input = r#"{"text": "value", "list": [1, -2, 3.5, null], "nested": {"flag": false}}"#;
+	let convert = |strategy| {
+		let mut output = vec![];
+		transcode(
+			input.as_bytes(),
+			In::Json,
+			&mut output,
+			Out::Cbor,
+			&Options {
+				strategy,
+				..Options::default()
+			},
+		)
+		.unwrap();
+
+		// CBOR containers may be encoded with or without length, so compare as JSON instead.
+		let mut json = vec![];
+		transcode(
+			&output[..],
+			In::Cbor,
+			&mut json,
+			Out::Json,
+			&Options::default(),
+		)
+		.unwrap();
+		String::from_utf8(json).unwrap()
+	};
+	assert_eq!(convert(Strategy::Streaming), convert(Strategy::Object));
+}
+
+#[test]
+fn streaming_errors() {
+	let streaming = Options {
+		strategy: Strategy::Streaming,
+		..Options::default()
+	};
+
+	let error = transcode("[1, ".as_bytes(), In::Json, vec![], Out::Cbor, &streaming).unwrap_err();
+	assert_eq!(error.exit_code(), 4);
+
+	let error =
+		transcode(&b"d1:ai1ee"[..], In::Bencode, vec![], Out::Json, &streaming).unwrap_err();
+	assert_eq!(error.exit_code(), 5);
+
+	let error = transcode("".as_bytes(), In::Yaml, vec![], Out::Json, &streaming).unwrap_err();
+	assert!(matches!(error, Error::Options(_)));
+}
+
+#[test]
+fn taml_diagnostics() {
+	let reported = Arc::new(Mutex::new(vec![]));
+	let options = Options {
+		reporter: Some(Reporter(Arc::new({
+			let reported = Arc::clone(&reported);
+			move |diagnostics: &Diagnostics| {
+				let mut rendered = Buffer::no_color();
+				diagnostics.emit("input.taml", &mut rendered, true).unwrap();
+				reported
+					.lock()
+					.unwrap()
+					.push(String::from_utf8(rendered.into_inner()).unwrap());
+			}
+		}))),
+		..Options::default()
+	};
+
+	let error = transcode(
+		"# [a]\nb: 1\nc: \n".as_bytes(),
+		In::Taml,
+		vec![],
+		Out::Json,
+		&options,
+	)
+	.unwrap_err();
+	assert_eq!(error.exit_code(), 4);
+	assert!(error.to_string().contains("TAML-P0005"));
+
+	let reported = reported.lock().unwrap();
+	assert_eq!(reported.len(), 1);
+	assert!(reported[0].contains("input.taml:3:4"));
+}
+
+#[test]
+fn toml_datetimes() {
+	let input = "released = 1979-05-27T07:32:00Z\n";
+	let convert = |in_format, input: &str, out_format, toml_datetimes| {
+		let mut output = vec![];
+		transcode(
+			input.as_bytes(),
+			in_format,
+			&mut output,
+			out_format,
+			&Options {
+				toml_datetimes,
+				..Options::default()
+			},
+		)
+		.unwrap();
+		String::from_utf8(output).unwrap()
+	};
+
+	assert_eq!(
+		convert(In::Toml, input, Out::Json, TomlDatetimes::String),
+		r#"{"released":"1979-05-27T07:32:00Z"}"#
+	);
+	let tagged = convert(In::Toml, input, Out::Json, TomlDatetimes::Tagged);
+	assert_eq!(
+		tagged,
+		r#"{"released":{"$datetime":"1979-05-27T07:32:00Z"}}"#
+	);
+	assert_eq!(
+		convert(In::Json, &tagged, Out::Toml, TomlDatetimes::String),
+		input
+	);
+	assert_eq!(
+		convert(In::Toml, input, Out::Toml, TomlDatetimes::String),
+		input
+	);
+}
+
+#[test]
+fn toml_layout() {
+	let mut output = vec![];
+	transcode(
+		r#"{"table": {"list": [1, 2]}, "value": "after the table"}"#.as_bytes(),
+		In::Json,
+		&mut output,
+		Out::Toml,
+		&Options {
+			pretty: true,
+			..Options::default()
+		},
+	)
+	.unwrap();
+	assert_eq!(
+		String::from_utf8(output).unwrap(),
+		"value = 'after the table'\n\n[table]\nlist = [\n    1,\n    2,\n]\n"
+	);
+}
+
+#[test]
+fn toml_unrepresentable() {
+	let error = transcode(
+		"[1, 2]".as_bytes(),
+		In::Json,
+		vec![],
+		Out::Toml,
+		&Options::default(),
+	)
+	.unwrap_err();
+	assert_eq!(error.exit_code(), 5);
+	assert_eq!(
+		error.to_string(),
+		"Failed to serialize output as toml: The top level must be a table, not array"
+	);
+
+	let error = transcode(
+		r#"{"a": {"b": [1, null]}}"#.as_bytes(),
+		In::Json,
+		vec![],
+		Out::Toml,
+		&Options::default(),
+	)
+	.unwrap_err();
+	assert_eq!(
+		error.to_string(),
+		"Failed to serialize output as toml: Arrays can't contain absent values (at `a.b[1]`)"
+	);
+}
+
+#[test]
+fn msgpack_bytes() {
+	let mut output = vec![];
+	transcode(
+		&b"3:abc"[..],
+		In::Bencode,
+		&mut output,
+		Out::Msgpack,
+		&Options::default(),
+	)
+	.unwrap();
+	assert_eq!(output, b"\xc4\x03abc");
+}
+
+#[test]
+fn msgpack_ext() {
+	let mut json = vec![];
+	transcode(
+		&b"\xd4\x05\x2a"[..],
+		In::Msgpack,
+		&mut json,
+		Out::Json,
+		&Options::default(),
+	)
+	.unwrap();
+	assert_eq!(
+		String::from_utf8(json.clone()).unwrap(),
+		r#"{"$ext":{"type":5,"data":[42]}}"#
+	);
+
+	let mut output = vec![];
+	transcode(
+		&json[..],
+		In::Json,
+		&mut output,
+		Out::Msgpack,
+		&Options::default(),
+	)
+	.unwrap();
+	assert_eq!(output, b"\xd4\x05\x2a");
+}
+
+#[test]
+fn taml_output() {
+	let input = r#"{"name": "demo", "owner": {"id": 7}, "points": [{"x": 1, "pos": {"y": 2}}, {"x": 3, "pos": {"y": 4}}]}"#;
+	let convert = |pretty| {
+		let mut output = vec![];
+		transcode(
+			input.as_bytes(),
+			In::Json,
+			&mut output,
+			Out::Taml,
+			&Options {
+				pretty,
+				..Options::default()
+			},
+		)
+		.unwrap();
+		String::from_utf8(output).unwrap()
+	};
+
+	assert_eq!(
+		convert(false),
+		"name: \"demo\"\n\n# owner\nid: 7\n\n# [points]\nx: 1\n\n## pos\ny: 2\n\n# [points]\nx: 3\n\n## pos\ny: 4\n"
+	);
+	assert_eq!(
+		convert(true),
+		"name: \"demo\"\n\n# owner\nid: 7\n\n# [[points].{x, pos.{y}}]\n1, 2\n3, 4\n"
+	);
+}
+
+#[test]
+fn taml_round_trip() {
+	let input =
+		"unit: Foo\ntuple: Bar(1, \"x\")\n\n# structured:Baz\nq: 1.5\n\n# [list]:Qux\nr: ()\n";
+	let mut output = vec![];
+	transcode(
+		input.as_bytes(),
+		In::Taml,
+		&mut output,
+		Out::Taml,
+		&Options::default(),
+	)
+	.unwrap();
+	assert_eq!(String::from_utf8(output).unwrap(), input);
+}
+
+#[test]
+fn bincode_schema() {
+	let schema: Schema = r#"{
+		"root": "Header",
+		"types": {
+			"Header": {"struct": {"id": "u16", "name": "string", "items": {"seq": "Item"}}},
+			"Item": {"enum": {
+				"Empty": "unit",
+				"Pair": {"tuple": ["i8", {"option": "bool"}]},
+				"Named": {"struct": {"bytes": "bytes"}}
+			}}
+		}
+	}"#
+	.parse()
+	.unwrap();
+
+	let input = [
+		&b"\x07\x00"[..],
+		b"\x02\x00\x00\x00\x00\x00\x00\x00ab",
+		b"\x03\x00\x00\x00\x00\x00\x00\x00",
+		b"\x00\x00\x00\x00",
+		b"\x01\x00\x00\x00\xff\x01\x01",
+		b"\x02\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\x2a",
+	]
+	.concat();
+	let mut output = vec![];
+	transcode(
+		&input[..],
+		In::Bincode,
+		&mut output,
+		Out::Json,
+		&Options {
+			schema: Some(schema.clone()),
+			..Options::default()
+		},
+	)
+	.unwrap();
+	assert_eq!(
+		String::from_utf8(output).unwrap(),
+		r#"{"id":7,"name":"ab","items":["Empty",{"Pair":[-1,true]},{"Named":{"bytes":[42]}}]}"#
+	);
+
+	let error = transcode(
+		&[&input[..], b"\x00"].concat()[..],
+		In::Bincode,
+		vec![],
+		Out::Json,
+		&Options {
+			schema: Some(schema),
+			..Options::default()
+		},
+	)
+	.unwrap_err();
+	assert_eq!(error.exit_code(), 4);
+
+	let error = transcode(
+		&input[..],
+		In::Bincode,
+		vec![],
+		Out::Json,
+		&Options::default(),
+	)
+	.unwrap_err();
+	assert!(matches!(error, Error::Options(_)));
+
+	let error = r#"{"root": {"seq": "Missing"}}"#.parse::<Schema>().unwrap_err();
+	assert_eq!(
+		error.to_string(),
+		"Invalid schema: Unknown type `Missing` at `root`"
+	);
+
+	let error = |schema: &str| schema.parse::<Schema>().unwrap_err().to_string();
+	assert_eq!(
+		error(r#"{"root": "A", "types": {"A": "A"}}"#),
+		"Invalid schema: Type `A` contains itself without an option, sequence, map or enum in between: `A` -> `A`"
+	);
+	assert_eq!(
+		error(r#"{"root": "A", "types": {"A": {"struct": {"b": "B"}}, "B": {"tuple": ["u8", {"newtype": "A"}]}}}"#),
+		"Invalid schema: Type `A` contains itself without an option, sequence, map or enum in between: `A` -> `B` -> `A`"
+	);
+	r#"{"root": "List", "types": {"List": {"struct": {"next": {"option": "List"}}}}}"#
+		.parse::<Schema>()
+		.unwrap();
+
+	// Reads until the input runs out, rather than allocating for the length up front.
+	let error = transcode(
+		&b"\x01\x02"[..],
+		In::Bincode,
+		vec![],
+		Out::Json,
+		&Options {
+			schema: Some(
+				r#"{"root": {"array": ["u8", 18446744073709551615]}}"#
+					.parse()
+					.unwrap(),
+			),
+			..Options::default()
+		},
+	)
+	.unwrap_err();
+	assert_eq!(error.exit_code(), 4);
+}
+
+#[test]
+fn transforms() {
+	let convert = |input: &[u8], in_format, transforms| {
+		let mut output = vec![];
+		transcode(
+			input,
+			in_format,
+			&mut output,
+			Out::Json,
+			&Options {
+				transforms,
+				..Options::default()
+			},
+		)
+		.unwrap();
+		String::from_utf8(output).unwrap()
+	};
+
+	assert_eq!(
+		convert(
+			b"flag: True\nother: FALSE\nkept: Yes\n",
+			In::Taml,
+			vec![Transform::EnumBools]
+		),
+		r#"{"flag":true,"other":false,"kept":"Yes"}"#
+	);
+	assert_eq!(
+		convert(
+			b"d4:listl3:abci1eee",
+			In::Bencode,
+			vec![Transform::Stringify(Encoding::Utf8)]
+		),
+		r#"{"list":["abc",1]}"#
+	);
+}
+
+#[test]
+fn stringify_encodings() {
+	let convert = |input: &[u8], encodings: &[Encoding]| {
+		let mut output = vec![];
+		transcode(
+			input,
+			In::Bencode,
+			&mut output,
+			Out::Json,
+			&Options {
+				transforms: encodings
+					.iter()
+					.copied()
+					.map(Transform::Stringify)
+					.collect(),
+				..Options::default()
+			},
+		)
+		.unwrap();
+		String::from_utf8(output).unwrap()
+	};
+
+	let torrent = b"d4:name3:abc6:pieces3:\xff\x00\x80e";
+	assert_eq!(
+		convert(torrent, &[Encoding::Utf8, Encoding::Hex]),
+		r#"{"name":"abc","pieces":"ff0080"}"#
+	);
+	assert_eq!(
+		convert(torrent, &[Encoding::Base64]),
+		r#"{"bmFtZQ==":"YWJj","cGllY2Vz":"/wCA"}"#
+	);
+	assert_eq!(
+		convert(b"4:caf\xe9", &[Encoding::Utf8, Encoding::Latin1]),
+		r#""café""#
+	);
+	assert_eq!(convert(b"1:\x80", &[Encoding::Cp1252]), r#""€""#);
+	assert_eq!(convert(b"1:\x80", &[Encoding::Latin1]), "\"\u{80}\"");
+	assert_eq!(convert(b"4:h\x00i\x00", &[Encoding::Utf16Le]), r#""hi""#);
+	assert_eq!(
+		convert(
+			b"6:\xfe\xff\x00h\x00i",
+			&[Encoding::Utf16Le, Encoding::Utf16Be]
+		),
+		"\"\u{feff}hi\""
+	);
+	assert_eq!(
+		convert(b"6:\xfe\xff\x00h\x00i", &[Encoding::Utf16Le]),
+		"[254,255,0,104,0,105]"
+	);
+	assert_eq!(convert(b"3:h\x00i", &[Encoding::Utf16Be]), "[104,0,105]");
+}
+
+#[test]
+fn bytes_from() {
+	let torrent = &b"d4:infod6:lengthi42e6:pieces3:\xff\x00\x80ee"[..];
+	let mut json = vec![];
+	transcode(
+		torrent,
+		In::Bencode,
+		&mut json,
+		Out::Json,
+		&Options {
+			transforms: vec![
+				Transform::Stringify(Encoding::Utf8),
+				Transform::Stringify(Encoding::Hex),
+			],
+			..Options::default()
+		},
+	)
+	.unwrap();
+	assert_eq!(
+		String::from_utf8(json.clone()).unwrap(),
+		r#"{"info":{"length":42,"pieces":"ff0080"}}"#
+	);
+
+	let bytes_from = |json: &[u8], out_format, path: Option<&str>| {
+		let mut output = vec![];
+		transcode(
+			json,
+			In::Json,
+			&mut output,
+			out_format,
+			&Options {
+				transforms: vec![Transform::BytesFrom {
+					encoding: Encoding::Hex,
+					path: path.map(|path| path.parse().unwrap()),
+				}],
+				..Options::default()
+			},
+		)
+		.map(|()| output)
+	};
+	assert_eq!(
+		bytes_from(&json, Out::Bencode, Some("info.pieces")).unwrap(),
+		torrent
+	);
+	assert_eq!(
+		bytes_from(br#"["hex:ff", "ff", "hexa"]"#, Out::Json, None).unwrap(),
+		br#"[[255],"ff","hexa"]"#
+	);
+
+	let error = bytes_from(br#"{"list": ["ff", "xx"]}"#, Out::Json, Some("list[*]")).unwrap_err();
+	assert_eq!(error.exit_code(), 6);
+	assert_eq!(
+		error.to_string(),
+		"Failed to apply bytes-from: Invalid hex: Invalid character 'x' at position 0 (at `list[1]`)"
+	);
+
+	// UTF-16 byte order marks survive the round trip.
+	let bencode = &b"d4:name4:\xff\xfeh\x00e"[..];
+	let mut json = vec![];
+	transcode(
+		bencode,
+		In::Bencode,
+		&mut json,
+		Out::Json,
+		&Options {
+			transforms: vec![
+				Transform::Stringify(Encoding::Utf8),
+				Transform::Stringify(Encoding::Utf16Le),
+			],
+			..Options::default()
+		},
+	)
+	.unwrap();
+	assert_eq!(json, "{\"name\":\"\u{feff}h\"}".as_bytes());
+	let mut output = vec![];
+	transcode(
+		&json[..],
+		In::Json,
+		&mut output,
+		Out::Bencode,
+		&Options {
+			transforms: vec![Transform::BytesFrom {
+				encoding: Encoding::Utf16Le,
+				path: Some("name".parse().unwrap()),
+			}],
+			..Options::default()
+		},
+	)
+	.unwrap();
+	assert_eq!(output, bencode);
+}
+
+#[test]
+fn key_paths() {
+	for path in &[
+		"",
+		"info.pieces",
+		"files[*].path",
+		"*[0]",
+		r#"info."piece length""#,
+		"/info/piece length",
+		"/a~1b/~0/0",
+	] {
+		assert_eq!(&path.parse::<KeyPath>().unwrap().to_string(), path);
+	}
+	assert_eq!(
+		"a.".parse::<KeyPath>().unwrap_err().to_string(),
+		"Invalid path: `a.` ends with `.`"
+	);
+	assert!("a[b]".parse::<KeyPath>().is_err());
+	assert_eq!(
+		"a[0".parse::<KeyPath>().unwrap_err().to_string(),
+		"Invalid path: Unterminated `[` in `a[0`"
+	);
+	assert!("a[".parse::<KeyPath>().is_err());
+}
+
+#[test]
+fn annotated() {
+	let annotated = Options {
+		annotated: true,
+		..Options::default()
+	};
+
+	let cbor = &b"\xa2\x61a\x43\x01\x02\x03\x61b\x82\x18\x2a\xf9\x3c\x00"[..];
+	let mut yaml = vec![];
+	transcode(cbor, In::Cbor, &mut yaml, Out::Yaml, &annotated).unwrap();
+	assert_eq!(
+		String::from_utf8(yaml.clone()).unwrap(),
+		"---\nmap:\n  - - string: a\n    - bytes: \"010203\"\n  - - string: b\n    - seq:\n        - u128: \"42\"\n        - f64: 1.0\n"
+	);
+	let mut output = vec![];
+	transcode(&yaml[..], In::Yaml, &mut output, Out::Cbor, &annotated).unwrap();
+	assert_eq!(output, cbor);
+
+	let mut json = vec![];
+	transcode(
+		"unit: Foo\ntuple: Bar(1, \"x\")\n".as_bytes(),
+		In::Taml,
+		&mut json,
+		Out::Json,
+		&annotated,
+	)
+	.unwrap();
+	assert_eq!(
+		String::from_utf8(json).unwrap(),
+		concat!(
+			r#"{"map":[[{"string":"unit"},{"unit_variant":{"name":"UNKNOWN_ENUM","variant":{"string":"Foo"}}}],"#,
+			r#"[{"string":"tuple"},{"tuple_variant":{"name":"UNKNOWN_ENUM","variant":{"string":"Bar"},"fields":{"seq":[{"u8":1},{"string":"x"}]}}}]]}"#,
+		)
+	);
+
+	let error = transcode(
+		r#"{"seq": [{"u8": 256}]}"#.as_bytes(),
+		In::Json,
+		vec![],
+		Out::Cbor,
+		&annotated,
+	)
+	.unwrap_err();
+	assert_eq!(
+		error.to_string(),
+		"Failed to deserialize input as json: Integer out of range (at `seq[0].u8`)"
+	);
+
+	let error = transcode(&b"le"[..], In::Bencode, vec![], Out::Cbor, &annotated).unwrap_err();
+	assert!(matches!(error, Error::Options(_)));
+}
+
+#[test]
+fn check_round_trip() {
+	let check = Options {
+		check_round_trip: true,
+		..Options::default()
+	};
+
+	let cbor = &b"\xa2\x61a\x43\x01\x02\x03\x61b\x82\x18\x2a\xf9\x3c\x00"[..];
+	let error = transcode(cbor, In::Cbor, vec![], Out::Json, &check).unwrap_err();
+	assert_eq!(error.exit_code(), 7);
+	assert_eq!(
+		error.to_string(),
+		"Converting to json and back changes the document:\n    bytes became a sequence (at `a`)"
+	);
+
+	let mut output = vec![];
+	transcode(
+		cbor,
+		In::Cbor,
+		&mut output,
+		Out::Json,
+		&Options {
+			annotated: true,
+			..check.clone()
+		},
+	)
+	.unwrap();
+	assert!(!output.is_empty());
+
+	let error = transcode(
+		"unit: Foo\nlist: (1, 2)\n".as_bytes(),
+		In::Taml,
+		vec![],
+		Out::Yaml,
+		&check,
+	)
+	.unwrap_err();
+	match error {
+		Error::RoundTrip { differences, .. } => {
+			assert_eq!(differences.len(), 1);
+			assert_eq!(differences[0].path(), "unit");
+			assert_eq!(
+				differences[0].description(),
+				"an enum variant became a string"
+			);
+		}
+		error => panic!("{}", error),
+	}
+
+	let mut output = vec![];
+	transcode(
+		r#"{"text": "value", "list": [1, -2, 3.5, null]}"#.as_bytes(),
+		In::Json,
+		&mut output,
+		Out::Cbor,
+		&check,
+	)
+	.unwrap();
+	assert!(!output.is_empty());
+}
+
+#[test]
+fn select() {
+	let select = |path: &str, input: &str| {
+		let mut output = vec![];
+		transcode(
+			input.as_bytes(),
+			In::Json,
+			&mut output,
+			Out::Json,
+			&Options {
+				transforms: vec![Transform::Select(path.parse().unwrap())],
+				..Options::default()
+			},
+		)
+		.map(|()| String::from_utf8(output).unwrap())
+		.map_err(|error| error.to_string())
+	};
+
+	let input = r#"{"info": {"name": "a.txt", "files": [{"path": "a/b"}], "a/b": 1}}"#;
+	assert_eq!(select("/info/name", input).unwrap(), r#""a.txt""#);
+	assert_eq!(select("info.files[0].path", input).unwrap(), r#""a/b""#);
+	assert_eq!(select("/info/files/0", input).unwrap(), r#"{"path":"a/b"}"#);
+	assert_eq!(select("/info/a~1b", input).unwrap(), "1");
+	assert_eq!(
+		select("info.nme", input).unwrap_err(),
+		"Failed to apply select: No key `nme` in a map, available keys are: `name`, `files`, `a/b` (at `info`)"
+	);
+	assert_eq!(
+		select("/info/files/1", input).unwrap_err(),
+		"Failed to apply select: No index 1 in a sequence of length 1 (at `info.files`)"
+	);
+	assert_eq!(
+		select("info.name.x", input).unwrap_err(),
+		"Failed to apply select: No key `x` in a string (at `info.name`)"
+	);
+	assert_eq!(
+		select("info.*", input).unwrap_err(),
+		"Failed to apply select: Can't select with wildcards (at `info`)"
+	);
+
+	let mut output = vec![];
+	transcode(
+		"tuple: Bar(1, \"x\")\n".as_bytes(),
+		In::Taml,
+		&mut output,
+		Out::Json,
+		&Options {
+			transforms: vec![Transform::Select("tuple.Bar[1]".parse().unwrap())],
+			..Options::default()
+		},
+	)
+	.unwrap();
+	assert_eq!(output, br#""x""#);
+}
+
+#[test]
+fn filter() {
+	let filter = |filter: &str, input: &str| {
+		let mut output = vec![];
+		transcode(
+			input.as_bytes(),
+			In::Json,
+			&mut output,
+			Out::Json,
+			&Options {
+				transforms: vec![Transform::Filter(filter.parse().unwrap())],
+				..Options::default()
+			},
+		)
+		.map(|()| String::from_utf8(output).unwrap())
+		.map_err(|error| error.to_string())
+	};
+
+	let input = r#"{"info": {"name": "a", "length": 3, "files": [{"path": "x", "length": 1}, {"path": "y", "length": 5}]}}"#;
+	assert_eq!(
+		filter(".info | {name, length}", input).unwrap(),
+		r#"{"name":"a","length":3}"#
+	);
+	assert_eq!(
+		filter(".info.files | map(select(.length > 2) | .path)", input).unwrap(),
+		r#"["y"]"#
+	);
+	assert_eq!(
+		filter("[.info | keys, length]", input).unwrap(),
+		r#"[["files","length","name"],3]"#
+	);
+	assert_eq!(
+		filter(
+			"[.info.files[-1].path, .missing.x // \"none\", 7 / 2]",
+			input
+		)
+		.unwrap(),
+		r#"["y","none",3.5]"#
+	);
+
+	// Filter literals are at most `i64`, so `i128::MIN` has to come from the input. It can't be negated.
+	let mut output = vec![];
+	transcode(
+		&i128::MIN.to_le_bytes()[..],
+		In::Bincode,
+		&mut output,
+		Out::Json,
+		&Options {
+			schema: Some(r#"{"root": "i128"}"#.parse().unwrap()),
+			transforms: vec![Transform::Filter(
+				"[., . / -1, length, . / 2]".parse().unwrap(),
+			)],
+			..Options::default()
+		},
+	)
+	.unwrap();
+	assert_eq!(
+		String::from_utf8(output).unwrap(),
+		"[-170141183460469231731687303715884105728,1.7014118346046923e38,1.7014118346046923e38,-85070591730234615865843651857942052864]"
+	);
+	assert_eq!(
+		filter(".info.files[]", input).unwrap_err(),
+		"Failed to apply filter: The filter produced 2 values, but only one document can be written. Wrap it in `[…]` to collect them."
+	);
+	assert_eq!(
+		filter(".info.name[0]", input).unwrap_err(),
+		"Failed to apply filter: Can't index a string with an integer"
+	);
+
+	assert_eq!(
+		".[".parse::<Filter>().unwrap_err().to_string(),
+		"Invalid filter: Unexpected end of `.[`"
+	);
+	assert_eq!(
+		"first(.)".parse::<Filter>().unwrap_err().to_string(),
+		"Invalid filter: Unknown function `first/1` in `first(.)`"
+	);
+}
+
+#[test]
+fn patch() {
+	let patch = |patch: &str, input: &[u8], in_format: In, out_format: Out| {
+		let mut output = vec![];
+		transcode(
+			input,
+			in_format,
+			&mut output,
+			out_format,
+			&Options {
+				transforms: vec![Transform::Patch(patch.parse().unwrap())],
+				..Options::default()
+			},
+		)
+		.map(|()| output)
+		.map_err(|error| error.to_string())
+	};
+	let yaml = b"name: app\nserver:\n  port: 8080\n  hosts: [a, b]\n";
+
+	assert_eq!(
+		patch(
+			r#"[
+				{"op": "replace", "path": "/server/port", "value": 9090},
+				{"op": "add", "path": "/server/hosts/-", "value": "c"},
+				{"op": "move", "from": "/name", "path": "/title"},
+				{"op": "copy", "from": "/server/hosts/0", "path": "/first"},
+				{"op": "remove", "path": "/server/hosts/1"},
+				{"op": "test", "path": "/title", "value": "app"}
+			]"#,
+			yaml,
+			In::Yaml,
+			Out::Json
+		)
+		.unwrap(),
+		br#"{"server":{"port":9090,"hosts":["a","c"]},"title":"app","first":"a"}"#
+	);
+	assert_eq!(
+		patch(
+			r#"{"server": {"port": null, "tls": true}, "name": "x"}"#,
+			yaml,
+			In::Yaml,
+			Out::Json
+		)
+		.unwrap(),
+		br#"{"name":"x","server":{"hosts":["a","b"],"tls":true}}"#
+	);
+	assert_eq!(
+		patch(
+			r#"{"info": {"name": "b"}}"#,
+			b"d8:announce3:url4:infod4:name1:aee",
+			In::Bencode,
+			Out::Bencode
+		)
+		.unwrap(),
+		b"d8:announce3:url4:infod4:name1:bee"
+	);
+
+	assert_eq!(
+		patch(
+			r#"[{"op": "add", "path": "/server/tls", "value": true}, {"op": "remove", "path": "/server/hosts/2"}]"#,
+			yaml,
+			In::Yaml,
+			Out::Json
+		)
+		.unwrap_err(),
+		"Failed to apply patch: Operation 2 (`remove` at `/server/hosts/2`) failed: No index 2 in a sequence of length 2 (at `server.hosts`)"
+	);
+	assert_eq!(
+		patch(
+			r#"[{"op": "test", "path": "/name", "value": "other"}]"#,
+			yaml,
+			In::Yaml,
+			Out::Json
+		)
+		.unwrap_err(),
+		r#"Failed to apply patch: Operation 1 (`test` at `/name`) failed: Expected "other" but found "app""#
+	);
+	assert_eq!(
+		r#"[{"op": "move", "path": "/a"}]"#.parse::<Patch>().unwrap_err().to_string(),
+		"Invalid patch: Operation 1 has no `from`"
+	);
+}
+
+#[test]
+fn patch_variants() {
+	let schema: Schema = r#"{
+		"root": {"struct": {"item": {"enum": {"Empty": "unit", "Named": {"struct": {"x": "u8"}}}}}}
+	}"#
+	.parse()
+	.unwrap();
+	let patch = |patch: &str| {
+		let mut output = vec![];
+		transcode(
+			&b"\x01\x00\x00\x00\x07"[..],
+			In::Bincode,
+			&mut output,
+			Out::Json,
+			&Options {
+				schema: Some(schema.clone()),
+				transforms: vec![Transform::Patch(patch.parse().unwrap())],
+				..Options::default()
+			},
+		)
+		.map(|()| String::from_utf8(output).unwrap())
+		.map_err(|error| error.to_string())
+	};
+
+	assert_eq!(
+		patch(r#"[{"op": "replace", "path": "/item/Named/x", "value": 8}]"#).unwrap(),
+		r#"{"item":{"Named":{"x":8}}}"#
+	);
+	assert_eq!(
+		patch(r#"[{"op": "remove", "path": "/item/Named"}]"#).unwrap_err(),
+		"Failed to apply patch: Operation 1 (`remove` at `/item/Named`) failed: Can't remove enum variant `Named`"
+	);
+	assert_eq!(
+		patch(r#"[{"op": "move", "from": "/item/Named", "path": "/named"}]"#).unwrap_err(),
+		"Failed to apply patch: Operation 1 (`move` at `/named`) failed: Can't remove enum variant `Named`"
+	);
+
+	assert_eq!(
+		patch(r#"{"item": {"Named": {"x": 9}}}"#).unwrap(),
+		r#"{"item":{"Named":{"x":9}}}"#
+	);
+	assert_eq!(
+		patch(r#"{"item": {"Named": null}}"#).unwrap_err(),
+		"Failed to apply patch: Can't remove enum variant `Named`"
+	);
+}
+
+#[test]
+fn multi_document() {
+	let transcode = |input: &[u8], in_format: In, out_format: Out, slurp: bool| {
+		let mut output = vec![];
+		transcode(
+			input,
+			in_format,
+			&mut output,
+			out_format,
+			&Options {
+				transforms: vec![Transform::Select("name".parse().unwrap())],
+				documents: if slurp {
+					Documents::Slurp
+				} else {
+					Documents::Each
+				},
+				..Options::default()
+			},
+		)
+		.map(|()| output)
+		.map_err(|error| error.to_string())
+	};
+	let yaml = b"name: a\n---\nname: b\n";
+
+	assert_eq!(
+		transcode(yaml, In::Yaml, Out::Json, false).unwrap(),
+		b"\"a\"\n\"b\"\n"
+	);
+	assert_eq!(
+		transcode(yaml, In::Yaml, Out::Json, true).unwrap(),
+		br#"["a","b"]"#
+	);
+	assert_eq!(
+		transcode(
+			b"{\"name\": 1}\n{\"name\": 2} {\"name\": 3}\n",
+			In::Json,
+			Out::Yaml,
+			false
+		)
+		.unwrap(),
+		b"---\n1\n---\n2\n---\n3\n"
+	);
+
+	// RFC 8742 CBOR sequence
+	let cbor = transcode(yaml, In::Yaml, Out::Cbor, false).unwrap();
+	assert_eq!(cbor, b"\x61a\x61b");
+	assert_eq!(
+		transcode(
+			b"\xa1\x64name\x61a\xa1\x64name\x61b",
+			In::Cbor,
+			Out::Json,
+			true
+		)
+		.unwrap(),
+		br#"["a","b"]"#
+	);
+
+	assert_eq!(
+		transcode(yaml, In::Yaml, Out::Toml, false).unwrap_err(),
+		"Invalid options: Can't write 2 documents as toml, which holds only one. Slurp them into a sequence instead."
+	);
+}